@@ -0,0 +1,1114 @@
+use std::borrow::Cow;
+use std::cmp::max;
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthChar;
+
+/// What to do with the control chars in the text, like the ANSI escape sequences of colors.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ControlChars {
+    /// Shown as `^X`, like less does: `ESC[1m` is `^[[1m`.
+    Caret,
+    /// `-R`: the color sequences (SGR, `ESC[...m`) go to the terminal, the others are shown
+    /// as `^X`.
+    Raw,
+    /// `--strip-ansi`: the escape sequences are removed, colors or not.
+    Strip,
+}
+
+/// How the control chars shown as text are written, in reverse video so they stand out.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ControlNotation {
+    /// `^G`, like less. DEL is `^?`.
+    Caret,
+    /// `\x07`.
+    Hex,
+}
+
+/// How the long lines are wrapped.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Wrapping {
+    /// How wide the rows are.
+    pub(crate) cols: usize,
+    /// Break the rows before the word that doesn't fit, instead of in the middle of it.
+    pub(crate) words: bool,
+    /// How wide the marker the rows after the first start with is: they're narrower.
+    pub(crate) marker_width: usize,
+}
+
+impl Wrapping {
+    /// How wide the row-th row of a line is, at least a column.
+    pub(crate) fn row_cols(&self, row: usize) -> usize {
+        if row == 0 {
+            max(self.cols, 1)
+        } else {
+            max(self.cols.saturating_sub(self.marker_width), 1)
+        }
+    }
+}
+
+const ESC: char = '\x1b';
+/// Back to the default colors.
+const SGR_RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const UNDERLINE: &str = "\x1b[4m";
+/// Around a control char shown as text: reverse video, leaving the colors as they are.
+const CONTROL_ON: &str = "\x1b[7m";
+const CONTROL_OFF: &str = "\x1b[27m";
+/// Around the glyphs shown for whitespace, and the marker of wrapped rows: dim, so they don't
+/// look like the text.
+const DIM_ON: &str = "\x1b[2m";
+const DIM_OFF: &str = "\x1b[22m";
+const TAB_GLYPH: char = '→';
+const TRAILING_SPACE_GLYPH: char = '·';
+const LINE_END_GLYPH: char = '¶';
+
+/// The part of line shown from column col_offset, at most cols wide, and if it has any text.
+/// Color sequences take no columns, and the ones before col_offset are kept too, so the
+/// colors are right after a horizontal scroll. Tabs go to the next multiple of tab_width.
+/// The other control chars are shown in notation, never written to the terminal: they could
+/// ring the bell, move the cursor or change the title. With whitespace, tabs, trailing spaces
+/// and the end of the line, if it ends with \n, are shown by dim glyphs, and a \r before it
+/// as a control char.
+pub(crate) fn render_line(
+    line: &str,
+    col_offset: usize,
+    cols: usize,
+    control_chars: ControlChars,
+    tab_width: usize,
+    notation: ControlNotation,
+    whitespace: bool,
+) -> (Cow<'_, str>, bool) {
+    let (line, line_end) = shown_line(line, whitespace);
+    // Most lines have no control chars: the part shown is a slice.
+    if !whitespace && !has_control_chars(line) {
+        return slice_columns(line, col_offset, cols);
+    }
+    let chars: Vec<char> = line.chars().collect();
+    let trailing = Some(trailing_spaces_start(&chars, control_chars)).filter(|_| whitespace);
+    let mut rendered = String::new();
+    let mut has_text = false;
+    let mut has_colors = false;
+    let mut col = 0;
+    let mut i = 0;
+    while i < chars.len() && col < col_offset + cols {
+        if let Some(len) = hidden_escape_len(&chars[i..], control_chars) {
+            if control_chars == ControlChars::Raw {
+                rendered.extend(&chars[i..i + len]);
+                has_colors = true;
+            }
+            i += len;
+            continue;
+        }
+        let (text, end) = next_text(&chars, i, col, control_chars, tab_width, notation, trailing);
+        let (shown, text_shown) = slice_columns(
+            &text,
+            col_offset.saturating_sub(col),
+            (col_offset + cols).saturating_sub(max(col, col_offset)),
+        );
+        if is_control(chars[i]) && text_shown {
+            rendered.push_str(CONTROL_ON);
+            rendered.push_str(&shown);
+            rendered.push_str(CONTROL_OFF);
+        } else if is_whitespace_glyph(&chars, i, trailing) && text_shown {
+            rendered.push_str(DIM_ON);
+            rendered.push_str(&shown);
+            rendered.push_str(DIM_OFF);
+        } else {
+            rendered.push_str(&shown);
+        }
+        has_text = has_text || text_shown;
+        col += text_width(&text);
+        i = end;
+    }
+    if line_end && i == chars.len() && col >= col_offset && col < col_offset + cols {
+        rendered.push_str(DIM_ON);
+        rendered.push(LINE_END_GLYPH);
+        rendered.push_str(DIM_OFF);
+        has_text = true;
+    }
+    // The colors of the line must not go on in the next one.
+    if has_colors {
+        rendered.push_str(SGR_RESET);
+    }
+    (Cow::Owned(rendered), has_text)
+}
+
+/// The columns of line from col_offset, at most cols. Each grapheme cluster, like an accented
+/// letter or a flag, is shown whole or not at all. A wide one cut in half at the start is
+/// shown as a space, one at the end is left out.
+fn slice_columns(line: &str, col_offset: usize, cols: usize) -> (Cow<'_, str>, bool) {
+    let mut col = 0;
+    // Where the part shown starts, and at which column.
+    let mut start = None;
+    let mut end = line.len();
+    for (i, cluster) in line.grapheme_indices(true) {
+        let width = cluster_width(cluster);
+        if start.is_none() && col >= col_offset {
+            start = Some((i, col));
+        }
+        if col + width > col_offset + cols {
+            end = i;
+            break;
+        }
+        col += width;
+    }
+    match start {
+        Some((start, col)) if start < end => {
+            if col > col_offset {
+                (Cow::Owned(format!(" {}", &line[start..end])), true)
+            } else {
+                (Cow::Borrowed(&line[start..end]), true)
+            }
+        }
+        _ => (Cow::Borrowed(""), false),
+    }
+}
+
+/// The text starting at start, at column col, as it's shown, and where it ends: a tab, up to
+/// the next tab stop, a control char in notation, or else the chars up to the next one of
+/// them or escape sequence not shown. The whitespace is shown if trailing is where the
+/// trailing spaces start.
+fn next_text(
+    chars: &[char],
+    start: usize,
+    col: usize,
+    control_chars: ControlChars,
+    tab_width: usize,
+    notation: ControlNotation,
+    trailing: Option<usize>,
+) -> (String, usize) {
+    match chars[start] {
+        '\t' if trailing.is_some() => {
+            let spaces = " ".repeat(tab_width - col % tab_width - 1);
+            return (format!("{}{}", TAB_GLYPH, spaces), start + 1);
+        }
+        '\t' => return (" ".repeat(tab_width - col % tab_width), start + 1),
+        c if is_control(c) => return (in_notation(c, notation), start + 1),
+        ' ' if trailing.is_some_and(|trailing| start >= trailing) => {
+            return (TRAILING_SPACE_GLYPH.to_string(), start + 1)
+        }
+        _ => (),
+    }
+    let end = (start + 1..chars.len())
+        .find(|i| {
+            chars[*i] == '\t'
+                || Some(*i) == trailing
+                || is_control(chars[*i])
+                || hidden_escape_len(&chars[*i..], control_chars).is_some()
+        })
+        .unwrap_or(chars.len());
+    (chars[start..end].iter().collect(), end)
+}
+
+/// How many columns a grapheme cluster takes on the terminal, as many as its first char: 2
+/// for the wide ones like CJK and emoji, even joined with others. Flags too.
+fn cluster_width(cluster: &str) -> usize {
+    let mut chars = cluster.chars();
+    let first = chars.next();
+    match first {
+        // Two regional indicators, like 🇮🇹.
+        Some('\u{1f1e6}'..='\u{1f1ff}') if chars.next().is_some() => 2,
+        Some(first) => first.width().unwrap_or(0),
+        None => 0,
+    }
+}
+
+/// How many columns text, with no control chars, takes.
+pub(crate) fn text_width(text: &str) -> usize {
+    text.graphemes(true).map(cluster_width).sum()
+}
+
+/// How many columns line takes.
+pub(crate) fn line_width(
+    line: &str,
+    control_chars: ControlChars,
+    tab_width: usize,
+    notation: ControlNotation,
+    whitespace: bool,
+) -> usize {
+    let (line, line_end) = shown_line(line, whitespace);
+    let chars: Vec<char> = line.chars().collect();
+    let mut width = 0;
+    let mut i = 0;
+    while i < chars.len() {
+        match hidden_escape_len(&chars[i..], control_chars) {
+            Some(len) => i += len,
+            None => {
+                let (text, end) =
+                    next_text(&chars, i, width, control_chars, tab_width, notation, None);
+                width += text_width(&text);
+                i = end;
+            }
+        }
+    }
+    // The ¶ of the line end.
+    width + usize::from(line_end)
+}
+
+/// The columns where the rows line is wrapped on start, the first one 0. A grapheme cluster
+/// that doesn't fit at the end of a row, like a wide char, starts the next one, or its word
+/// does if wrapping words.
+pub(crate) fn wrap_columns(
+    line: &str,
+    control_chars: ControlChars,
+    tab_width: usize,
+    notation: ControlNotation,
+    whitespace: bool,
+    wrapping: Wrapping,
+) -> Vec<usize> {
+    let (line, line_end) = shown_line(line, whitespace);
+    let chars: Vec<char> = line.chars().collect();
+    let trailing = Some(trailing_spaces_start(&chars, control_chars)).filter(|_| whitespace);
+    let mut rows = WrappedRows {
+        wrapping,
+        starts: vec![0],
+        col: 0,
+        word_start: 0,
+    };
+    let mut i = 0;
+    while i < chars.len() {
+        if let Some(len) = hidden_escape_len(&chars[i..], control_chars) {
+            i += len;
+            continue;
+        }
+        let (text, end) = next_text(
+            &chars,
+            i,
+            rows.col,
+            control_chars,
+            tab_width,
+            notation,
+            trailing,
+        );
+        for cluster in text.graphemes(true) {
+            rows.fit(cluster == " ", cluster_width(cluster));
+        }
+        i = end;
+    }
+    // The ¶ of the line end.
+    if line_end {
+        rows.fit(false, 1);
+    }
+    rows.starts
+}
+
+/// The rows a line is wrapped on, as its grapheme clusters are put in them.
+struct WrappedRows {
+    wrapping: Wrapping,
+    /// The column each row starts at.
+    starts: Vec<usize>,
+    /// The column of the next cluster.
+    col: usize,
+    /// The column the last word starts at, after the last space.
+    word_start: usize,
+}
+
+impl WrappedRows {
+    /// Puts a cluster width wide, a space if blank, at the end of the last row, or if it
+    /// doesn't fit there on a new row.
+    fn fit(&mut self, blank: bool, width: usize) {
+        loop {
+            let row = self.starts.len() - 1;
+            let row_start = self.starts[row];
+            if self.col + width <= row_start + self.wrapping.row_cols(row) || self.col <= row_start
+            {
+                break;
+            }
+            // Rather than starting the next row, the spaces past the end of one aren't shown.
+            if blank && self.wrapping.words {
+                break;
+            }
+            let start = if self.wrapping.words && self.word_start > row_start {
+                self.word_start
+            } else {
+                self.col
+            };
+            self.starts.push(start);
+        }
+        self.col += width;
+        if blank {
+            self.word_start = self.col;
+        }
+    }
+}
+
+/// text, dim.
+pub(crate) fn dimmed(text: &str) -> String {
+    format!("{}{}{}", DIM_ON, text, DIM_OFF)
+}
+
+/// Turns the overstrikes made by nroff for man pages into styles: `c BACKSPACE c` is a bold c,
+/// `_ BACKSPACE c` an underlined c. None if line has none.
+pub(crate) fn overstrike(line: &str) -> Option<String> {
+    if !line.contains('\x08') {
+        return None;
+    }
+    let chars: Vec<char> = line.chars().collect();
+    let mut styled = String::new();
+    let mut current = "";
+    let mut i = 0;
+    while i < chars.len() {
+        let (style, c) = match chars.get(i + 1..i + 3) {
+            Some(&['\x08', c]) => {
+                i += 3;
+                match chars[i - 3] {
+                    first if first == c => (BOLD, c),
+                    '_' => (UNDERLINE, c),
+                    // Like a + over an o for a bullet: show the last one.
+                    _ => ("", c),
+                }
+            }
+            _ => {
+                i += 1;
+                ("", chars[i - 1])
+            }
+        };
+        if style != current {
+            styled.push_str(if style.is_empty() { SGR_RESET } else { style });
+            current = style;
+        }
+        styled.push(c);
+    }
+    if !current.is_empty() {
+        styled.push_str(SGR_RESET);
+    }
+    Some(styled)
+}
+
+/// The control notation called name: `caret` or `hex`.
+pub fn parse_control_notation(name: &str) -> Result<ControlNotation, String> {
+    match name {
+        "caret" => Ok(ControlNotation::Caret),
+        "hex" => Ok(ControlNotation::Hex),
+        _ => Err(format!("{:?} is not caret or hex", name)),
+    }
+}
+
+/// The color sequence for a style name from the config file, like `red`, `bold yellow` or
+/// `bright-blue`. None if a word is not known.
+pub(crate) fn parse_style(style: &str) -> Option<String> {
+    let mut codes = Vec::new();
+    for word in style.split_whitespace() {
+        let (bright, color) = match word.strip_prefix("bright-") {
+            Some(color) => (true, color),
+            None => (false, word),
+        };
+        let code = match color {
+            "bold" if !bright => 1,
+            "dim" if !bright => 2,
+            "italic" if !bright => 3,
+            "underline" if !bright => 4,
+            "reverse" if !bright => 7,
+            "black" => 30,
+            "red" => 31,
+            "green" => 32,
+            "yellow" => 33,
+            "blue" => 34,
+            "magenta" => 35,
+            "cyan" => 36,
+            "white" => 37,
+            _ => return None,
+        };
+        // The bright colors are 90-97.
+        codes.push(if bright { code + 60 } else { code }.to_string());
+    }
+    if codes.is_empty() {
+        return None;
+    }
+    Some(format!("\x1b[{}m", codes.join(";")))
+}
+
+/// Files with Windows line endings end their lines with \r\n: the \r is not shown.
+fn without_cr(line: &str) -> &str {
+    line.strip_suffix('\r').unwrap_or(line)
+}
+
+/// If the char at i is shown by a glyph: a tab, or a trailing space.
+fn is_whitespace_glyph(chars: &[char], i: usize, trailing: Option<usize>) -> bool {
+    trailing.is_some_and(|trailing| chars[i] == '\t' || (chars[i] == ' ' && i >= trailing))
+}
+
+/// The part of line shown as text, and if its end is shown: with whitespace, the \n it ends
+/// with, if any, is shown and the \r before it too. Otherwise neither of them is.
+fn shown_line(line: &str, whitespace: bool) -> (&str, bool) {
+    match line.strip_suffix('\n') {
+        Some(line) if whitespace => (line, true),
+        Some(line) => (without_cr(line), false),
+        None if whitespace => (line, false),
+        None => (without_cr(line), false),
+    }
+}
+
+/// Where the spaces at the end of chars start, past the escape sequences not shown. The end
+/// of chars if there are none. A \r ending the line doesn't count.
+fn trailing_spaces_start(chars: &[char], control_chars: ControlChars) -> usize {
+    let len = match chars.last() {
+        Some('\r') => chars.len() - 1,
+        _ => chars.len(),
+    };
+    let mut start = len;
+    let mut i = 0;
+    while i < len {
+        match hidden_escape_len(&chars[i..], control_chars) {
+            Some(escape_len) => i += escape_len,
+            None => {
+                if chars[i] != ' ' {
+                    start = len;
+                } else if start == len {
+                    start = i;
+                }
+                i += 1;
+            }
+        }
+    }
+    start
+}
+
+/// If c is a control char shown in notation: C0 but tab, DEL or C1, like the CSI of 8-bit
+/// terminals.
+fn is_control(c: char) -> bool {
+    matches!(c, '\x00'..='\x08' | '\x0a'..='\x1f' | '\x7f'..='\u{9f}')
+}
+
+/// If line has control chars, looking at its bytes: C1 chars are 0xc2 and 0x80-0x9f in UTF-8.
+fn has_control_chars(line: &str) -> bool {
+    let bytes = line.as_bytes();
+    bytes.iter().enumerate().any(|(i, c)| {
+        *c < b' ' || *c == b'\x7f' || (*c == 0xc2 && matches!(bytes.get(i + 1), Some(0x80..=0x9f)))
+    })
+}
+
+/// How the control char c is shown. C1 chars have no caret notation: they're in hex.
+fn in_notation(c: char, notation: ControlNotation) -> String {
+    match (c, notation) {
+        ('\x00'..='\x1f', ControlNotation::Caret) => format!("^{}", (c as u8 + b'@') as char),
+        ('\x7f', ControlNotation::Caret) => "^?".to_owned(),
+        _ => format!("\\x{:02x}", c as u32),
+    }
+}
+
+/// The length of the escape sequence at the start of chars, if it's not shown as text:
+/// passed to the terminal or removed.
+fn hidden_escape_len(chars: &[char], control_chars: ControlChars) -> Option<usize> {
+    match control_chars {
+        ControlChars::Caret => None,
+        ControlChars::Raw => sgr_len(chars),
+        ControlChars::Strip => escape_len(chars),
+    }
+}
+
+/// The length of the color sequence at the start of chars, if there's one.
+fn sgr_len(chars: &[char]) -> Option<usize> {
+    if chars.get(..2) != Some(&[ESC, '['][..]) {
+        return None;
+    }
+    let params = chars[2..]
+        .iter()
+        .take_while(|c| c.is_ascii_digit() || **c == ';' || **c == ':')
+        .count();
+    match chars.get(2 + params) {
+        Some('m') => Some(2 + params + 1),
+        _ => None,
+    }
+}
+
+/// The length of the escape sequence at the start of chars, if there's one: a CSI like
+/// `ESC[2J`, an OSC like the `ESC]8;;url BEL` of links, or ESC and a single char.
+fn escape_len(chars: &[char]) -> Option<usize> {
+    if chars.first() != Some(&ESC) {
+        return None;
+    }
+    let len = match chars.get(1) {
+        None => 1,
+        Some('[') => {
+            // Parameters and intermediate bytes, then the final one.
+            let body = chars[2..]
+                .iter()
+                .take_while(|c| matches!(c, '\x20'..='\x3f'))
+                .count();
+            match chars.get(2 + body) {
+                Some('\x40'..='\x7e') => 2 + body + 1,
+                _ => 2 + body,
+            }
+        }
+        // Ends with BEL, or with ESC \.
+        Some(']') => match chars[2..]
+            .windows(2)
+            .position(|pair| pair[0] == '\x07' || pair == [ESC, '\\'])
+        {
+            Some(end) if chars[2 + end] == '\x07' => 2 + end + 1,
+            Some(end) => 2 + end + 2,
+            // Unterminated: the rest of the line.
+            None => chars.len(),
+        },
+        Some(_) => 2,
+    };
+    Some(len)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lesser::ansi::{
+        line_width, overstrike, parse_style, render_line, wrap_columns, ControlChars,
+        ControlNotation, Wrapping,
+    };
+    use std::borrow::Cow;
+
+    #[test]
+    fn test_render_line() {
+        let line = "\x1b[31mred\x1b[0m plain";
+        assert_eq!(
+            render_line(
+                line,
+                0,
+                5,
+                ControlChars::Raw,
+                8,
+                ControlNotation::Caret,
+                false
+            ),
+            ("\x1b[31mred\x1b[0m p\x1b[0m".into(), true)
+        );
+        // The color set before the first column shown is kept.
+        assert_eq!(
+            render_line(
+                line,
+                1,
+                2,
+                ControlChars::Raw,
+                8,
+                ControlNotation::Caret,
+                false
+            ),
+            ("\x1b[31med\x1b[0m".into(), true)
+        );
+        assert_eq!(
+            render_line(
+                line,
+                0,
+                7,
+                ControlChars::Caret,
+                8,
+                ControlNotation::Caret,
+                false
+            ),
+            ("\x1b[7m^[\x1b[27m[31mr".into(), true)
+        );
+        assert_eq!(
+            render_line(
+                "a\tb",
+                0,
+                10,
+                ControlChars::Caret,
+                8,
+                ControlNotation::Caret,
+                false
+            ),
+            ("a       b".into(), true)
+        );
+        assert_eq!(
+            render_line(
+                "short",
+                10,
+                10,
+                ControlChars::Raw,
+                8,
+                ControlNotation::Caret,
+                false
+            ),
+            ("".into(), false)
+        );
+        assert_eq!(
+            line_width(line, ControlChars::Raw, 8, ControlNotation::Caret, false),
+            9
+        );
+        assert_eq!(
+            line_width(line, ControlChars::Caret, 8, ControlNotation::Caret, false),
+            20
+        );
+        assert_eq!(
+            line_width(
+                "dos\r",
+                ControlChars::Caret,
+                8,
+                ControlNotation::Caret,
+                false
+            ),
+            3
+        );
+    }
+
+    #[test]
+    fn test_overstrike() {
+        assert_eq!(overstrike("plain"), None);
+        assert_eq!(
+            overstrike("B\x08Bo\x08ld _\x08u+\x08o"),
+            Some("\x1b[1mB\x1b[0mld \x1b[4mu\x1b[0mo".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_parse_style() {
+        assert_eq!(parse_style("red"), Some("\x1b[31m".to_owned()));
+        assert_eq!(
+            parse_style("bold bright-yellow"),
+            Some("\x1b[1;93m".to_owned())
+        );
+        assert_eq!(parse_style("bright-bold"), None);
+        assert_eq!(parse_style("purple"), None);
+        assert_eq!(parse_style(""), None);
+    }
+
+    #[test]
+    fn test_strip() {
+        let line = "\x1b[1;31merror\x1b[0m: \x1b]8;;http://x\x07link\x1b]8;;\x1b\\ \x1b[2Kend";
+        assert_eq!(
+            render_line(
+                line,
+                0,
+                80,
+                ControlChars::Strip,
+                8,
+                ControlNotation::Caret,
+                false
+            ),
+            ("error: link end".into(), true)
+        );
+        assert_eq!(
+            line_width(line, ControlChars::Strip, 8, ControlNotation::Caret, false),
+            15
+        );
+        assert_eq!(
+            render_line(
+                "a\x1b",
+                0,
+                80,
+                ControlChars::Strip,
+                8,
+                ControlNotation::Caret,
+                false
+            ),
+            ("a".into(), true)
+        );
+    }
+
+    #[test]
+    fn test_render_plain_line() {
+        let (rendered, has_text) = render_line(
+            "héllo wörld\r",
+            1,
+            6,
+            ControlChars::Caret,
+            8,
+            ControlNotation::Caret,
+            false,
+        );
+        assert!(matches!(rendered, Cow::Borrowed("éllo w")));
+        assert!(has_text);
+        assert_eq!(
+            render_line(
+                "héllo",
+                5,
+                6,
+                ControlChars::Caret,
+                8,
+                ControlNotation::Caret,
+                false
+            ),
+            ("".into(), false)
+        );
+        assert_eq!(
+            render_line(
+                "a\tb",
+                0,
+                80,
+                ControlChars::Caret,
+                8,
+                ControlNotation::Caret,
+                false
+            ),
+            ("a       b".into(), true)
+        );
+    }
+
+    #[test]
+    fn test_tabs() {
+        // To the next tab stop, wherever the tab is.
+        assert_eq!(
+            render_line(
+                "a\tbc\td",
+                0,
+                80,
+                ControlChars::Caret,
+                4,
+                ControlNotation::Caret,
+                false
+            ),
+            ("a   bc  d".into(), true)
+        );
+        assert_eq!(
+            line_width(
+                "a\tbc\td",
+                ControlChars::Caret,
+                4,
+                ControlNotation::Caret,
+                false
+            ),
+            9
+        );
+        assert_eq!(
+            line_width(
+                "\t\t",
+                ControlChars::Caret,
+                8,
+                ControlNotation::Caret,
+                false
+            ),
+            16
+        );
+        // Scrolled, the tab stops stay where they are in the line.
+        assert_eq!(
+            render_line(
+                "a\tb",
+                2,
+                80,
+                ControlChars::Caret,
+                8,
+                ControlNotation::Caret,
+                false
+            ),
+            ("      b".into(), true)
+        );
+        // Colors take no columns.
+        assert_eq!(
+            render_line(
+                "\x1b[1ma\x1b[0m\tb",
+                0,
+                80,
+                ControlChars::Raw,
+                4,
+                ControlNotation::Caret,
+                false
+            ),
+            ("\x1b[1ma\x1b[0m   b\x1b[0m".into(), true)
+        );
+    }
+
+    #[test]
+    fn test_control_notation() {
+        let line = "a\x07b\u{9b}2J\x7f";
+        assert_eq!(
+            render_line(
+                line,
+                0,
+                80,
+                ControlChars::Caret,
+                8,
+                ControlNotation::Caret,
+                false
+            ),
+            (
+                "a\x1b[7m^G\x1b[27mb\x1b[7m\\x9b\x1b[27m2J\x1b[7m^?\x1b[27m".into(),
+                true
+            )
+        );
+        assert_eq!(
+            render_line(
+                line,
+                0,
+                80,
+                ControlChars::Caret,
+                8,
+                ControlNotation::Hex,
+                false
+            ),
+            (
+                "a\x1b[7m\\x07\x1b[27mb\x1b[7m\\x9b\x1b[27m2J\x1b[7m\\x7f\x1b[27m".into(),
+                true
+            )
+        );
+        assert_eq!(
+            line_width(line, ControlChars::Caret, 8, ControlNotation::Hex, false),
+            16
+        );
+        // Cut by the horizontal scroll, and among colors.
+        assert_eq!(
+            render_line(
+                line,
+                3,
+                2,
+                ControlChars::Caret,
+                8,
+                ControlNotation::Hex,
+                false
+            ),
+            ("\x1b[7m07\x1b[27m".into(), true)
+        );
+        assert_eq!(
+            render_line(
+                "\x1b[31ma\x01",
+                0,
+                80,
+                ControlChars::Raw,
+                8,
+                ControlNotation::Caret,
+                false
+            ),
+            ("\x1b[31ma\x1b[7m^A\x1b[27m\x1b[0m".into(), true)
+        );
+    }
+
+    #[test]
+    fn test_whitespace() {
+        let render = |line, col_offset, cols| {
+            render_line(
+                line,
+                col_offset,
+                cols,
+                ControlChars::Caret,
+                4,
+                ControlNotation::Caret,
+                true,
+            )
+        };
+        assert_eq!(
+            render("a\tb c  \n", 0, 80),
+            (
+                "a\x1b[2m→  \x1b[22mb c\x1b[2m·\x1b[22m\x1b[2m·\x1b[22m\x1b[2m¶\x1b[22m".into(),
+                true
+            )
+        );
+        // The last line may have no line ending.
+        assert_eq!(render("a", 0, 80), ("a".into(), true));
+        assert_eq!(
+            render("dos\r\n", 0, 80),
+            ("dos\x1b[7m^M\x1b[27m\x1b[2m¶\x1b[22m".into(), true)
+        );
+        // Only the end of the line is shown.
+        assert_eq!(render("ab\n", 2, 80), ("\x1b[2m¶\x1b[22m".into(), true));
+        assert_eq!(render("ab\n", 3, 80), ("".into(), false));
+        assert_eq!(render("ab\n", 0, 2), ("ab".into(), true));
+        // Spaces before colors are trailing too.
+        let line = "a \x1b[0m\n";
+        assert_eq!(
+            render_line(
+                line,
+                0,
+                80,
+                ControlChars::Raw,
+                8,
+                ControlNotation::Caret,
+                true
+            ),
+            (
+                "a\x1b[2m·\x1b[22m\x1b[0m\x1b[2m¶\x1b[22m\x1b[0m".into(),
+                true
+            )
+        );
+        assert_eq!(
+            line_width(
+                "a\tb \r\n",
+                ControlChars::Caret,
+                4,
+                ControlNotation::Caret,
+                true
+            ),
+            9
+        );
+        assert_eq!(
+            line_width(
+                "a\tb \r\n",
+                ControlChars::Caret,
+                4,
+                ControlNotation::Caret,
+                false
+            ),
+            6
+        );
+    }
+
+    fn at(cols: usize) -> Wrapping {
+        Wrapping {
+            cols,
+            words: false,
+            marker_width: 0,
+        }
+    }
+
+    #[test]
+    fn test_wrap_words() {
+        let wrap = |line, words, marker_width| {
+            let wrapping = Wrapping {
+                cols: 6,
+                words,
+                marker_width,
+            };
+            wrap_columns(
+                line,
+                ControlChars::Caret,
+                8,
+                ControlNotation::Caret,
+                false,
+                wrapping,
+            )
+        };
+        let line = "one two three";
+        assert_eq!(wrap(line, false, 0), vec![0, 6, 12]);
+        // The spaces at the end of a row are left out.
+        assert_eq!(wrap(line, true, 0), vec![0, 4, 8]);
+        assert_eq!(wrap("abcdef ghi", true, 0), vec![0, 7]);
+        // Words longer than a row are split.
+        assert_eq!(wrap("a verylongword", true, 0), vec![0, 2, 8]);
+        // The rows after the first are narrower by the marker.
+        assert_eq!(wrap("abcdefghijk", false, 2), vec![0, 6, 10]);
+        assert_eq!(wrap(line, true, 2), vec![0, 4, 8, 12]);
+    }
+
+    #[test]
+    fn test_wrap_columns() {
+        let wrap = |line, whitespace, cols| {
+            wrap_columns(
+                line,
+                ControlChars::Caret,
+                4,
+                ControlNotation::Caret,
+                whitespace,
+                at(cols),
+            )
+        };
+        assert_eq!(wrap("", false, 4), vec![0]);
+        assert_eq!(wrap("abcd", false, 4), vec![0]);
+        assert_eq!(wrap("abcdefghij", false, 4), vec![0, 4, 8]);
+        // The ¶ takes a column too.
+        assert_eq!(wrap("abcd\n", true, 4), vec![0, 4]);
+        assert_eq!(wrap("abcd\n", false, 4), vec![0]);
+        // A wide char doesn't fit in the last column.
+        assert_eq!(wrap("abc日本", false, 4), vec![0, 3]);
+        assert_eq!(wrap("a\tb", false, 3), vec![0, 3]);
+        // Colors take no columns.
+        let line = "\x1b[31mabcd\x1b[0mef";
+        assert_eq!(
+            wrap_columns(
+                line,
+                ControlChars::Raw,
+                8,
+                ControlNotation::Caret,
+                false,
+                at(4)
+            ),
+            vec![0, 4]
+        );
+        assert_eq!(wrap("abc", false, 0), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn test_wide_chars() {
+        // Each CJK char takes two columns.
+        assert_eq!(
+            line_width(
+                "日本語",
+                ControlChars::Caret,
+                8,
+                ControlNotation::Caret,
+                false
+            ),
+            6
+        );
+        assert_eq!(
+            render_line(
+                "日本語",
+                0,
+                5,
+                ControlChars::Caret,
+                8,
+                ControlNotation::Caret,
+                false
+            ),
+            ("日本".into(), true)
+        );
+        // Cut in half at the start, it's a space.
+        assert_eq!(
+            render_line(
+                "日本語",
+                1,
+                4,
+                ControlChars::Caret,
+                8,
+                ControlNotation::Caret,
+                false
+            ),
+            (" 本".into(), true)
+        );
+        assert_eq!(
+            render_line(
+                "\x1b[1m日本語",
+                3,
+                3,
+                ControlChars::Raw,
+                8,
+                ControlNotation::Caret,
+                false
+            ),
+            ("\x1b[1m 語\x1b[0m".into(), true)
+        );
+    }
+
+    #[test]
+    fn test_grapheme_clusters() {
+        // An accented e, a family and a flag take one, two and two columns.
+        let line = "e\u{301}👨\u{200d}👩\u{200d}👧🇮🇹x";
+        assert_eq!(
+            line_width(line, ControlChars::Caret, 8, ControlNotation::Caret, false),
+            6
+        );
+        assert_eq!(
+            render_line(
+                line,
+                0,
+                1,
+                ControlChars::Caret,
+                8,
+                ControlNotation::Caret,
+                false
+            ),
+            ("e\u{301}".into(), true)
+        );
+        assert_eq!(
+            render_line(
+                line,
+                1,
+                3,
+                ControlChars::Caret,
+                8,
+                ControlNotation::Caret,
+                false
+            ),
+            ("👨\u{200d}👩\u{200d}👧".into(), true)
+        );
+        assert_eq!(
+            render_line(
+                line,
+                2,
+                4,
+                ControlChars::Caret,
+                8,
+                ControlNotation::Caret,
+                false
+            ),
+            (" 🇮🇹x".into(), true)
+        );
+        // Between colors too.
+        assert_eq!(
+            render_line(
+                "\x1b[1me\u{301}\x1b[0mx",
+                1,
+                1,
+                ControlChars::Raw,
+                8,
+                ControlNotation::Caret,
+                false
+            ),
+            ("\x1b[1m\x1b[0mx\x1b[0m".into(), true)
+        );
+    }
+}
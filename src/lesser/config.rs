@@ -0,0 +1,403 @@
+use crate::lesser::ansi::{parse_control_notation, parse_style, ControlNotation};
+use crate::lesser::highlight::{default_log_levels, LogLevel};
+use crate::lesser::keys::{default_keymap, parse_key, Action, Keymap};
+use crate::lesser::lesskey::{lesskey_path, parse_lesskey};
+use regex::Regex;
+use std::convert::TryFrom;
+use std::io::{Error, ErrorKind, Result};
+use std::path::PathBuf;
+use toml::Value;
+
+/// Settings read from `~/.config/lesser/config.toml`. They replace the built-in defaults, and
+/// the command line flags replace them.
+pub struct Config {
+    /// `literal = true`: patterns are literal strings by default, instead of regexes.
+    pub literal_search: bool,
+    /// `line-numbers = true`: show the line numbers.
+    pub line_numbers: bool,
+    /// `quit-if-one-screen = true`: print the file and exit if it fits in the screen.
+    pub quit_if_one_screen: bool,
+    /// `no-alt-screen = true`: leave the last page on the terminal when exiting.
+    pub no_alt_screen: bool,
+    /// `raw-control-chars = true`: pass the colors in the text to the terminal.
+    pub raw_control_chars: bool,
+    /// `strip-ansi = true`: remove the escape sequences, like colors, from the text.
+    pub strip_ansi: bool,
+    /// `tabs = 4`: the tab stops are this many columns apart, 8 by default.
+    pub tab_width: usize,
+    /// `control-chars = "hex"`: show the control chars like `\x07` instead of `^G`.
+    pub control_notation: ControlNotation,
+    /// `show-whitespace = true`: show the tabs, trailing spaces and line endings.
+    pub show_whitespace: bool,
+    /// `wrap = true`: wrap the long lines instead of scrolling them horizontally.
+    pub wrap: bool,
+    /// `wrap-words = true`: wrap the long lines between words.
+    pub wrap_words: bool,
+    /// `wrap-marker = "↪ "`: what the rows a long line is wrapped on start with.
+    pub wrap_marker: String,
+    /// `syntax-highlighting = false`: don't color the text by its syntax.
+    pub syntax_highlighting: bool,
+    /// The `[log-levels]` section: a pattern and the style of the lines of log files with it,
+    /// like `ERROR = "bold red"`. `"none"` doesn't color them.
+    pub log_levels: Vec<LogLevel>,
+    /// `jump-target = 5`: the screen row where goto and search put their target. Negative
+    /// counts from the bottom.
+    pub jump_target: i64,
+    /// `page-overlap = 1`: paging keeps this many rows of the page on the screen.
+    pub page_overlap: u64,
+    /// `prompt = "%f %pb\\%"`: the status line format, like less's -Ps.
+    pub prompt: Option<String>,
+    /// `resume = true`: reopen files where they were left the last time.
+    pub resume: bool,
+    /// `table-header = true`: keep the first row of CSV and TSV tables on the screen.
+    pub table_header: bool,
+    /// `render-markdown = true`: show Markdown files with styles instead of their markup.
+    pub render_markdown: bool,
+    /// `buffer-max = "16M"`: how much piped text is kept in memory before it's moved to a
+    /// temporary file. A number of bytes, or with K, M or G.
+    pub buffer_max: u64,
+    /// `drop-oldest = true`: past buffer-max, drop the oldest lines instead.
+    pub drop_oldest: bool,
+    /// `index-cache = true`: save the line index of big files, for the next time.
+    pub index_cache: bool,
+    /// The default keys, with the ones in `~/.lesskey` and in the `[keys]` section replaced.
+    pub keys: Keymap,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            literal_search: false,
+            line_numbers: false,
+            quit_if_one_screen: false,
+            no_alt_screen: false,
+            raw_control_chars: false,
+            strip_ansi: false,
+            tab_width: 8,
+            control_notation: ControlNotation::Caret,
+            show_whitespace: false,
+            wrap: false,
+            wrap_words: false,
+            wrap_marker: String::new(),
+            syntax_highlighting: true,
+            log_levels: default_log_levels(),
+            jump_target: 1,
+            page_overlap: 0,
+            prompt: None,
+            resume: false,
+            table_header: false,
+            render_markdown: false,
+            buffer_max: 4 * 1024 * 1024,
+            drop_oldest: false,
+            index_cache: false,
+            keys: default_keymap(),
+        }
+    }
+}
+
+impl Config {
+    /// Reads `~/.lesskey` and then the config file, so the config file wins. Missing files
+    /// mean the defaults.
+    pub fn load() -> Result<Config> {
+        let mut config = Config::default();
+        if let Some(text) = lesskey_path().map(read_if_exists).transpose()?.flatten() {
+            config.keys.extend(parse_lesskey(&text));
+        }
+        let path = match config_path() {
+            Some(path) => path,
+            None => return Ok(config),
+        };
+        if let Some(text) = read_if_exists(path.clone())? {
+            config.apply(&text).map_err(|error| {
+                Error::new(
+                    ErrorKind::InvalidData,
+                    format!("{}: {}", path.display(), error),
+                )
+            })?;
+        }
+        Ok(config)
+    }
+
+    /// Replaces the settings found in the config file text.
+    fn apply(&mut self, text: &str) -> std::result::Result<(), String> {
+        let value: Value = text.parse().map_err(|error| format!("{}", error))?;
+        let settings = value.as_table().ok_or("expected a table")?;
+        for (name, value) in settings {
+            match name.as_str() {
+                "literal" => self.literal_search = as_bool(name, value)?,
+                "line-numbers" => self.line_numbers = as_bool(name, value)?,
+                "quit-if-one-screen" => self.quit_if_one_screen = as_bool(name, value)?,
+                "no-alt-screen" => self.no_alt_screen = as_bool(name, value)?,
+                "raw-control-chars" => self.raw_control_chars = as_bool(name, value)?,
+                "strip-ansi" => self.strip_ansi = as_bool(name, value)?,
+                "tabs" => {
+                    self.tab_width = usize::try_from(as_integer(name, value)?)
+                        .ok()
+                        .filter(|tab_width| *tab_width > 0)
+                        .ok_or(format!("{} must be a positive number", name))?
+                }
+                "control-chars" => {
+                    self.control_notation = parse_control_notation(as_str(name, value)?)
+                        .map_err(|error| format!("{}: {}", name, error))?
+                }
+                "show-whitespace" => self.show_whitespace = as_bool(name, value)?,
+                "wrap" => self.wrap = as_bool(name, value)?,
+                "wrap-words" => self.wrap_words = as_bool(name, value)?,
+                "wrap-marker" => self.wrap_marker = as_str(name, value)?.to_owned(),
+                "syntax-highlighting" => self.syntax_highlighting = as_bool(name, value)?,
+                "jump-target" => self.jump_target = as_integer(name, value)?,
+                "page-overlap" => {
+                    self.page_overlap = u64::try_from(as_integer(name, value)?)
+                        .map_err(|_| format!("{} can't be negative", name))?
+                }
+                "prompt" => self.prompt = Some(as_str(name, value)?.to_owned()),
+                "resume" => self.resume = as_bool(name, value)?,
+                "table-header" => self.table_header = as_bool(name, value)?,
+                "render-markdown" => self.render_markdown = as_bool(name, value)?,
+                "buffer-max" => self.buffer_max = as_size(name, value)?,
+                "drop-oldest" => self.drop_oldest = as_bool(name, value)?,
+                "index-cache" => self.index_cache = as_bool(name, value)?,
+                "keys" => self.apply_keys(value)?,
+                "log-levels" => self.apply_log_levels(value)?,
+                _ => return Err(format!("unknown setting {:?}", name)),
+            }
+        }
+        Ok(())
+    }
+
+    fn apply_keys(&mut self, keys: &Value) -> std::result::Result<(), String> {
+        let keys = keys.as_table().ok_or("[keys] must be a table")?;
+        for (key_name, action_name) in keys {
+            let key = parse_key(key_name).ok_or(format!("unknown key {:?}", key_name))?;
+            let action = action_name
+                .as_str()
+                .and_then(Action::from_name)
+                .ok_or(format!(
+                    "unknown action {} for key {:?}",
+                    action_name, key_name
+                ))?;
+            self.keys.insert(key, action);
+        }
+        Ok(())
+    }
+
+    /// Levels already known (like the default ones) are replaced.
+    fn apply_log_levels(&mut self, levels: &Value) -> std::result::Result<(), String> {
+        let levels = levels.as_table().ok_or("[log-levels] must be a table")?;
+        for (name, style) in levels {
+            self.log_levels.retain(|level| level.name != *name);
+            let style = as_str(name, style)?;
+            if style == "none" {
+                continue;
+            }
+            let pattern = Regex::new(name)
+                .map_err(|error| format!("invalid log level pattern {:?}: {}", name, error))?;
+            let style =
+                parse_style(style).ok_or(format!("unknown style {:?} for {:?}", style, name))?;
+            self.log_levels.push(LogLevel {
+                name: name.clone(),
+                pattern,
+                style,
+            });
+        }
+        Ok(())
+    }
+}
+
+fn as_bool(name: &str, value: &Value) -> std::result::Result<bool, String> {
+    value
+        .as_bool()
+        .ok_or(format!("{} must be true or false", name))
+}
+
+fn as_str<'a>(name: &str, value: &'a Value) -> std::result::Result<&'a str, String> {
+    value.as_str().ok_or(format!("{} must be a string", name))
+}
+
+fn as_integer(name: &str, value: &Value) -> std::result::Result<i64, String> {
+    value
+        .as_integer()
+        .ok_or(format!("{} must be a number", name))
+}
+
+/// A number of bytes, or a string with K, M or G after it, like `"16M"`.
+fn as_size(name: &str, value: &Value) -> std::result::Result<u64, String> {
+    let error = || format!("{} must be a size, like 4096 or \"16M\"", name);
+    match value.as_integer() {
+        Some(bytes) => u64::try_from(bytes).map_err(|_| error()),
+        None => parse_size(value.as_str().ok_or_else(error)?).map_err(|_| error()),
+    }
+}
+
+/// A number of bytes, with K, M or G after it for KiB, MiB or GiB, like `16M`.
+pub fn parse_size(text: &str) -> std::result::Result<u64, String> {
+    let text = text.trim();
+    let (number, unit) = match text.char_indices().last() {
+        Some((index, 'K' | 'k')) => (&text[..index], 1 << 10),
+        Some((index, 'M' | 'm')) => (&text[..index], 1 << 20),
+        Some((index, 'G' | 'g')) => (&text[..index], 1 << 30),
+        _ => (text, 1),
+    };
+    number
+        .trim()
+        .parse::<u64>()
+        .ok()
+        .and_then(|number| number.checked_mul(unit))
+        .ok_or(format!("{:?} is not a size, like 4096 or 16M", text))
+}
+
+/// A byte the lines end with: a char of one byte, like `,`, or `\0`, `\n`, `\r`, `\t`, or
+/// `\x` and two hex digits, like `\x1e`.
+pub fn parse_separator(text: &str) -> std::result::Result<u8, String> {
+    let byte = match text {
+        "\\0" => Some(b'\0'),
+        "\\n" => Some(b'\n'),
+        "\\r" => Some(b'\r'),
+        "\\t" => Some(b'\t'),
+        _ => match text.strip_prefix("\\x") {
+            Some(hex) if hex.len() == 2 => u8::from_str_radix(hex, 16).ok(),
+            Some(_) => None,
+            None if text.len() == 1 => Some(text.as_bytes()[0]),
+            None => None,
+        },
+    };
+    byte.ok_or(format!("{:?} is not a byte, like , or \\0 or \\x1e", text))
+}
+
+fn read_if_exists(path: PathBuf) -> Result<Option<String>> {
+    match std::fs::read_to_string(path) {
+        Ok(text) => Ok(Some(text)),
+        Err(error) if error.kind() == ErrorKind::NotFound => Ok(None),
+        Err(error) => Err(error),
+    }
+}
+
+/// `$XDG_CONFIG_HOME/lesser/config.toml`, or `~/.config/lesser/config.toml`.
+fn config_path() -> Option<PathBuf> {
+    let config_dir = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+    };
+    Some(config_dir.join("lesser").join("config.toml"))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lesser::ansi::ControlNotation;
+    use crate::lesser::config::{parse_separator, Config};
+    use crate::lesser::keys::Action;
+    use termion::event::Key;
+
+    #[test]
+    fn test_settings() {
+        let mut config = Config::default();
+        config.apply("literal = true\n").unwrap();
+        assert!(config.literal_search);
+        assert!(!config.line_numbers);
+
+        config.apply("tabs = 4").unwrap();
+        assert_eq!(config.tab_width, 4);
+        config.apply("control-chars = \"hex\"").unwrap();
+        assert_eq!(config.control_notation, ControlNotation::Hex);
+        config.apply("show-whitespace = true").unwrap();
+        assert!(config.show_whitespace);
+        config.apply("wrap = true").unwrap();
+        assert!(config.wrap);
+        config.apply("wrap-marker = \"↪ \"").unwrap();
+        assert_eq!(config.wrap_marker, "↪ ");
+        config.apply("page-overlap = 2").unwrap();
+        assert_eq!(config.page_overlap, 2);
+        assert!(Config::default()
+            .apply("control-chars = \"octal\"")
+            .is_err());
+        assert!(Config::default().apply("tabs = 0").is_err());
+        assert!(Config::default().apply("page-overlap = -1").is_err());
+        assert!(Config::default().apply("line-numbers = 1").is_err());
+        assert!(Config::default().apply("bell = true").is_err());
+    }
+
+    #[test]
+    fn test_buffer_max() {
+        let mut config = Config::default();
+        config.apply("buffer-max = \"16M\"").unwrap();
+        assert_eq!(config.buffer_max, 16 * 1024 * 1024);
+        config.apply("buffer-max = 1000").unwrap();
+        assert_eq!(config.buffer_max, 1000);
+        config.apply("buffer-max = \"2 k\"").unwrap();
+        assert_eq!(config.buffer_max, 2048);
+        assert!(!config.drop_oldest);
+        config.apply("drop-oldest = true").unwrap();
+        assert!(config.drop_oldest);
+
+        assert!(Config::default().apply("buffer-max = -1").is_err());
+        assert!(Config::default().apply("buffer-max = \"16X\"").is_err());
+    }
+
+    #[test]
+    fn test_parse_separator() {
+        assert_eq!(parse_separator("\\0"), Ok(0));
+        assert_eq!(parse_separator(","), Ok(b','));
+        assert_eq!(parse_separator("\\t"), Ok(b'\t'));
+        assert_eq!(parse_separator("\\x1e"), Ok(0x1e));
+        assert!(parse_separator("").is_err());
+        assert!(parse_separator("ab").is_err());
+        assert!(parse_separator("é").is_err());
+        assert!(parse_separator("\\x1").is_err());
+    }
+
+    #[test]
+    fn test_keys_section() {
+        let mut config = Config::default();
+        config
+            .apply(
+                r#"
+[keys]
+x = "quit"
+"ctrl-j" = "down"
+space = "half-page-down"
+q = "nothing"
+"#,
+            )
+            .unwrap();
+        assert_eq!(config.keys.get(&Key::Char('x')), Some(&Action::Quit));
+        assert_eq!(config.keys.get(&Key::Ctrl('j')), Some(&Action::Down));
+        assert_eq!(
+            config.keys.get(&Key::Char(' ')),
+            Some(&Action::HalfPageDown)
+        );
+        assert_eq!(config.keys.get(&Key::Char('q')), Some(&Action::Nothing));
+        // The keys not in the file keep their default.
+        assert_eq!(config.keys.get(&Key::Char('j')), Some(&Action::Down));
+
+        assert!(Config::default().apply("[keys]\nx = \"fly\"").is_err());
+        assert!(Config::default()
+            .apply("[keys]\nctrl-xy = \"quit\"")
+            .is_err());
+    }
+
+    #[test]
+    fn test_log_levels_section() {
+        let mut config = Config::default();
+        config
+            .apply("[log-levels]\nERROR = \"reverse red\"\nTRACE = \"none\"\nFATAL = \"magenta\"\n")
+            .unwrap();
+        let styles: Vec<(&str, &str)> = config
+            .log_levels
+            .iter()
+            .map(|level| (level.name.as_str(), level.style.as_str()))
+            .collect();
+        assert_eq!(
+            styles,
+            vec![
+                ("WARN", "\x1b[33m"),
+                ("INFO", "\x1b[32m"),
+                ("DEBUG", "\x1b[34m"),
+                ("ERROR", "\x1b[7;31m"),
+                ("FATAL", "\x1b[35m"),
+            ]
+        );
+        assert!(Config::default()
+            .apply("[log-levels]\nERROR = \"purple\"")
+            .is_err());
+    }
+}
@@ -0,0 +1,77 @@
+use std::path::{Path, PathBuf};
+
+/// A compression format, known by the magic bytes at the start of the file.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Compression {
+    Gzip,
+    Bzip2,
+    Xz,
+    Zstd,
+}
+
+impl Compression {
+    /// The compression of a file starting with header, if it's compressed.
+    pub(crate) fn of(header: &[u8]) -> Option<Compression> {
+        let compression = if header.starts_with(b"\x1f\x8b") {
+            Compression::Gzip
+        } else if header.starts_with(b"BZh") {
+            Compression::Bzip2
+        } else if header.starts_with(b"\xfd7zXZ\x00") {
+            Compression::Xz
+        } else if header.starts_with(b"\x28\xb5\x2f\xfd") {
+            Compression::Zstd
+        } else {
+            return None;
+        };
+        Some(compression)
+    }
+
+    /// The command decompressing its standard input to its standard output, like zless
+    /// uses.
+    pub(crate) fn command(self) -> (&'static str, &'static [&'static str]) {
+        match self {
+            Compression::Gzip => ("gzip", &["-dc"]),
+            Compression::Bzip2 => ("bzip2", &["-dc"]),
+            Compression::Xz => ("xz", &["-dc"]),
+            Compression::Zstd => ("zstd", &["-dcq"]),
+        }
+    }
+}
+
+/// The path without the extension of the compression, like `access.log` for
+/// `access.log.gz`, to know the format of the text in it.
+pub(crate) fn decompressed_path(path: &Path) -> PathBuf {
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some("gz" | "bz2" | "xz" | "zst") => path.with_extension(""),
+        _ => path.to_path_buf(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lesser::decompress::{decompressed_path, Compression};
+    use std::path::Path;
+
+    #[test]
+    fn test_compression() {
+        assert_eq!(
+            Compression::of(b"\x1f\x8b\x08\x00"),
+            Some(Compression::Gzip)
+        );
+        assert_eq!(Compression::of(b"BZh91AY"), Some(Compression::Bzip2));
+        assert_eq!(Compression::of(b"\xfd7zXZ\x00\x00"), Some(Compression::Xz));
+        assert_eq!(
+            Compression::of(b"\x28\xb5\x2f\xfd"),
+            Some(Compression::Zstd)
+        );
+        assert_eq!(Compression::of(b"plain text"), None);
+        assert_eq!(
+            decompressed_path(Path::new("logs/access.log.gz")),
+            Path::new("logs/access.log")
+        );
+        assert_eq!(
+            decompressed_path(Path::new("data.csv")),
+            Path::new("data.csv")
+        );
+    }
+}
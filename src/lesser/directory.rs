@@ -0,0 +1,135 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+/// The files in a directory, a line each, and the path on each line.
+#[derive(Debug)]
+pub(crate) struct Listing {
+    pub(crate) text: String,
+    /// The path of the file on each line, starting from line 0.
+    pub(crate) paths: Vec<PathBuf>,
+}
+
+/// Lists the files in dir like `ls -l` with their size and last change: the parent
+/// directory first, then the directories, then the other files.
+pub(crate) fn list(dir: &Path) -> io::Result<Listing> {
+    let mut entries = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        // Follows symlinks, like opening the file would. A broken one has no size.
+        let metadata = fs::metadata(entry.path()).ok();
+        let is_dir = matches!(&metadata, Some(metadata) if metadata.is_dir());
+        let mut name = entry.file_name().to_string_lossy().into_owned();
+        if is_dir {
+            name.push('/');
+        }
+        entries.push((!is_dir, name, metadata, entry.path()));
+    }
+    entries.sort_by(|a, b| (a.0, &a.1).cmp(&(b.0, &b.1)));
+    let parent = (
+        "../".to_owned(),
+        fs::metadata(dir.join("..")).ok(),
+        dir.join(".."),
+    );
+    let entries = std::iter::once(parent).chain(
+        entries
+            .into_iter()
+            .map(|(_is_file, name, metadata, path)| (name, metadata, path)),
+    );
+    let mut lines = Vec::new();
+    let mut paths = Vec::new();
+    for (name, metadata, path) in entries {
+        let (size, modified) = match &metadata {
+            Some(metadata) if metadata.is_dir() => (String::new(), metadata.modified().ok()),
+            Some(metadata) => (human_size(metadata.len()), metadata.modified().ok()),
+            None => (String::new(), None),
+        };
+        let modified = modified
+            .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+            .map_or_else(String::new, |since| format_time(since.as_secs()));
+        lines.push(format!("{:>6}  {:<16}  {}", size, modified, name));
+        paths.push(path);
+    }
+    Ok(Listing {
+        text: lines.join("\n"),
+        paths,
+    })
+}
+
+/// The size in bytes, or in K, M, G... with a decimal when it's short, like `ls -lh`.
+pub(crate) fn human_size(bytes: u64) -> String {
+    if bytes < 1024 {
+        return bytes.to_string();
+    }
+    let mut size = bytes as f64;
+    for unit in ["K", "M", "G", "T", "P"] {
+        size /= 1024.0;
+        if size < 1024.0 || unit == "P" {
+            return if size < 10.0 {
+                format!("{:.1}{}", size, unit)
+            } else {
+                format!("{:.0}{}", size, unit)
+            };
+        }
+    }
+    unreachable!()
+}
+
+/// The time, in seconds since the epoch, as `YYYY-MM-DD HH:MM` in UTC.
+fn format_time(secs: u64) -> String {
+    let days = (secs / 86400) as i64;
+    let (hour, minute) = (secs % 86400 / 3600, secs % 3600 / 60);
+    // The civil date of the days since 1970-01-01, from Howard Hinnant's algorithms.
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}",
+        year, month, day, hour, minute
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lesser::directory::{format_time, human_size, list};
+    use std::fs;
+
+    #[test]
+    fn test_list() {
+        let dir = tempdir::TempDir::new("lesser").unwrap();
+        fs::write(dir.path().join("b.txt"), "hello").unwrap();
+        fs::write(dir.path().join("a.txt"), "").unwrap();
+        fs::create_dir(dir.path().join("z")).unwrap();
+        let listing = list(dir.path()).unwrap();
+        let names: Vec<&str> = listing
+            .text
+            .lines()
+            .map(|line| line.rsplit(' ').next().unwrap())
+            .collect();
+        assert_eq!(names, ["../", "z/", "a.txt", "b.txt"]);
+        assert_eq!(listing.paths[3], dir.path().join("b.txt"));
+        assert!(listing.text.lines().nth(3).unwrap().starts_with("     5  "));
+    }
+
+    #[test]
+    fn test_formats() {
+        assert_eq!(human_size(0), "0");
+        assert_eq!(human_size(1023), "1023");
+        assert_eq!(human_size(1536), "1.5K");
+        assert_eq!(human_size(200 * 1024 * 1024), "200M");
+        assert_eq!(format_time(0), "1970-01-01 00:00");
+        assert_eq!(format_time(951_827_696), "2000-02-29 12:34");
+    }
+}
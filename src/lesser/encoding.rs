@@ -0,0 +1,133 @@
+use encoding_rs::{DecoderResult, Encoding, SHIFT_JIS, UTF_16BE, UTF_16LE, UTF_8, WINDOWS_1252};
+
+/// The encoding of a text starting with head, if it's not UTF-8: from its byte order mark, or
+/// else guessed. UTF-16 has a NUL byte in each ASCII char. Text that isn't UTF-8 is Shift-JIS
+/// if it decodes as Japanese, with kana in it, or else Latin-1. None for UTF-8, and for what
+/// looks binary: NUL bytes, or many control chars.
+pub(crate) fn detect(head: &[u8]) -> Option<&'static Encoding> {
+    if let Some((encoding, _bom_len)) = Encoding::for_bom(head) {
+        return Some(encoding).filter(|encoding| *encoding != UTF_8);
+    }
+    if let Some(encoding) = utf_16(head) {
+        return Some(encoding);
+    }
+    if head.contains(&0) {
+        return None;
+    }
+    match std::str::from_utf8(head) {
+        Ok(_) => return None,
+        // The last char was cut by the end of head.
+        Err(error) if error.error_len().is_none() => return None,
+        Err(_) => (),
+    }
+    let controls = head
+        .iter()
+        .filter(|byte| byte.is_ascii_control() && !b"\t\n\r\x0c\x1b\x08".contains(byte))
+        .count();
+    if controls * 10 > head.len() {
+        return None;
+    }
+    if looks_japanese(head) {
+        Some(SHIFT_JIS)
+    } else {
+        Some(WINDOWS_1252)
+    }
+}
+
+/// UTF-16 without a byte order mark: most of the high (or low) bytes are 0, and none of the
+/// others, like for ASCII text.
+fn utf_16(head: &[u8]) -> Option<&'static Encoding> {
+    let units = head.len() / 2;
+    if units == 0 {
+        return None;
+    }
+    let zeros = |first: usize| {
+        head.iter()
+            .skip(first)
+            .step_by(2)
+            .filter(|c| **c == 0)
+            .count()
+    };
+    let (even, odd) = (zeros(0), zeros(1));
+    if odd * 2 > units && even == 0 {
+        Some(UTF_16LE)
+    } else if even * 2 > units && odd == 0 {
+        Some(UTF_16BE)
+    } else {
+        None
+    }
+}
+
+/// If head is all Shift-JIS, and at least a fifth of its chars that aren't ASCII are hiragana
+/// or katakana: Latin-1 text decodes as Shift-JIS too, as kanji or half-width katakana.
+fn looks_japanese(head: &[u8]) -> bool {
+    let mut decoder = SHIFT_JIS.new_decoder_without_bom_handling();
+    let mut decoded = String::with_capacity(
+        decoder
+            .max_utf8_buffer_length_without_replacement(head.len())
+            .unwrap_or(0),
+    );
+    // Not the last bytes: the last char can be cut by the end of head.
+    let (result, _read) = decoder.decode_to_string_without_replacement(head, &mut decoded, false);
+    if result != DecoderResult::InputEmpty {
+        return false;
+    }
+    let not_ascii = decoded.chars().filter(|c| !c.is_ascii()).count();
+    let kana = decoded
+        .chars()
+        .filter(|c| ('\u{3040}'..='\u{30ff}').contains(c))
+        .count();
+    kana > 0 && kana * 5 >= not_ascii
+}
+
+/// How long the UTF-8 byte order mark text starts with is: 0 if it has none.
+pub(crate) fn utf_8_bom_len(text: &[u8]) -> usize {
+    match Encoding::for_bom(text) {
+        Some((encoding, bom_len)) if encoding == UTF_8 => bom_len,
+        _ => 0,
+    }
+}
+
+/// The encoding called name, like `latin1`, `utf-16le` or `shift_jis`.
+pub fn parse_encoding(name: &str) -> Result<&'static Encoding, String> {
+    Encoding::for_label(name.as_bytes()).ok_or(format!("{:?} is not a known encoding", name))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lesser::encoding::{detect, parse_encoding, utf_8_bom_len};
+    use encoding_rs::{SHIFT_JIS, UTF_16BE, UTF_16LE, WINDOWS_1252};
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(detect(b"plain text\n"), None);
+        assert_eq!(detect("caf\u{e9}\n".as_bytes()), None);
+        assert_eq!(detect(b"\xef\xbb\xbfwith a BOM\n"), None);
+        assert_eq!(detect(b"\xff\xfea\x00b\x00"), Some(UTF_16LE));
+        assert_eq!(detect(b"a\x00b\x00\n\x00"), Some(UTF_16LE));
+        assert_eq!(detect(b"\x00a\x00b\x00\n"), Some(UTF_16BE));
+        assert_eq!(detect(b"caf\xe9 cr\xe8me\n"), Some(WINDOWS_1252));
+        // こんにちは, then a char cut short.
+        assert_eq!(
+            detect(b"\x82\xb1\x82\xf1\x82\xc9\x82\xbf\x82\xcd\n\x82"),
+            Some(SHIFT_JIS)
+        );
+        // Binary.
+        assert_eq!(detect(b"\x7fELF\x02\x01\x01\x00\x00\x00"), None);
+        assert_eq!(detect(b"\x01\x02\x03\xff\x04"), None);
+    }
+
+    #[test]
+    fn test_utf_8_bom_len() {
+        assert_eq!(utf_8_bom_len(b"\xef\xbb\xbfa"), 3);
+        assert_eq!(utf_8_bom_len(b"a"), 0);
+        assert_eq!(utf_8_bom_len(b"\xff\xfea\x00"), 0);
+    }
+
+    #[test]
+    fn test_parse_encoding() {
+        assert_eq!(parse_encoding("latin1"), Ok(WINDOWS_1252));
+        assert_eq!(parse_encoding("UTF-16LE"), Ok(UTF_16LE));
+        assert!(parse_encoding("klingon").is_err());
+    }
+}
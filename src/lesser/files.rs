@@ -0,0 +1,83 @@
+use std::path::{Path, PathBuf};
+
+/// The files given on the command line, and which one is shown.
+pub(crate) struct FileList {
+    filenames: Vec<PathBuf>,
+    current: usize,
+}
+
+impl FileList {
+    /// With no filenames the standard input is shown.
+    pub(crate) fn new(filenames: Vec<PathBuf>) -> Self {
+        FileList {
+            filenames,
+            current: 0,
+        }
+    }
+
+    /// The file shown, None (or `-`) for the standard input.
+    pub(crate) fn current(&self) -> Option<&Path> {
+        self.filenames.get(self.current).map(PathBuf::as_path)
+    }
+
+    /// The index of the file shown.
+    pub(crate) fn index(&self) -> usize {
+        self.current
+    }
+
+    pub(crate) fn get(&self, index: usize) -> &Path {
+        &self.filenames[index]
+    }
+
+    pub(crate) fn set_current(&mut self, index: usize) {
+        self.current = index;
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.filenames.len()
+    }
+
+    /// Adds filename at the end of the list.
+    pub(crate) fn push(&mut self, filename: PathBuf) {
+        self.filenames.push(filename);
+    }
+
+    /// The index of the file count files after the current one, if there's one.
+    pub(crate) fn next_index(&self, count: usize) -> Option<usize> {
+        let index = self.current.checked_add(count)?;
+        (index < self.filenames.len()).then_some(index)
+    }
+
+    /// The index of the file count files before the current one, if there's one.
+    pub(crate) fn previous_index(&self, count: usize) -> Option<usize> {
+        self.current.checked_sub(count)
+    }
+
+    /// Which file is shown, from 1, and how many there are. None if there's only one.
+    pub(crate) fn position(&self) -> Option<(usize, usize)> {
+        (self.filenames.len() > 1).then_some((self.current + 1, self.filenames.len()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lesser::files::FileList;
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    fn test_file_list() {
+        let mut files = FileList::new(vec![PathBuf::from("a"), PathBuf::from("b")]);
+        assert_eq!(files.current(), Some(Path::new("a")));
+        assert_eq!(files.position(), Some((1, 2)));
+        assert_eq!(files.previous_index(1), None);
+        assert_eq!(files.next_index(2), None);
+        files.set_current(files.next_index(1).unwrap());
+        assert_eq!(files.current(), Some(Path::new("b")));
+        assert_eq!(files.position(), Some((2, 2)));
+        assert_eq!(files.previous_index(1), Some(0));
+
+        let stdin = FileList::new(Vec::new());
+        assert_eq!(stdin.current(), None);
+        assert_eq!(stdin.position(), None);
+    }
+}
@@ -1,14 +1,141 @@
+use crate::lesser::prompt::PromptLine;
+use crate::lesser::search::Pattern;
+use std::path::PathBuf;
+
 #[derive(Debug)]
 pub(crate) enum Message {
     Empty,
-    ScrollDownPage,
-    ScrollDown,
-    ScrollUpPage,
-    ScrollUp,
-    ScrollLeft,
-    ScrollRight,
+    // Scrolls carry how many times to repeat them.
+    ScrollDownPage(u64),
+    /// Move down one line (a row, if filters are active).
+    ScrollDown(u64),
+    ScrollUpPage(u64),
+    /// Move up one line (a row, if filters are active).
+    ScrollUp(u64),
+    ScrollLeft(u64),
+    ScrollRight(u64),
+    /// Move down this many rows, or half a page if None.
+    ScrollDownHalfPage(Option<u64>),
+    /// Move up this many rows, or half a page if None.
+    ScrollUpHalfPage(Option<u64>),
     ScrollToBeginning,
     ScrollToEnd,
+    /// Go to this line number (starting from 1).
+    GotoLine(u64),
+    /// Go to this percent of the file.
+    GotoPercent(u64),
     Exit,
     Reload,
+    ToggleLineNumbers,
+    /// Flip the option with this letter, like N for the line numbers.
+    ToggleOption(char),
+    /// Remember the current position with this letter.
+    SetMark(char),
+    /// Go back to the position marked with this letter, or before the last jump if `'`.
+    GotoMark(char),
+    /// Go back this many entries in the jump list.
+    JumpBack(u64),
+    /// Go forward this many entries in the jump list.
+    JumpForward(u64),
+    /// Go to the page starting at the count-th form feed after the top of the screen, or
+    /// before it if backwards.
+    FormFeed {
+        count: u64,
+        backwards: bool,
+    },
+    /// Fold the object or array on the jump target, or unfold it.
+    ToggleFold,
+    /// Fold the objects and arrays deeper than this level, or toggle all the folds if None.
+    FoldLevel(Option<u64>),
+    /// Switch between showing Markdown rendered and as it is.
+    ToggleMarkdown,
+    /// Switch between the text and its hex dump.
+    ToggleHex,
+    /// Show or hide the tabs, trailing spaces and line endings.
+    ToggleWhitespace,
+    /// Wrap the long lines, or scroll them horizontally.
+    ToggleWrap,
+    /// Show the next file given on the command line.
+    NextFile,
+    /// Show the previous file given on the command line.
+    PreviousFile,
+    /// Open the file on the jump target of a directory listing, or else move down this many
+    /// lines.
+    Open(u64),
+    /// Open this file, adding it to the ones given on the command line.
+    Examine(PathBuf),
+    /// Keep showing the end of the file as it grows, until a key is pressed.
+    Follow,
+    /// Time to look at the file again: it's followed, or still coming.
+    Tick,
+    /// Read the file again, or run the --exec command again.
+    ReloadFile,
+    /// Show the keys, until quit.
+    Help,
+    /// Only show the lines matching this pattern. Empty removes the filters.
+    Filter(Pattern),
+    /// Go to the next line matching the pattern. Empty repeats the last search.
+    Search {
+        pattern: Pattern,
+        backwards: bool,
+    },
+    /// Repeat the last search, in the same direction, this many times.
+    SearchNext(u64),
+    /// Repeat the last search, in the opposite direction, this many times.
+    SearchPrevious(u64),
+    /// The big search with this id searched this percent of the text.
+    SearchProgress(u64, u8),
+    /// The big search with this id is done: where the line found starts, if any.
+    SearchDone(u64, Option<usize>),
+    /// Show this text in the prompt line, or hide the prompt if None.
+    Prompt(Option<PromptLine>),
+}
+
+impl Message {
+    /// The scroll and the next one as a single scroll, if they go the same way: a held key
+    /// sends them faster than they're shown.
+    pub(crate) fn merge(&self, next: &Message) -> Option<Message> {
+        Some(match (self, next) {
+            (Message::ScrollDown(count), Message::ScrollDown(next)) => {
+                Message::ScrollDown(count.saturating_add(*next))
+            }
+            (Message::ScrollUp(count), Message::ScrollUp(next)) => {
+                Message::ScrollUp(count.saturating_add(*next))
+            }
+            (Message::ScrollDownPage(count), Message::ScrollDownPage(next)) => {
+                Message::ScrollDownPage(count.saturating_add(*next))
+            }
+            (Message::ScrollUpPage(count), Message::ScrollUpPage(next)) => {
+                Message::ScrollUpPage(count.saturating_add(*next))
+            }
+            (Message::ScrollLeft(count), Message::ScrollLeft(next)) => {
+                Message::ScrollLeft(count.saturating_add(*next))
+            }
+            (Message::ScrollRight(count), Message::ScrollRight(next)) => {
+                Message::ScrollRight(count.saturating_add(*next))
+            }
+            _ => return None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lesser::formats::Message;
+
+    #[test]
+    fn test_merge() {
+        assert!(matches!(
+            Message::ScrollDown(1).merge(&Message::ScrollDown(2)),
+            Some(Message::ScrollDown(3))
+        ));
+        assert!(matches!(
+            Message::ScrollRight(1).merge(&Message::ScrollRight(1)),
+            Some(Message::ScrollRight(2))
+        ));
+        assert!(Message::ScrollDown(1)
+            .merge(&Message::ScrollUp(1))
+            .is_none());
+        assert!(Message::Tick.merge(&Message::Tick).is_none());
+    }
 }
@@ -0,0 +1,82 @@
+use std::io::{Result, Write};
+
+/// Shown on the rows past the end of the text, like in less and vi.
+const PAST_END: &str = "~";
+
+/// The page on the screen, a row at a time, so that only the rows that changed are written
+/// again: clearing the whole screen for each key makes it flicker, and over ssh every byte
+/// counts.
+#[derive(Debug, Default)]
+pub(crate) struct Frame {
+    /// The rows written last, as they were written. Empty when what's on the screen isn't
+    /// known.
+    rows: Vec<String>,
+    /// The columns and rows of the screen they were written to.
+    size: (u16, u16),
+}
+
+impl Frame {
+    /// What's on the screen isn't known anymore, like after a resize: the next page is
+    /// written all again.
+    pub(crate) fn invalidate(&mut self) {
+        self.rows.clear();
+    }
+
+    /// Writes page, its rows separated by "\n\r", over the page on the screen of size. Each
+    /// row clears what's left after it, instead of clearing the screen first: that flashes on
+    /// slow terminals. A shorter page ends the text: the rows after it, down to the status
+    /// line, show a ~.
+    pub(crate) fn write_page(
+        &mut self,
+        screen: &mut dyn Write,
+        page: &str,
+        size: (u16, u16),
+    ) -> Result<()> {
+        let mut rows: Vec<String> = page.split("\n\r").map(str::to_owned).collect();
+        let page_rows = size.1.saturating_sub(1) as usize;
+        if rows.len() < page_rows {
+            rows.resize(page_rows, PAST_END.to_owned());
+        }
+        let known = !self.rows.is_empty() && self.size == size;
+        for (row, text) in rows.iter().enumerate() {
+            if !known || self.rows.get(row) != Some(text) {
+                write!(
+                    screen,
+                    "{}{}{}",
+                    termion::cursor::Goto(1, row as u16 + 1),
+                    text,
+                    termion::clear::UntilNewline
+                )?;
+            }
+        }
+        self.rows = rows;
+        self.size = size;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lesser::frame::Frame;
+
+    #[test]
+    fn test_write_page() {
+        let mut frame = Frame::default();
+        let mut screen = Vec::new();
+        frame
+            .write_page(&mut screen, "a\n\rb\n\rc", (80, 4))
+            .unwrap();
+        assert_eq!(screen, b"\x1b[1;1Ha\x1b[K\x1b[2;1Hb\x1b[K\x1b[3;1Hc\x1b[K");
+        // Only the second row changed, and the text ends before the third.
+        let mut screen = Vec::new();
+        frame.write_page(&mut screen, "a\n\rB", (80, 4)).unwrap();
+        assert_eq!(screen, b"\x1b[2;1HB\x1b[K\x1b[3;1H~\x1b[K");
+        let mut screen = Vec::new();
+        frame.write_page(&mut screen, "a\n\rB", (80, 4)).unwrap();
+        assert!(screen.is_empty());
+        // Resized: the third row could have anything.
+        let mut screen = Vec::new();
+        frame.write_page(&mut screen, "a\n\rB", (100, 4)).unwrap();
+        assert_eq!(screen, b"\x1b[1;1Ha\x1b[K\x1b[2;1HB\x1b[K\x1b[3;1H~\x1b[K");
+    }
+}
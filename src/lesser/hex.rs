@@ -0,0 +1,89 @@
+/// How many bytes a row of the hex dump shows.
+pub(crate) const HEX_ROW_BYTES: usize = 16;
+
+/// How much of the beginning of a file is checked to tell if it's binary.
+pub(crate) const BINARY_CHECK_BYTES: usize = 4096;
+
+/// If text looks like a binary file rather than text: it has NUL bytes, or many control
+/// chars or bytes that are not UTF-8 near the beginning. Tabs, form feeds, escapes (colors)
+/// and backspaces (man pages) are text.
+pub(crate) fn looks_binary(text: &[u8]) -> bool {
+    let text = &text[..text.len().min(BINARY_CHECK_BYTES)];
+    if text.contains(&0) {
+        return true;
+    }
+    let mut not_text = text
+        .iter()
+        .filter(|byte| byte.is_ascii_control() && !b"\t\n\r\x0c\x1b\x08".contains(byte))
+        .count();
+    let mut rest = text;
+    while let Err(error) = std::str::from_utf8(rest) {
+        // None if the last char was cut by BINARY_CHECK_BYTES.
+        let invalid = match error.error_len() {
+            Some(invalid) => invalid,
+            None => break,
+        };
+        not_text += invalid;
+        rest = &rest[error.valid_up_to() + invalid..];
+    }
+    not_text * 10 > text.len()
+}
+
+/// A row of the hex dump, like xxd's: the offset of the first byte, the bytes in hex and as
+/// text, with `.` for the ones that are not printable.
+pub(crate) fn hex_row(offset: usize, bytes: &[u8]) -> String {
+    let hex: Vec<String> = bytes
+        .chunks(2)
+        .map(|pair| pair.iter().map(|byte| format!("{:02x}", byte)).collect())
+        .collect();
+    let text: String = bytes
+        .iter()
+        .map(|byte| match byte {
+            b' '..=b'~' => *byte as char,
+            _ => '.',
+        })
+        .collect();
+    // Short rows are padded, so their text lines up with the other rows.
+    let hex_width = HEX_ROW_BYTES * 2 + HEX_ROW_BYTES / 2 - 1;
+    format!(
+        "{:08x}: {:<width$}  {}",
+        offset,
+        hex.join(" "),
+        text,
+        width = hex_width
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lesser::hex::{hex_row, looks_binary};
+
+    #[test]
+    fn test_hex_row() {
+        assert_eq!(
+            hex_row(
+                0,
+                b"\x7fELF\x02\x01\x01\x00\x00\x00\x00\x00\x00\x00\x00\x00"
+            ),
+            "00000000: 7f45 4c46 0201 0100 0000 0000 0000 0000  .ELF............"
+        );
+        assert_eq!(
+            hex_row(32, b"hi!\n"),
+            "00000020: 6869 210a                                hi!."
+        );
+    }
+
+    #[test]
+    fn test_looks_binary() {
+        assert!(!looks_binary(
+            "plain text, àccents\tand \x1b[1mcolors\x1b[0m\n".as_bytes()
+        ));
+        assert!(looks_binary(b"\x7fELF\x02\x01\x01\x00"));
+        assert!(looks_binary(b"\xff\xfe\x01\x02abcdefgh"));
+        assert!(!looks_binary(b"almost all text \x01"));
+        // A char cut at the end of the part checked is still text.
+        let mut text = "a".repeat(4095).into_bytes();
+        text.extend("é".as_bytes());
+        assert!(!looks_binary(&text));
+    }
+}
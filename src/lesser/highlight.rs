@@ -0,0 +1,290 @@
+use crate::lesser::ansi::parse_style;
+use crate::lesser::json::highlight_json_line;
+use crate::lesser::markdown::MarkdownRenderer;
+use regex::Regex;
+use std::borrow::Cow;
+use std::cmp::min;
+use std::path::Path;
+use syntect::highlighting::{self, HighlightIterator, HighlightState, Theme, ThemeSet};
+use syntect::parsing::{ParseState, ScopeStack, SyntaxSet};
+use syntect::util::as_24_bit_terminal_escaped;
+
+/// The parser state is saved every this many lines, so going back in the file doesn't need to
+/// parse it from the beginning again.
+const CHECKPOINT_LINES: usize = 256;
+
+const THEME: &str = "base16-ocean.dark";
+
+/// How the lines are colored.
+pub(crate) enum Highlighter {
+    /// By the syntax of the file type.
+    Syntax(SyntaxHighlighter),
+    /// By the log level of each line.
+    LogLevels(Vec<LogLevel>),
+    /// Added and removed lines of a unified diff.
+    Diff,
+    /// Keys and values of pretty printed JSON.
+    Json,
+    /// Markdown, with styles in place of the markup.
+    Markdown(MarkdownRenderer),
+}
+
+impl Highlighter {
+    /// The text of line with the color escape sequences. text_of gives the text of a line,
+    /// the ones before line may be needed too.
+    pub(crate) fn highlight<'a>(
+        &mut self,
+        line: usize,
+        text_of: impl Fn(usize) -> Cow<'a, str>,
+    ) -> String {
+        match self {
+            Highlighter::Syntax(highlighter) => highlighter.highlight(line, text_of),
+            Highlighter::LogLevels(levels) => highlight_log_line(levels, &text_of(line)),
+            Highlighter::Diff => highlight_diff_line(&text_of(line)),
+            Highlighter::Json => highlight_json_line(&text_of(line)),
+            Highlighter::Markdown(renderer) => renderer.highlight(line, text_of),
+        }
+    }
+
+    /// The text of a line with the color escape sequences, if they don't depend on the lines
+    /// before it.
+    pub(crate) fn highlight_alone(&self, line: &str) -> Option<String> {
+        match self {
+            Highlighter::LogLevels(levels) => Some(highlight_log_line(levels, line)),
+            Highlighter::Diff => Some(highlight_diff_line(line)),
+            Highlighter::Json => Some(highlight_json_line(line)),
+            Highlighter::Syntax(_) | Highlighter::Markdown(_) => None,
+        }
+    }
+}
+
+/// If text, the beginning of a file, looks like a unified diff (e.g. the output of git diff).
+pub(crate) fn looks_like_diff(text: &str) -> bool {
+    ["diff ", "--- ", "Index: ", "From "]
+        .iter()
+        .any(|start| text.starts_with(start))
+}
+
+/// Colors the added lines green, the removed ones red and the hunk headers cyan. The headers
+/// of the files are bold.
+fn highlight_diff_line(line: &str) -> String {
+    let style = if line.starts_with("+++ ")
+        || line.starts_with("--- ")
+        || line.starts_with("diff ")
+        || line.starts_with("index ")
+    {
+        "\x1b[1m"
+    } else if line.starts_with("@@") {
+        "\x1b[36m"
+    } else if line.starts_with('+') {
+        "\x1b[32m"
+    } else if line.starts_with('-') {
+        "\x1b[31m"
+    } else {
+        return line.to_owned();
+    };
+    format!("{}{}", style, line)
+}
+
+/// A log level, like ERROR, and how the lines with it are colored.
+#[derive(Debug, Clone)]
+pub(crate) struct LogLevel {
+    /// The pattern, as written in the config file.
+    pub(crate) name: String,
+    pub(crate) pattern: Regex,
+    /// The color escape sequence.
+    pub(crate) style: String,
+}
+
+/// The levels colored by default, with their style name.
+const LOG_LEVELS: &[(&str, &str)] = &[
+    ("ERROR", "bold red"),
+    ("WARN", "yellow"),
+    ("INFO", "green"),
+    ("DEBUG", "blue"),
+    ("TRACE", "bright-black"),
+];
+
+pub(crate) fn default_log_levels() -> Vec<LogLevel> {
+    LOG_LEVELS
+        .iter()
+        .map(|(name, style)| LogLevel {
+            name: (*name).to_owned(),
+            pattern: Regex::new(name).expect("Valid log level pattern"),
+            style: parse_style(style).expect("Valid log level style"),
+        })
+        .collect()
+}
+
+/// If text has a log level, so the file is probably a log.
+pub(crate) fn has_log_level(levels: &[LogLevel], text: &str) -> bool {
+    levels.iter().any(|level| level.pattern.is_match(text))
+}
+
+/// Colors the line with the style of the level found first in it.
+fn highlight_log_line(levels: &[LogLevel], line: &str) -> String {
+    let level = levels
+        .iter()
+        .filter_map(|level| Some((level.pattern.find(line)?.start(), level)))
+        .min_by_key(|(start, _level)| *start);
+    match level {
+        Some((_start, level)) => format!("{}{}", level.style, line),
+        None => line.to_owned(),
+    }
+}
+
+/// Where the parser and the highlighter are at the start of a line.
+#[derive(Clone)]
+struct LineState {
+    parse: ParseState,
+    highlight: HighlightState,
+}
+
+/// Colors the lines of a file by its syntax. A line's colors depend on the ones before it (e.g.
+/// in a multi-line comment), so they're parsed in order.
+pub(crate) struct SyntaxHighlighter {
+    syntax_set: SyntaxSet,
+    theme: Theme,
+    /// The state at the start of each CHECKPOINT_LINES-th line, starting from line 0.
+    checkpoints: Vec<LineState>,
+    /// The state after the last highlighted line, and the next line.
+    current: Option<(usize, LineState)>,
+}
+
+impl SyntaxHighlighter {
+    /// Finds the file type by the name of the file (its extension), or by its first line (like
+    /// a `#!/bin/sh`). None if it's not known.
+    pub(crate) fn for_file(path: Option<&Path>, first_line: &str) -> Option<Self> {
+        let syntax_set = SyntaxSet::load_defaults_nonewlines();
+        let by_name = path.and_then(|path| syntax_set.find_syntax_for_file(path).ok().flatten());
+        let syntax = by_name.or_else(|| syntax_set.find_syntax_by_first_line(first_line))?;
+        debug!("Highlighting as {}", syntax.name);
+        let theme = ThemeSet::load_defaults().themes.remove(THEME)?;
+        let highlighter = highlighting::Highlighter::new(&theme);
+        let first_state = LineState {
+            parse: ParseState::new(syntax),
+            highlight: HighlightState::new(&highlighter, ScopeStack::new()),
+        };
+        Some(SyntaxHighlighter {
+            syntax_set,
+            theme,
+            checkpoints: vec![first_state],
+            current: None,
+        })
+    }
+
+    /// The text of line with the color escape sequences. text_of gives the text of a line,
+    /// the ones before line are needed too.
+    pub(crate) fn highlight<'a>(
+        &mut self,
+        line: usize,
+        text_of: impl Fn(usize) -> Cow<'a, str>,
+    ) -> String {
+        // The last checkpoint before line that was saved.
+        let checkpoint = min(line / CHECKPOINT_LINES, self.checkpoints.len() - 1);
+        let (mut at, mut state) = match self.current.take() {
+            Some((at, state)) if at <= line && at >= checkpoint * CHECKPOINT_LINES => (at, state),
+            _ => (
+                checkpoint * CHECKPOINT_LINES,
+                self.checkpoints[checkpoint].clone(),
+            ),
+        };
+        while at < line {
+            self.highlight_line(&mut state, &text_of(at));
+            at += 1;
+            self.save_checkpoint(at, &state);
+        }
+        let highlighted = self.highlight_line(&mut state, &text_of(line));
+        self.save_checkpoint(line + 1, &state);
+        self.current = Some((line + 1, state));
+        highlighted
+    }
+
+    fn highlight_line(&self, state: &mut LineState, text: &str) -> String {
+        let highlighter = highlighting::Highlighter::new(&self.theme);
+        let ops = state.parse.parse_line(text, &self.syntax_set);
+        let ranges: Vec<_> =
+            HighlightIterator::new(&mut state.highlight, &ops, text, &highlighter).collect();
+        as_24_bit_terminal_escaped(&ranges, false)
+    }
+
+    fn save_checkpoint(&mut self, line: usize, state: &LineState) {
+        // Checkpoints are saved in order, so the next one is at the end.
+        if line == self.checkpoints.len() * CHECKPOINT_LINES {
+            self.checkpoints.push(state.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lesser::highlight::{
+        default_log_levels, looks_like_diff, Highlighter, SyntaxHighlighter,
+    };
+    use std::borrow::Cow;
+    use std::path::Path;
+
+    #[test]
+    fn test_highlight() {
+        assert!(SyntaxHighlighter::for_file(Some(Path::new("notes.unknown")), "hi").is_none());
+        assert!(SyntaxHighlighter::for_file(None, "#!/bin/sh").is_some());
+
+        let lines = ["fn main() {", "    let x = 1;", "}"];
+        let mut highlighter =
+            SyntaxHighlighter::for_file(Some(Path::new("main.rs")), lines[0]).unwrap();
+        let text_of = |line: usize| Cow::Borrowed(lines[line]);
+        // Out of order, the lines before are parsed again.
+        let last = highlighter.highlight(2, text_of);
+        let first = highlighter.highlight(0, text_of);
+        assert!(last.contains("\x1b[38;2;") && last.contains('}'));
+        assert!(first.contains("main"));
+    }
+
+    #[test]
+    fn test_log_levels() {
+        let lines = [
+            "2020-01-01 INFO started",
+            "2020-01-01 ERROR INFO lost",
+            "plain",
+        ];
+        let mut highlighter = Highlighter::LogLevels(default_log_levels());
+        let text_of = |line: usize| Cow::Borrowed(lines[line]);
+        assert_eq!(
+            highlighter.highlight(0, text_of),
+            "\x1b[32m2020-01-01 INFO started"
+        );
+        // The first level in the line wins.
+        assert!(highlighter.highlight(1, text_of).starts_with("\x1b[1;31m"));
+        assert_eq!(highlighter.highlight(2, text_of), "plain");
+    }
+
+    #[test]
+    fn test_diff() {
+        let lines = [
+            "diff --git a/x b/x",
+            "--- a/x",
+            "@@ -1,2 +1,2 @@",
+            "-old",
+            "+new",
+            " same",
+        ];
+        assert!(looks_like_diff(lines[0]));
+        assert!(!looks_like_diff(lines[5]));
+        let mut highlighter = Highlighter::Diff;
+        let text_of = |line: usize| Cow::Borrowed(lines[line]);
+        let styles: Vec<String> = (0..lines.len())
+            .map(|line| highlighter.highlight(line, text_of))
+            .collect();
+        assert_eq!(
+            styles,
+            [
+                "\x1b[1mdiff --git a/x b/x",
+                "\x1b[1m--- a/x",
+                "\x1b[36m@@ -1,2 +1,2 @@",
+                "\x1b[31m-old",
+                "\x1b[32m+new",
+                " same",
+            ]
+        );
+    }
+}
@@ -0,0 +1,147 @@
+use crate::lesser::line_index::LineIndex;
+use crate::lesser::text::Text;
+use std::collections::hash_map::DefaultHasher;
+use std::fs::{self, Metadata};
+use std::hash::{Hash, Hasher};
+use std::io::{ErrorKind, Result};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::UNIX_EPOCH;
+
+/// Smaller files are indexed again: it takes no time.
+const CACHED_MIN: u64 = 64 << 20;
+
+/// The line index of path, of size bytes and lines ending with newline, saved the last time it
+/// was opened. None if it's not there, or if the file changed since.
+pub(crate) fn load(path: &Path, size: u64, text: Arc<Text>, newline: u8) -> Option<LineIndex> {
+    if size < CACHED_MIN {
+        return None;
+    }
+    let metadata = fs::metadata(path).ok()?;
+    if metadata.len() != size {
+        return None;
+    }
+    let path = &fs::canonicalize(path).ok()?;
+    let bytes = match fs::read(cache_path(path)?) {
+        Ok(bytes) => bytes,
+        Err(error) => {
+            if error.kind() != ErrorKind::NotFound {
+                debug!("Can't read the line index of {}: {}", path.display(), error);
+            }
+            return None;
+        }
+    };
+    let index = bytes
+        .strip_prefix(header(path, &metadata)?.as_bytes())
+        .and_then(|index| LineIndex::from_bytes(text, newline, index));
+    if index.is_none() {
+        debug!("The line index of {} is of another file", path.display());
+    }
+    index
+}
+
+/// Saves the line index of path, of size bytes, for the next time it's opened.
+pub(crate) fn save(path: &Path, size: u64, index: &LineIndex) {
+    if size < CACHED_MIN {
+        return;
+    }
+    if let Err(error) = write(path, size, index) {
+        debug!("Can't save the line index of {}: {}", path.display(), error);
+    }
+}
+
+fn write(path: &Path, size: u64, index: &LineIndex) -> Result<()> {
+    let metadata = fs::metadata(path)?;
+    // It changed since it was indexed.
+    if metadata.len() != size {
+        return Ok(());
+    }
+    let path = &fs::canonicalize(path)?;
+    let (cache_path, header) = match (cache_path(path), header(path, &metadata)) {
+        (Some(cache_path), Some(header)) => (cache_path, header),
+        _ => return Ok(()),
+    };
+    if let Some(dir) = cache_path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut bytes = header.into_bytes();
+    bytes.extend(index.to_bytes());
+    // Renamed when it's all written, so another lesser never reads half of it.
+    let partial = cache_path.with_extension("partial");
+    fs::write(&partial, bytes)?;
+    fs::rename(partial, cache_path)
+}
+
+/// What the index is of: `lesser index`, then the full path of the file, its size and when it
+/// was last changed, a line each.
+fn header(path: &Path, metadata: &Metadata) -> Option<String> {
+    let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    Some(format!(
+        "lesser index\n{}\n{} {}.{:09}\n",
+        path.display(),
+        metadata.len(),
+        modified.as_secs(),
+        modified.subsec_nanos()
+    ))
+}
+
+/// The index of path, a full path, is in a file named by its hash.
+fn cache_path(path: &Path) -> Option<PathBuf> {
+    let mut hasher = DefaultHasher::new();
+    path.hash(&mut hasher);
+    Some(cache_dir()?.join(format!("{:016x}", hasher.finish())))
+}
+
+/// `$XDG_CACHE_HOME/lesser/index`, or `~/.cache/lesser/index`.
+fn cache_dir() -> Option<PathBuf> {
+    let cache_dir = match std::env::var_os("XDG_CACHE_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".cache"),
+    };
+    Some(cache_dir.join("lesser").join("index"))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lesser::index_cache::{load, save, CACHED_MIN};
+    use crate::lesser::line_index::LineIndex;
+    use crate::lesser::text::Text;
+    use std::fs::File;
+    use std::io::Write;
+    use std::sync::Arc;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn test_save_and_load() {
+        let dir = tempdir::TempDir::new("lesser").unwrap();
+        std::env::set_var("XDG_CACHE_HOME", dir.path().join("cache"));
+        let path = dir.path().join("big.log");
+        let mut file = File::create(&path).unwrap();
+        file.write_all(b"a\nb\n").unwrap();
+        // Sparse, it takes no room.
+        file.set_len(CACHED_MIN).unwrap();
+        let text = Arc::new(Text::map(&File::open(&path).unwrap()).unwrap());
+        let mut index = LineIndex::lines(Arc::clone(&text), b'\n');
+        for end in [1, 3, CACHED_MIN as usize] {
+            index.push(end);
+        }
+        let lines: Vec<_> = index.iter().collect();
+        save(&path, CACHED_MIN, &index);
+        // Found by the full path, however it's named.
+        let other_name = dir.path().join(".").join("big.log");
+        let loaded = load(&other_name, CACHED_MIN, Arc::clone(&text), b'\n').unwrap();
+        assert_eq!(loaded.iter().collect::<Vec<_>>(), lines);
+        assert!(load(&path, CACHED_MIN, Arc::clone(&text), b'\r').is_none());
+
+        // Changed since.
+        file.set_modified(UNIX_EPOCH + Duration::from_secs(1))
+            .unwrap();
+        assert!(load(&path, CACHED_MIN, Arc::clone(&text), b'\n').is_none());
+        save(&path, CACHED_MIN, &index);
+        assert!(load(&path, CACHED_MIN, Arc::clone(&text), b'\n').is_some());
+        file.set_len(CACHED_MIN + 1).unwrap();
+        file.set_modified(UNIX_EPOCH + Duration::from_secs(1))
+            .unwrap();
+        assert!(load(&path, CACHED_MIN + 1, text, b'\n').is_none());
+    }
+}
@@ -0,0 +1,122 @@
+use crate::lesser::text::Text;
+use crossbeam_channel::{Receiver, Sender};
+use memchr::memchr_iter;
+use std::cmp::{max, min};
+use std::sync::Arc;
+use std::thread;
+
+/// How many bytes are looked at a time for the newlines, between looks at what's asked.
+const SCAN_CHUNK: usize = 1 << 20;
+/// How many lines past the last one asked for are indexed, so the next pages are ready.
+const INDEX_AHEAD: usize = 10_000;
+
+/// Finds where the lines of a text start and end in a thread of its own, ahead of the lines
+/// asked for: paging into a part of a big file not indexed yet doesn't freeze the screen.
+pub(crate) struct Indexer {
+    /// How many lines are needed, counting the ones indexed before.
+    requests: Sender<usize>,
+    /// The end of the lines found, in order. Disconnected when the text is all indexed.
+    lines: Receiver<Vec<usize>>,
+}
+
+impl Indexer {
+    /// Indexes the lines of text ending with newline from from, the start of a line, after the
+    /// first lines lines. It stops when the indexer is dropped.
+    pub(crate) fn spawn(text: Arc<Text>, newline: u8, from: usize, lines: usize) -> Indexer {
+        let (requests, requested) = crossbeam_channel::unbounded();
+        let (found, found_lines) = crossbeam_channel::unbounded();
+        thread::spawn(move || index(&text, newline, from, lines, requested, found));
+        Indexer {
+            requests,
+            lines: found_lines,
+        }
+    }
+
+    /// Asks for the first lines lines, and more after them.
+    pub(crate) fn request(&self, lines: usize) {
+        // The text could be all indexed already.
+        let _ = self.requests.send(lines);
+    }
+
+    /// The next lines found, waiting for them if wait. None if there are none yet, or the
+    /// text is all indexed.
+    pub(crate) fn next_lines(&self, wait: bool) -> Option<Vec<usize>> {
+        if wait {
+            self.lines.recv().ok()
+        } else {
+            self.lines.try_recv().ok()
+        }
+    }
+}
+
+fn index(
+    text: &Text,
+    newline: u8,
+    mut at: usize,
+    mut lines: usize,
+    requested: Receiver<usize>,
+    found: Sender<Vec<usize>>,
+) {
+    let len = text.len();
+    let mut wanted = 0;
+    // Left side, is inclusive.
+    let mut start = at;
+    while at < len {
+        // Far enough ahead: waits to be asked for more.
+        while lines >= wanted {
+            match requested.recv() {
+                Ok(request) => wanted = max(wanted, request.saturating_add(INDEX_AHEAD)),
+                Err(_) => return,
+            }
+        }
+        for request in requested.try_iter() {
+            wanted = max(wanted, request.saturating_add(INDEX_AHEAD));
+        }
+        // The pages a truncation took away can't be read: the pager shows the file again.
+        if text.shrunk() {
+            return;
+        }
+        let end = min(at.saturating_add(SCAN_CHUNK), len);
+        let mut chunk_lines = Vec::new();
+        // With SIMD, it's an order of magnitude faster than looking at each byte.
+        for line_end in memchr_iter(newline, &text.bytes(at..end)) {
+            chunk_lines.push(at + line_end);
+            start = at + line_end + 1;
+        }
+        at = end;
+        // The last line, if it has no newline.
+        if at == len && start < len {
+            chunk_lines.push(len);
+        }
+        lines += chunk_lines.len();
+        if found.send(chunk_lines).is_err() {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lesser::indexer::Indexer;
+    use crate::lesser::text::Text;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_indexer() {
+        let text = Arc::new(Text::from(b"a\n\nbc\nd".to_vec()));
+        let indexer = Indexer::spawn(text, b'\n', 0, 0);
+        indexer.request(1);
+        let mut lines = Vec::new();
+        while let Some(found) = indexer.next_lines(true) {
+            lines.extend(found);
+        }
+        assert_eq!(lines, vec![1, 2, 5, 7]);
+
+        // From the middle, after the first two lines.
+        let text = Arc::new(Text::from(b"a\n\nbc\n".to_vec()));
+        let indexer = Indexer::spawn(text, b'\n', 3, 2);
+        indexer.request(3);
+        assert_eq!(indexer.next_lines(true), Some(vec![5]));
+        assert_eq!(indexer.next_lines(true), None);
+    }
+}
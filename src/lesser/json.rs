@@ -0,0 +1,313 @@
+use crate::lesser::reader::Block;
+use regex::Regex;
+
+/// Indentation of each level.
+const INDENT: &str = "  ";
+/// Deeper JSON is not pretty printed: the parser is recursive.
+const MAX_DEPTH: usize = 512;
+
+const KEY_STYLE: &str = "\x1b[1;34m";
+const STRING_STYLE: &str = "\x1b[32m";
+const NUMBER_STYLE: &str = "\x1b[36m";
+const LITERAL_STYLE: &str = "\x1b[35m";
+const RESET: &str = "\x1b[0m";
+
+/// JSON written one value per line, indented, and where its objects and arrays are.
+#[derive(Debug)]
+pub(crate) struct PrettyJson {
+    pub(crate) text: String,
+    /// The objects and arrays taking more than one line, which can be folded.
+    pub(crate) blocks: Vec<Block>,
+}
+
+/// If text could be JSON: it starts with an object or an array.
+pub(crate) fn looks_like_json(text: &[u8]) -> bool {
+    matches!(
+        text.iter().find(|c| !c.is_ascii_whitespace()),
+        Some(b'{') | Some(b'[')
+    )
+}
+
+/// Indents json like `jq .` does. A file with more than one value, like JSON Lines, has them
+/// one after the other. The error says what's wrong and where.
+pub(crate) fn pretty_print(json: &[u8]) -> Result<PrettyJson, String> {
+    let mut printer = Printer {
+        json,
+        at: 0,
+        lines: Vec::new(),
+        blocks: Vec::new(),
+        number: Regex::new(r"^-?(0|[1-9][0-9]*)(\.[0-9]+)?([eE][+-]?[0-9]+)?$")
+            .expect("Valid number pattern"),
+    };
+    if printer.peek().is_none() {
+        return Err("Empty JSON".to_owned());
+    }
+    while printer.peek().is_some() {
+        printer.value(String::new(), 0)?;
+    }
+    printer.blocks.sort_by_key(|block| block.first_line);
+    Ok(PrettyJson {
+        text: printer.lines.join("\n"),
+        blocks: printer.blocks,
+    })
+}
+
+struct Printer<'a> {
+    json: &'a [u8],
+    /// Where the parser is in json.
+    at: usize,
+    lines: Vec<String>,
+    blocks: Vec<Block>,
+    number: Regex,
+}
+
+impl<'a> Printer<'a> {
+    /// The next char that is not whitespace, without taking it.
+    fn peek(&mut self) -> Option<u8> {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.json.get(self.at) {
+            self.at += 1;
+        }
+        self.json.get(self.at).copied()
+    }
+
+    /// Adds the lines of the value at depth. The first one starts with prefix: the
+    /// indentation, and the key in objects.
+    fn value(&mut self, prefix: String, depth: usize) -> Result<(), String> {
+        match self.peek() {
+            Some(open @ (b'{' | b'[')) => self.block(open, prefix, depth),
+            Some(b'"') => {
+                let string = self.string()?;
+                self.lines.push(prefix + string);
+                Ok(())
+            }
+            Some(_) => {
+                let start = self.at;
+                while let Some(b'a'..=b'z' | b'0'..=b'9' | b'-' | b'+' | b'.' | b'E') =
+                    self.json.get(self.at)
+                {
+                    self.at += 1;
+                }
+                let word = self.text(start, self.at);
+                if word.is_empty() {
+                    return Err(self.error("Expected a value"));
+                }
+                if !matches!(word, "true" | "false" | "null") && !self.number.is_match(word) {
+                    self.at = start;
+                    return Err(self.error("Invalid value"));
+                }
+                self.lines.push(prefix + word);
+                Ok(())
+            }
+            None => Err(self.error("Expected a value")),
+        }
+    }
+
+    /// An object or an array, opened by open.
+    fn block(&mut self, open: u8, prefix: String, depth: usize) -> Result<(), String> {
+        if depth >= MAX_DEPTH {
+            return Err(self.error("Too deep"));
+        }
+        let close = if open == b'{' { b'}' } else { b']' };
+        self.at += 1;
+        if self.peek() == Some(close) {
+            self.at += 1;
+            self.lines
+                .push(format!("{}{}{}", prefix, open as char, close as char));
+            return Ok(());
+        }
+        let first_line = self.lines.len();
+        self.lines.push(format!("{}{}", prefix, open as char));
+        let indent = INDENT.repeat(depth + 1);
+        loop {
+            let prefix = if open == b'{' {
+                if self.peek() != Some(b'"') {
+                    return Err(self.error("Expected a key"));
+                }
+                let key = self.string()?;
+                if self.peek() != Some(b':') {
+                    return Err(self.error("Expected ':'"));
+                }
+                self.at += 1;
+                format!("{}{}: ", indent, key)
+            } else {
+                indent.clone()
+            };
+            self.value(prefix, depth + 1)?;
+            match self.peek() {
+                Some(b',') => {
+                    self.at += 1;
+                    if let Some(last) = self.lines.last_mut() {
+                        last.push(',');
+                    }
+                }
+                Some(c) if c == close => {
+                    self.at += 1;
+                    break;
+                }
+                _ => return Err(self.error(&format!("Expected ',' or '{}'", close as char))),
+            }
+        }
+        self.lines
+            .push(format!("{}{}", INDENT.repeat(depth), close as char));
+        self.blocks.push(Block {
+            first_line,
+            last_line: self.lines.len() - 1,
+            depth,
+        });
+        Ok(())
+    }
+
+    /// The string starting at the current char, quotes included.
+    fn string(&mut self) -> Result<&'a str, String> {
+        let start = self.at;
+        self.at += 1;
+        loop {
+            match self.json.get(self.at) {
+                Some(b'"') => break,
+                Some(b'\\') => self.at += 2,
+                Some(0..=0x1f) | None => return Err(self.error("Unterminated string")),
+                Some(_) => self.at += 1,
+            }
+        }
+        self.at += 1;
+        Ok(self.text(start, self.at))
+    }
+
+    fn text(&self, start: usize, end: usize) -> &'a str {
+        std::str::from_utf8(&self.json[start..end]).unwrap_or("\u{fffd}")
+    }
+
+    /// What went wrong at the current char.
+    fn error(&self, what: &str) -> String {
+        let before = &self.json[..self.at];
+        let line = before.iter().filter(|c| **c == b'\n').count() + 1;
+        let col = before.iter().rev().take_while(|c| **c != b'\n').count() + 1;
+        format!("{} at line {} column {}", what, line, col)
+    }
+}
+
+/// Colors a line written by pretty_print: keys, strings, numbers and true, false and null.
+pub(crate) fn highlight_json_line(line: &str) -> String {
+    let mut highlighted = String::new();
+    let mut rest = line;
+    while let Some(c) = rest.chars().next() {
+        let len = match c {
+            '"' => string_len(rest),
+            '-' | '0'..='9' | 'a'..='z' => rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || "+-.".contains(c)))
+                .unwrap_or(rest.len()),
+            _ => {
+                highlighted.push(c);
+                rest = &rest[c.len_utf8()..];
+                continue;
+            }
+        };
+        let (token, after) = rest.split_at(len);
+        let style = match c {
+            '"' if after.starts_with(':') => KEY_STYLE,
+            '"' => STRING_STYLE,
+            'a'..='z' => LITERAL_STYLE,
+            _ => NUMBER_STYLE,
+        };
+        highlighted.push_str(style);
+        highlighted.push_str(token);
+        highlighted.push_str(RESET);
+        rest = after;
+    }
+    highlighted
+}
+
+/// The length of the string at the start of text, quotes included.
+fn string_len(text: &str) -> usize {
+    let mut escaped = false;
+    for (i, c) in text.char_indices().skip(1) {
+        match c {
+            '"' if !escaped => return i + 1,
+            '\\' => escaped = !escaped,
+            _ => escaped = false,
+        }
+    }
+    text.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lesser::json::{highlight_json_line, looks_like_json, pretty_print};
+    use crate::lesser::reader::Block;
+
+    #[test]
+    fn test_pretty_print() {
+        let json = br#"{"name": "a \"b\"", "list": [1, -2.5e3, true, null], "empty": {},
+            "nested": {"x": []}}"#;
+        let pretty = pretty_print(json).unwrap();
+        assert_eq!(
+            pretty.text,
+            r#"{
+  "name": "a \"b\"",
+  "list": [
+    1,
+    -2.5e3,
+    true,
+    null
+  ],
+  "empty": {},
+  "nested": {
+    "x": []
+  }
+}"#
+        );
+        assert_eq!(
+            pretty.blocks,
+            [
+                Block {
+                    first_line: 0,
+                    last_line: 12,
+                    depth: 0
+                },
+                Block {
+                    first_line: 2,
+                    last_line: 7,
+                    depth: 1
+                },
+                Block {
+                    first_line: 9,
+                    last_line: 11,
+                    depth: 1
+                },
+            ]
+        );
+        // JSON Lines.
+        assert_eq!(pretty_print(b"1\n[2]\n").unwrap().text, "1\n[\n  2\n]");
+
+        assert!(looks_like_json(b"  {\"a\": 1}"));
+        assert!(!looks_like_json(b"plain"));
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert_eq!(
+            pretty_print(b"{\"a\": 1,\n \"b\" 2}").unwrap_err(),
+            "Expected ':' at line 2 column 6"
+        );
+        assert_eq!(
+            pretty_print(b"[01]").unwrap_err(),
+            "Invalid value at line 1 column 2"
+        );
+        assert!(pretty_print(b"[\"open").is_err());
+        assert!(pretty_print(b"[1 2]").is_err());
+        assert!(pretty_print(b"  ").is_err());
+        assert!(pretty_print(&b"[".repeat(1000)).is_err());
+    }
+
+    #[test]
+    fn test_highlight_json_line() {
+        assert_eq!(
+            highlight_json_line(r#"  "k\"": "v","#),
+            "  \x1b[1;34m\"k\\\"\"\x1b[0m: \x1b[32m\"v\"\x1b[0m,"
+        );
+        assert_eq!(
+            highlight_json_line("  -1.5, null"),
+            "  \x1b[36m-1.5\x1b[0m, \x1b[35mnull\x1b[0m"
+        );
+    }
+}
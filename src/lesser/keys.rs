@@ -0,0 +1,389 @@
+use std::collections::HashMap;
+use termion::event::Key;
+
+/// What a key does. The names are the ones used in the `[keys]` section of the config file.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Action {
+    Quit,
+    Down,
+    /// Open the file on the jump target, in a directory listing. Down elsewhere.
+    Open,
+    Up,
+    PageDown,
+    PageUp,
+    HalfPageDown,
+    HalfPageUp,
+    Left,
+    Right,
+    /// Go to the beginning, or to the line typed before the key.
+    Top,
+    /// Go to the end, or to the line typed before the key.
+    Bottom,
+    /// Go to the percent of the file typed before the key.
+    Percent,
+    ToggleLineNumbers,
+    SearchNext,
+    SearchPrevious,
+    Filter,
+    Search,
+    SearchBackwards,
+    Command,
+    /// Flip the option whose letter is typed next.
+    ToggleOption,
+    /// Mark the position with the letter typed next.
+    SetMark,
+    /// Go to the mark whose letter is typed next.
+    GotoMark,
+    /// Go back to where the previous jump started, like vim's Ctrl-O.
+    JumpBack,
+    /// Undo a JumpBack, like vim's Ctrl-I.
+    JumpForward,
+    /// Go to the next page, started by a form feed.
+    NextFormFeed,
+    /// Go to the previous page, started by a form feed.
+    PreviousFormFeed,
+    /// Fold or unfold the JSON object or array on the jump target.
+    ToggleFold,
+    /// Fold the JSON deeper than the level typed before the key, or toggle all the folds.
+    FoldLevel,
+    /// Switch between the rendered Markdown and its markup.
+    ToggleMarkdown,
+    /// Switch between the text and its hex dump.
+    ToggleHex,
+    /// Show or hide the tabs, trailing spaces and line endings.
+    ToggleWhitespace,
+    /// Wrap the long lines, or scroll them horizontally.
+    ToggleWrap,
+    /// Keep showing the end of the file as it grows, like `tail -f`.
+    Follow,
+    /// Read the file again, or run the --exec command again.
+    Reload,
+    Help,
+    /// Used to unbind a key.
+    Nothing,
+}
+
+/// The name of each action in the config file, and what it does for the help screen.
+const ACTION_NAMES: &[(&str, Action, &str)] = &[
+    ("quit", Action::Quit, "Exit (or leave the help)."),
+    ("down", Action::Down, "Move down one line."),
+    (
+        "open",
+        Action::Open,
+        "Open the file at the top of a directory listing, or move down one line.",
+    ),
+    ("up", Action::Up, "Move up one line."),
+    ("page-down", Action::PageDown, "Move down one page."),
+    ("page-up", Action::PageUp, "Move up one page."),
+    (
+        "half-page-down",
+        Action::HalfPageDown,
+        "Move down half a page, or N lines from now on.",
+    ),
+    (
+        "half-page-up",
+        Action::HalfPageUp,
+        "Move up half a page, or N lines from now on.",
+    ),
+    ("left", Action::Left, "Move left one column."),
+    ("right", Action::Right, "Move right one column."),
+    ("top", Action::Top, "Go to the beginning, or to line N."),
+    ("bottom", Action::Bottom, "Go to the end, or to line N."),
+    ("percent", Action::Percent, "Go to N percent of the file."),
+    (
+        "toggle-line-numbers",
+        Action::ToggleLineNumbers,
+        "Show or hide the line numbers.",
+    ),
+    ("search-next", Action::SearchNext, "Repeat the last search."),
+    (
+        "search-previous",
+        Action::SearchPrevious,
+        "Repeat the last search, in the opposite direction.",
+    ),
+    (
+        "filter",
+        Action::Filter,
+        "Only show the lines matching a pattern (!pattern hides them).",
+    ),
+    ("search", Action::Search, "Search forward for a pattern."),
+    (
+        "search-backwards",
+        Action::SearchBackwards,
+        "Search backwards for a pattern.",
+    ),
+    (
+        "command",
+        Action::Command,
+        "Run a command, like :42 to go to line 42.",
+    ),
+    (
+        "toggle-option",
+        Action::ToggleOption,
+        "Flip an option, like -N for the line numbers.",
+    ),
+    (
+        "set-mark",
+        Action::SetMark,
+        "Mark the position with the letter typed next.",
+    ),
+    (
+        "goto-mark",
+        Action::GotoMark,
+        "Go to a mark, or ' to go back before the last jump.",
+    ),
+    (
+        "jump-back",
+        Action::JumpBack,
+        "Go back to where the previous jump (goto, search, mark) started.",
+    ),
+    (
+        "jump-forward",
+        Action::JumpForward,
+        "Go forward in the jumps.",
+    ),
+    (
+        "next-form-feed",
+        Action::NextFormFeed,
+        "Go to the next page break (form feed), or the N-th next.",
+    ),
+    (
+        "previous-form-feed",
+        Action::PreviousFormFeed,
+        "Go to the previous page break (form feed), or the N-th previous.",
+    ),
+    (
+        "toggle-fold",
+        Action::ToggleFold,
+        "Fold or unfold the JSON object or array at the top.",
+    ),
+    (
+        "fold-level",
+        Action::FoldLevel,
+        "Fold the JSON deeper than N levels, or fold and unfold everything.",
+    ),
+    (
+        "toggle-markdown",
+        Action::ToggleMarkdown,
+        "Show Markdown rendered, or as it is.",
+    ),
+    (
+        "toggle-hex",
+        Action::ToggleHex,
+        "Show the bytes in hex, like xxd, or the text.",
+    ),
+    (
+        "toggle-whitespace",
+        Action::ToggleWhitespace,
+        "Show or hide the tabs, trailing spaces and line endings.",
+    ),
+    (
+        "toggle-wrap",
+        Action::ToggleWrap,
+        "Wrap the long lines on more rows, or cut them at the edge.",
+    ),
+    (
+        "follow",
+        Action::Follow,
+        "Keep going to the end as the file grows, until a key is pressed.",
+    ),
+    (
+        "reload",
+        Action::Reload,
+        "Read the file again, saying how it changed, or run the --exec command again.",
+    ),
+    ("help", Action::Help, "Show this help."),
+    ("nothing", Action::Nothing, "Do nothing."),
+];
+
+impl Action {
+    pub(crate) fn from_name(name: &str) -> Option<Action> {
+        ACTION_NAMES
+            .iter()
+            .find(|(action_name, _, _)| *action_name == name)
+            .map(|(_, action, _)| *action)
+    }
+}
+
+/// Which action each key does.
+pub type Keymap = HashMap<Key, Action>;
+
+/// The less-like bindings, used for the keys not set in the config file.
+pub(crate) fn default_keymap() -> Keymap {
+    let bindings = [
+        (Key::Char('q'), Action::Quit),
+        (Key::Char('Q'), Action::Quit),
+        (Key::Ctrl('c'), Action::Quit),
+        (Key::PageUp, Action::PageUp),
+        (Key::PageDown, Action::PageDown),
+        (Key::Left, Action::Left),
+        (Key::Down, Action::Down),
+        (Key::Up, Action::Up),
+        (Key::Right, Action::Right),
+        (Key::Char('g'), Action::Top),
+        (Key::Char('<'), Action::Top),
+        (Key::Home, Action::Top),
+        (Key::Char('G'), Action::Bottom),
+        (Key::Char('>'), Action::Bottom),
+        (Key::End, Action::Bottom),
+        (Key::Char('p'), Action::Percent),
+        (Key::Char('%'), Action::Percent),
+        // Enter goes down, or opens a file in a directory listing.
+        (Key::Char('\n'), Action::Open),
+        (Key::Char('e'), Action::Down),
+        (Key::Char('j'), Action::Down),
+        (Key::Ctrl('e'), Action::Down),
+        (Key::Ctrl('n'), Action::Down),
+        (Key::Char('y'), Action::Up),
+        (Key::Char('k'), Action::Up),
+        (Key::Ctrl('y'), Action::Up),
+        (Key::Ctrl('p'), Action::Up),
+        (Key::Char('b'), Action::PageUp),
+        (Key::Ctrl('b'), Action::PageUp),
+        (Key::Char(' '), Action::PageDown),
+        (Key::Char('f'), Action::PageDown),
+        (Key::Ctrl('f'), Action::PageDown),
+        (Key::Char('d'), Action::HalfPageDown),
+        (Key::Ctrl('d'), Action::HalfPageDown),
+        (Key::Char('u'), Action::HalfPageUp),
+        (Key::Ctrl('u'), Action::HalfPageUp),
+        (Key::Char('#'), Action::ToggleLineNumbers),
+        (Key::Char('n'), Action::SearchNext),
+        (Key::Char('N'), Action::SearchPrevious),
+        (Key::Char('&'), Action::Filter),
+        (Key::Char('/'), Action::Search),
+        (Key::Char('?'), Action::SearchBackwards),
+        (Key::Char(':'), Action::Command),
+        (Key::Char('-'), Action::ToggleOption),
+        (Key::Char('m'), Action::SetMark),
+        (Key::Char('\''), Action::GotoMark),
+        (Key::Ctrl('o'), Action::JumpBack),
+        // Ctrl-I is Tab for the terminal.
+        (Key::Char('\t'), Action::JumpForward),
+        (Key::Char(']'), Action::NextFormFeed),
+        (Key::Char('['), Action::PreviousFormFeed),
+        (Key::Char('z'), Action::ToggleFold),
+        (Key::Char('Z'), Action::FoldLevel),
+        (Key::Char('M'), Action::ToggleMarkdown),
+        (Key::Char('x'), Action::ToggleHex),
+        (Key::Char('W'), Action::ToggleWhitespace),
+        (Key::Char('w'), Action::ToggleWrap),
+        (Key::Char('F'), Action::Follow),
+        (Key::Char('R'), Action::Reload),
+        (Key::Char('h'), Action::Help),
+        (Key::Char('H'), Action::Help),
+    ];
+    bindings.iter().copied().collect()
+}
+
+/// Parses a key name from the config file: a single character like `j`, a named key like
+/// `space` or `pagedown`, or a modifier like `ctrl-f` or `alt-x`.
+pub(crate) fn parse_key(name: &str) -> Option<Key> {
+    let mut chars = name.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        return Some(Key::Char(c));
+    }
+    let lowercase = name.to_lowercase();
+    if let Some(c) = single_char(lowercase.strip_prefix("ctrl-")) {
+        return Some(Key::Ctrl(c));
+    }
+    if let Some(c) = single_char(name.get(4..).filter(|_| lowercase.starts_with("alt-"))) {
+        return Some(Key::Alt(c));
+    }
+    if let Some(n) = lowercase.strip_prefix('f').and_then(|n| n.parse().ok()) {
+        return Some(Key::F(n));
+    }
+    let key = match lowercase.as_str() {
+        "space" => Key::Char(' '),
+        "enter" => Key::Char('\n'),
+        "tab" => Key::Char('\t'),
+        "backspace" => Key::Backspace,
+        "esc" => Key::Esc,
+        "up" => Key::Up,
+        "down" => Key::Down,
+        "left" => Key::Left,
+        "right" => Key::Right,
+        "home" => Key::Home,
+        "end" => Key::End,
+        "pageup" => Key::PageUp,
+        "pagedown" => Key::PageDown,
+        "delete" => Key::Delete,
+        "insert" => Key::Insert,
+        _ => return None,
+    };
+    Some(key)
+}
+
+/// The name of key in the config file, the opposite of parse_key.
+fn key_name(key: Key) -> String {
+    match key {
+        Key::Char(' ') => "space".to_owned(),
+        Key::Char('\n') => "enter".to_owned(),
+        Key::Char('\t') => "tab".to_owned(),
+        Key::Char(c) => c.to_string(),
+        Key::Ctrl(c) => format!("ctrl-{}", c),
+        Key::Alt(c) => format!("alt-{}", c),
+        Key::F(n) => format!("f{}", n),
+        Key::Backspace => "backspace".to_owned(),
+        Key::Esc => "esc".to_owned(),
+        Key::Up => "up".to_owned(),
+        Key::Down => "down".to_owned(),
+        Key::Left => "left".to_owned(),
+        Key::Right => "right".to_owned(),
+        Key::Home => "home".to_owned(),
+        Key::End => "end".to_owned(),
+        Key::PageUp => "pageup".to_owned(),
+        Key::PageDown => "pagedown".to_owned(),
+        Key::Delete => "delete".to_owned(),
+        Key::Insert => "insert".to_owned(),
+        key => format!("{:?}", key),
+    }
+}
+
+/// The help screen: the keys bound to each action, taken from keymap.
+pub(crate) fn help_text(keymap: &Keymap) -> String {
+    let mut text = "Keys (press q to go back)\n\n".to_owned();
+    text.push_str("A number N typed before a key repeats it, or is used as shown.\n\n");
+    for (_name, action, description) in ACTION_NAMES {
+        let mut keys: Vec<String> = keymap
+            .iter()
+            .filter(|(_key, key_action)| *key_action == action)
+            .map(|(key, _action)| key_name(*key))
+            .collect();
+        if keys.is_empty() || *action == Action::Nothing {
+            continue;
+        }
+        keys.sort();
+        text.push_str(&format!("  {:<28} {}\n", keys.join(" "), description));
+    }
+    text
+}
+
+fn single_char(text: Option<&str>) -> Option<char> {
+    let mut chars = text?.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Some(c),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lesser::keys::{default_keymap, help_text, parse_key, Action};
+    use termion::event::Key;
+
+    #[test]
+    fn test_help_text() {
+        let mut keymap = default_keymap();
+        keymap.insert(parse_key("ctrl-x").unwrap(), Action::Quit);
+        keymap.insert(Key::Char('j'), Action::Nothing);
+        let help = help_text(&keymap);
+        assert!(help.contains("  Q ctrl-c ctrl-x q "));
+        let down = help
+            .lines()
+            .find(|line| line.contains("down one line"))
+            .unwrap();
+        assert!(!down.contains(" j "));
+        assert!(down.contains("ctrl-n"));
+    }
+}
@@ -0,0 +1,230 @@
+use crate::lesser::status_line::PromptStyle;
+
+/// When lesser quits by itself at the end of the text, like with less's `-e` and `-E`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum QuitAtEof {
+    Never,
+    /// `-E`: as soon as the end is shown.
+    First,
+    /// `-e`: going forward again from the end.
+    Second,
+}
+
+/// When searches and filters ignore the case of the letters, like with less's `-i` and `-I`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IgnoreCase {
+    Never,
+    /// `-i`: unless the pattern has capitals.
+    Smart,
+    /// `-I`: even if the pattern has capitals.
+    Always,
+}
+
+/// The options in the `LESS` environment variable that lesser understands, like the `FRX`
+/// that git sets. They work like command line flags given before the real ones.
+#[derive(Debug, Default, PartialEq)]
+pub struct LessOptions {
+    /// `-N` shows the line numbers, `-n` hides them.
+    pub line_numbers: Option<bool>,
+    /// `-F` prints the file and exits if it fits in the screen.
+    pub quit_if_one_screen: Option<bool>,
+    /// `-e` quits going forward from the end, `-E` once the end is shown.
+    pub quit_at_eof: Option<QuitAtEof>,
+    /// `-i` ignores the case of the letters when searching, unless the pattern has capitals;
+    /// `-I` always does.
+    pub ignore_case: Option<IgnoreCase>,
+    /// `-X` doesn't switch to the alternate screen.
+    pub no_alt_screen: Option<bool>,
+    /// `-jN` puts the target of goto and search on the Nth row of the screen.
+    pub jump_target: Option<i64>,
+    /// `-z-N` pages by the screen less N rows, keeping them on the screen.
+    pub page_overlap: Option<u64>,
+    /// `-m` shows the medium prompt, `-M` the long one.
+    pub prompt_style: Option<PromptStyle>,
+    /// `-R` passes the colors to the terminal.
+    pub raw_control_chars: Option<bool>,
+    /// `-xN` puts the tab stops N columns apart. Only the first of a list like `-x4,8`.
+    pub tab_width: Option<usize>,
+    /// `-f` opens binary files without asking.
+    pub force: Option<bool>,
+    /// `-L` doesn't use the `LESSOPEN` input preprocessor.
+    pub no_lessopen: Option<bool>,
+    /// `-Pstring` sets a prompt. In LESS it ends at a `$`, since it can have spaces.
+    pub prompts: Vec<String>,
+}
+
+/// The less options followed by a value, like the 4 in `-x4`.
+const OPTIONS_WITH_VALUE: &str = "bhjkoOpPtTxyz#\"";
+
+impl LessOptions {
+    pub fn from_env() -> Self {
+        match std::env::var("LESS") {
+            Ok(less) => LessOptions::parse(&less),
+            Err(_) => LessOptions::default(),
+        }
+    }
+
+    /// Options we don't know (yet) are skipped, like less does with the ones it doesn't have.
+    fn parse(text: &str) -> Self {
+        let mut options = LessOptions::default();
+        let mut words = text.split_whitespace();
+        while let Some(word) = words.next() {
+            if let Some(long) = word.strip_prefix("--") {
+                options.set_long(long);
+                continue;
+            }
+            // `+cmd` runs a command at startup, it's not an option.
+            if word.starts_with('+') {
+                continue;
+            }
+            // In LESS the dash is optional: "FRX" is the same as "-FRX".
+            let flags = word.strip_prefix('-').unwrap_or(word);
+            for (i, flag) in flags.char_indices() {
+                if OPTIONS_WITH_VALUE.contains(flag) {
+                    // The value is the rest of the word, or the next one.
+                    let mut value = match &flags[i + flag.len_utf8()..] {
+                        "" => words.next().unwrap_or("").to_owned(),
+                        value => value.to_owned(),
+                    };
+                    if flag == 'P' {
+                        while !value.contains('$') {
+                            match words.next() {
+                                Some(word) => value = format!("{} {}", value, word),
+                                None => break,
+                            }
+                        }
+                        value.truncate(value.find('$').unwrap_or(value.len()));
+                    }
+                    options.set_value(flag, &value);
+                    break;
+                }
+                options.set(flag);
+            }
+        }
+        options
+    }
+
+    fn set(&mut self, flag: char) {
+        match flag {
+            'N' => self.line_numbers = Some(true),
+            'n' => self.line_numbers = Some(false),
+            'F' => self.quit_if_one_screen = Some(true),
+            'e' => self.quit_at_eof = Some(QuitAtEof::Second),
+            'E' => self.quit_at_eof = Some(QuitAtEof::First),
+            'X' => self.no_alt_screen = Some(true),
+            'i' => self.ignore_case = Some(IgnoreCase::Smart),
+            'I' => self.ignore_case = Some(IgnoreCase::Always),
+            // -S cuts the long lines, which lesser does unless --wrap: nothing to set.
+            'S' => (),
+            'R' => self.raw_control_chars = Some(true),
+            'f' => self.force = Some(true),
+            'L' => self.no_lessopen = Some(true),
+            'm' => self.prompt_style = Some(PromptStyle::Medium),
+            'M' => self.prompt_style = Some(PromptStyle::Long),
+            _ => (),
+        }
+    }
+
+    fn set_value(&mut self, flag: char, value: &str) {
+        match flag {
+            'j' => self.jump_target = value.parse().ok(),
+            // -zN, a page of N rows, changes with the screen: only -z-N is kept.
+            'z' => {
+                self.page_overlap = value
+                    .strip_prefix('-')
+                    .and_then(|overlap| overlap.parse().ok())
+            }
+            'x' => self.tab_width = parse_tab_width(value).ok(),
+            'P' => self.prompts.push(value.to_owned()),
+            _ => (),
+        }
+    }
+
+    fn set_long(&mut self, name: &str) {
+        // Like in less, --LINE-NUMBERS is -N and --line-numbers is -n.
+        match name {
+            "LINE-NUMBERS" => self.set('N'),
+            "line-numbers" => self.set('n'),
+            "quit-if-one-screen" => self.set('F'),
+            "quit-at-eof" => self.set('e'),
+            "QUIT-AT-EOF" => self.set('E'),
+            "no-init" => self.set('X'),
+            "ignore-case" => self.set('i'),
+            "IGNORE-CASE" => self.set('I'),
+            "RAW-CONTROL-CHARS" => self.set('R'),
+            "force" => self.set('f'),
+            "no-lessopen" => self.set('L'),
+            "long-prompt" => self.set('m'),
+            "LONG-PROMPT" => self.set('M'),
+            _ => (),
+        }
+    }
+}
+
+/// The first tab stop of `-x`, like 4 or `4,8`: how many columns apart the tab stops are.
+pub fn parse_tab_width(value: &str) -> Result<usize, String> {
+    value
+        .split(',')
+        .next()
+        .and_then(|first| first.trim().parse().ok())
+        .filter(|tab_width| *tab_width > 0)
+        .ok_or(format!("{} is not a tab width, like 4", value))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lesser::less_env::{IgnoreCase, LessOptions, QuitAtEof};
+    use crate::lesser::status_line::PromptStyle;
+
+    #[test]
+    fn test_parse_less() {
+        let git = LessOptions::parse("FRX");
+        assert_eq!(git.quit_if_one_screen, Some(true));
+        assert_eq!(git.no_alt_screen, Some(true));
+        assert_eq!(git.raw_control_chars, Some(true));
+        assert_eq!(LessOptions::parse("-R -N").line_numbers, Some(true));
+        assert_eq!(LessOptions::parse("-RNn").line_numbers, Some(false));
+        // N is the value of -j here, not an option.
+        assert_eq!(LessOptions::parse("-j N").line_numbers, None);
+        assert_eq!(LessOptions::parse("-j -3 -N").jump_target, Some(-3));
+        assert_eq!(LessOptions::parse("-Rj5").jump_target, Some(5));
+        assert_eq!(LessOptions::parse("-z-2").page_overlap, Some(2));
+        assert_eq!(LessOptions::parse("-z 30").page_overlap, None);
+        let prompt = LessOptions::parse("-M -Pm%f at %pb$ -N");
+        assert_eq!(prompt.prompts, vec!["m%f at %pb".to_owned()]);
+        assert_eq!(prompt.prompt_style, Some(PromptStyle::Long));
+        assert_eq!(prompt.line_numbers, Some(true));
+        assert_eq!(LessOptions::parse("-x4N").line_numbers, None);
+        assert_eq!(LessOptions::parse("-x4,8").tab_width, Some(4));
+        assert_eq!(LessOptions::parse("-x0").tab_width, None);
+        assert_eq!(
+            LessOptions::parse("--LINE-NUMBERS").line_numbers,
+            Some(true)
+        );
+        assert_eq!(
+            LessOptions::parse("-eR").quit_at_eof,
+            Some(QuitAtEof::Second)
+        );
+        assert_eq!(
+            LessOptions::parse("--QUIT-AT-EOF").quit_at_eof,
+            Some(QuitAtEof::First)
+        );
+        assert_eq!(
+            LessOptions::parse("-iR").ignore_case,
+            Some(IgnoreCase::Smart)
+        );
+        assert_eq!(
+            LessOptions::parse("-I").ignore_case,
+            Some(IgnoreCase::Always)
+        );
+        assert_eq!(
+            LessOptions::parse("--ignore-case").ignore_case,
+            Some(IgnoreCase::Smart)
+        );
+        assert_eq!(
+            LessOptions::parse("--IGNORE-CASE").ignore_case,
+            Some(IgnoreCase::Always)
+        );
+        assert_eq!(LessOptions::parse("-S"), LessOptions::default());
+    }
+}
@@ -0,0 +1,150 @@
+use crate::lesser::keys::Action;
+use std::path::PathBuf;
+use termion::event::Key;
+
+/// `$LESSKEYIN`, or `~/.lesskey`.
+pub(crate) fn lesskey_path() -> Option<PathBuf> {
+    match std::env::var_os("LESSKEYIN") {
+        Some(path) if !path.is_empty() => Some(PathBuf::from(path)),
+        _ => Some(PathBuf::from(std::env::var_os("HOME")?).join(".lesskey")),
+    }
+}
+
+/// The bindings in the `#command` section of a lesskey source file (see `man lesskey`), so the
+/// keys set up for less also work here. Lines with commands lesser doesn't have, or bound to a
+/// sequence of keys, are skipped.
+pub(crate) fn parse_lesskey(text: &str) -> Vec<(Key, Action)> {
+    let mut bindings = Vec::new();
+    // The file starts in the command section.
+    let mut in_command_section = true;
+    for line in text.lines() {
+        let line = line.trim();
+        if line.starts_with('#') {
+            match line.split_whitespace().next() {
+                Some("#command") => in_command_section = true,
+                Some("#line-edit") | Some("#env") => in_command_section = false,
+                _ => (),
+            }
+            continue;
+        }
+        if !in_command_section {
+            continue;
+        }
+        let mut words = line.split_whitespace();
+        let (keys, command) = match (words.next(), words.next()) {
+            (Some(keys), Some(command)) => (keys, command),
+            _ => continue,
+        };
+        match (parse_keys(keys).as_slice(), command_action(command)) {
+            ([key], Some(action)) => bindings.push((*key, action)),
+            _ => debug!("Skipping lesskey binding: {}", line),
+        }
+    }
+    bindings
+}
+
+/// The keys in a lesskey key string, like `^F`, `\kd` or `\eb` (Esc then b).
+fn parse_keys(text: &str) -> Vec<Key> {
+    let mut keys = Vec::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        let key = match c {
+            '^' => match chars.next() {
+                Some(c) => Key::Ctrl(c.to_ascii_lowercase()),
+                None => Key::Char('^'),
+            },
+            '\\' => match chars.next() {
+                Some('e') => Key::Esc,
+                Some('n') | Some('r') => Key::Char('\n'),
+                Some('t') => Key::Char('\t'),
+                Some('b') => Key::Backspace,
+                Some('k') => match chars.next() {
+                    Some('u') => Key::Up,
+                    Some('d') => Key::Down,
+                    Some('l') => Key::Left,
+                    Some('r') => Key::Right,
+                    Some('U') => Key::PageUp,
+                    Some('D') => Key::PageDown,
+                    Some('h') => Key::Home,
+                    Some('e') => Key::End,
+                    Some('x') => Key::Delete,
+                    _ => return Vec::new(),
+                },
+                Some(c) => Key::Char(c),
+                None => Key::Char('\\'),
+            },
+            c => Key::Char(c),
+        };
+        keys.push(key);
+    }
+    keys
+}
+
+/// The lesser action for a less command name.
+fn command_action(command: &str) -> Option<Action> {
+    let action = match command {
+        "forw-line" | "forw-line-force" => Action::Down,
+        "back-line" | "back-line-force" => Action::Up,
+        "forw-screen" | "forw-screen-force" | "forw-window" => Action::PageDown,
+        "back-screen" | "back-window" => Action::PageUp,
+        "forw-scroll" => Action::HalfPageDown,
+        "back-scroll" => Action::HalfPageUp,
+        "left-scroll" => Action::Left,
+        "right-scroll" => Action::Right,
+        "goto-line" => Action::Top,
+        "goto-end" | "goto-end-buffered" => Action::Bottom,
+        "percent" => Action::Percent,
+        "forw-search" => Action::Search,
+        "back-search" => Action::SearchBackwards,
+        "repeat-search" => Action::SearchNext,
+        "reverse-search" => Action::SearchPrevious,
+        "filter" => Action::Filter,
+        "toggle-option" => Action::ToggleOption,
+        "set-mark" => Action::SetMark,
+        "goto-mark" => Action::GotoMark,
+        "help" => Action::Help,
+        "quit" => Action::Quit,
+        "noaction" | "invalid" => Action::Nothing,
+        _ => return None,
+    };
+    Some(action)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lesser::keys::Action;
+    use crate::lesser::lesskey::parse_lesskey;
+    use termion::event::Key;
+
+    #[test]
+    fn test_parse_lesskey() {
+        let bindings = parse_lesskey(
+            r"
+# A comment
+#command
+j forw-line
+^F forw-screen
+\kD forw-scroll
+\eb back-screen
+x set-mark
+X pipe
+q noaction
+#env
+LESS = -N
+#command
+Q quit
+",
+        );
+        assert_eq!(
+            bindings,
+            vec![
+                (Key::Char('j'), Action::Down),
+                (Key::Ctrl('f'), Action::PageDown),
+                (Key::PageDown, Action::HalfPageDown),
+                (Key::Char('x'), Action::SetMark),
+                (Key::Char('q'), Action::Nothing),
+                (Key::Char('Q'), Action::Quit),
+            ]
+        );
+    }
+}
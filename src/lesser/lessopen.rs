@@ -0,0 +1,133 @@
+use std::process::Command;
+
+/// An input preprocessor from `LESSOPEN`, like `lesspipe %s` or `|lesspipe %s`, turning files
+/// (PDFs, archives...) into text to show.
+#[derive(Debug, PartialEq)]
+pub(crate) struct LessOpen {
+    /// The shell command, with `%s` for the file name.
+    command: String,
+    /// `|command`: the text to show is its output. Otherwise it prints the name of a file to
+    /// show instead.
+    pub(crate) pipe: bool,
+    /// `||command`: an empty output is the text to show, if the command succeeds.
+    pub(crate) empty_ok: bool,
+    /// `|-command`: it's run for the standard input too, with `-` as the file name.
+    pub(crate) stdin: bool,
+}
+
+impl LessOpen {
+    /// None if there's no command.
+    pub(crate) fn parse(lessopen: &str) -> Option<LessOpen> {
+        let (pipe, empty_ok, command) = match lessopen.strip_prefix("||") {
+            Some(command) => (true, true, command),
+            None => match lessopen.strip_prefix('|') {
+                Some(command) => (true, false, command),
+                None => (false, false, lessopen),
+            },
+        };
+        let (stdin, command) = match command.strip_prefix('-') {
+            Some(command) if pipe => (true, command),
+            _ => (false, command),
+        };
+        let command = command.trim();
+        if command.is_empty() {
+            return None;
+        }
+        Some(LessOpen {
+            command: command.to_owned(),
+            pipe,
+            empty_ok,
+            stdin,
+        })
+    }
+
+    /// The shell command to run for filename.
+    pub(crate) fn command_for(&self, filename: &str) -> String {
+        substitute(&self.command, &[filename])
+    }
+}
+
+/// Runs the `LESSCLOSE` command when dropped, after the file replaced by `LESSOPEN` has been
+/// shown: e.g. to remove the temporary file.
+pub(crate) struct LessClose {
+    pub(crate) command: String,
+}
+
+impl LessClose {
+    /// The first `%s` of command is the name of the file, the second the name of its
+    /// replacement (`-` if it was a pipe).
+    pub(crate) fn new(command: &str, filename: &str, replacement: &str) -> Self {
+        LessClose {
+            command: substitute(command, &[filename, replacement]),
+        }
+    }
+}
+
+impl Drop for LessClose {
+    fn drop(&mut self) {
+        if let Err(error) = Command::new("sh").arg("-c").arg(&self.command).status() {
+            debug!("Can't run LESSCLOSE {:?}: {}", self.command, error);
+        }
+    }
+}
+
+/// Puts names, quoted for the shell, in place of the `%s` in command, in order. `%%` is a `%`.
+fn substitute(command: &str, names: &[&str]) -> String {
+    let mut substituted = String::new();
+    let mut names = names.iter();
+    let mut chars = command.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            substituted.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('s') => substituted.push_str(&shell_quote(names.next().unwrap_or(&""))),
+            Some('%') => substituted.push('%'),
+            Some(other) => {
+                substituted.push('%');
+                substituted.push(other);
+            }
+            None => substituted.push('%'),
+        }
+    }
+    substituted
+}
+
+/// text as a single word for the shell, whatever chars it has.
+fn shell_quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', r"'\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lesser::lessopen::{substitute, LessOpen};
+
+    #[test]
+    fn test_parse() {
+        let lessopen = LessOpen::parse("||-lesspipe %s").unwrap();
+        assert!(lessopen.pipe && lessopen.empty_ok && lessopen.stdin);
+        assert_eq!(lessopen.command_for("-"), "lesspipe '-'");
+        let lessopen = LessOpen::parse("| /usr/bin/lesspipe %s").unwrap();
+        assert!(lessopen.pipe && !lessopen.empty_ok && !lessopen.stdin);
+        assert_eq!(
+            LessOpen::parse("lessopen.sh %s"),
+            Some(LessOpen {
+                command: "lessopen.sh %s".to_owned(),
+                pipe: false,
+                empty_ok: false,
+                stdin: false,
+            })
+        );
+        assert_eq!(LessOpen::parse("|"), None);
+        assert_eq!(LessOpen::parse(""), None);
+    }
+
+    #[test]
+    fn test_substitute() {
+        assert_eq!(
+            substitute("lessclose.sh %s %s 100%%", &["it's.pdf", "/tmp/x"]),
+            r"lessclose.sh 'it'\''s.pdf' '/tmp/x' 100%"
+        );
+    }
+}
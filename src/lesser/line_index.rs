@@ -0,0 +1,470 @@
+use crate::lesser::encoding::utf_8_bom_len;
+use crate::lesser::text::Text;
+use memchr::memchr_iter;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::sync::Arc;
+
+/// How many lines share an anchor.
+const ANCHOR_EVERY: usize = 64;
+/// Past this many lines (64 MiB of deltas), only checkpoints are kept.
+const SPARSE_FROM: usize = 1 << 24;
+/// How many lines there are from a checkpoint to the next one.
+const CHECKPOINT_EVERY: usize = 1024;
+
+/// Where each line of the text starts and ends, kept compact for files with millions of lines.
+/// Only the ends are kept: a line starts right after the end of the one before. And they're
+/// u32 offsets from an anchor, the end of one line every ANCHOR_EVERY: about 4 bytes a line,
+/// instead of 16 for a start and an end. For the lines of a text, only checkpoints are kept
+/// past SPARSE_FROM lines.
+pub(crate) struct LineIndex {
+    /// The end of the first line of each group of ANCHOR_EVERY lines.
+    anchors: Vec<usize>,
+    /// The end of each line, from the anchor of its group. u32::MAX if it's too far from it,
+    /// after a line of more than 4 GiB: the end is in far_ends then.
+    deltas: Vec<u32>,
+    far_ends: BTreeMap<usize, usize>,
+    /// How many bytes are between the end of a line and the start of the next one.
+    separator: usize,
+    /// Where the first line starts: after the byte order mark of UTF-8 text, not shown.
+    first_start: usize,
+    /// The lines past the first ones, for the lines of a text.
+    sparse: Option<Sparse>,
+}
+
+/// The lines past the ones in the deltas, for billion-line files: the end of every
+/// CHECKPOINT_EVERY line is kept, and the ends between two checkpoints are found again in the
+/// text when they're needed.
+struct Sparse {
+    text: Arc<Text>,
+    /// The byte ending each line.
+    newline: u8,
+    /// How many lines are in the deltas, before the sparse ones.
+    from: usize,
+    /// The end of the last line of each group of CHECKPOINT_EVERY lines.
+    checkpoints: Vec<usize>,
+    /// The ends of the lines after the last checkpoint.
+    tail: Vec<usize>,
+    /// The last group scanned, and the ends of its lines.
+    scanned: RefCell<Option<(usize, Vec<usize>)>>,
+}
+
+impl LineIndex {
+    /// Lines with separator bytes between them: 1 for the newline, 0 for the rows of the hex
+    /// dump.
+    pub(crate) fn new(separator: usize) -> Self {
+        LineIndex {
+            anchors: Vec::new(),
+            deltas: Vec::new(),
+            far_ends: BTreeMap::new(),
+            separator,
+            first_start: 0,
+            sparse: None,
+        }
+    }
+
+    /// The lines of text, each ending with the newline byte. Past SPARSE_FROM lines, only
+    /// checkpoints are kept.
+    pub(crate) fn lines(text: Arc<Text>, newline: u8) -> Self {
+        LineIndex::sparse_from(text, newline, SPARSE_FROM)
+    }
+
+    fn sparse_from(text: Arc<Text>, newline: u8, from: usize) -> Self {
+        LineIndex {
+            first_start: utf_8_bom_len(&text.head(3)),
+            sparse: Some(Sparse {
+                text,
+                newline,
+                from,
+                checkpoints: Vec::new(),
+                tail: Vec::new(),
+                scanned: RefCell::new(None),
+            }),
+            ..LineIndex::new(1)
+        }
+    }
+
+    /// Finds the lines in text from now on, the old text with more bytes after it.
+    pub(crate) fn set_text(&mut self, text: Arc<Text>) {
+        if let Some(sparse) = &mut self.sparse {
+            sparse.text = text;
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.deltas.len() + self.sparse.as_ref().map_or(0, Sparse::len)
+    }
+
+    fn end(&self, line: usize) -> usize {
+        match self.sparse.as_ref() {
+            Some(sparse) if line >= sparse.from => sparse.end(line - sparse.from, self),
+            _ => self.dense_end(line),
+        }
+    }
+
+    fn dense_end(&self, line: usize) -> usize {
+        match self.deltas[line] {
+            u32::MAX => self.far_ends[&line],
+            delta => self.anchors[line / ANCHOR_EVERY] + delta as usize,
+        }
+    }
+
+    /// The start and end of line, that must be indexed.
+    pub(crate) fn line(&self, line: usize) -> (usize, usize) {
+        let start = match line.checked_sub(1) {
+            Some(previous) => self.end(previous) + self.separator,
+            None => self.first_start,
+        };
+        (start, self.end(line))
+    }
+
+    pub(crate) fn get(&self, line: usize) -> Option<(usize, usize)> {
+        (line < self.len()).then(|| self.line(line))
+    }
+
+    pub(crate) fn last(&self) -> Option<(usize, usize)> {
+        self.get(self.len().checked_sub(1)?)
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        (0..self.len()).map(move |line| self.line(line))
+    }
+
+    /// Adds the line ending at end, after the last one.
+    pub(crate) fn push(&mut self, end: usize) {
+        match &mut self.sparse {
+            Some(sparse) if self.deltas.len() == sparse.from => sparse.push(end),
+            _ => self.push_dense(end),
+        }
+    }
+
+    fn push_dense(&mut self, end: usize) {
+        let line = self.deltas.len();
+        if line.is_multiple_of(ANCHOR_EVERY) {
+            self.anchors.push(end);
+        }
+        let anchor = self.anchors[line / ANCHOR_EVERY];
+        match u32::try_from(end - anchor) {
+            Ok(delta) if delta != u32::MAX => self.deltas.push(delta),
+            _ => {
+                self.deltas.push(u32::MAX);
+                self.far_ends.insert(line, end);
+            }
+        }
+    }
+
+    /// Removes the last line.
+    pub(crate) fn pop(&mut self) {
+        if let Some(sparse) = self.sparse.take() {
+            let mut sparse = sparse;
+            let popped = sparse.pop(self);
+            self.sparse = Some(sparse);
+            if popped {
+                return;
+            }
+        }
+        if self.deltas.pop().is_none() {
+            return;
+        }
+        let line = self.deltas.len();
+        self.far_ends.remove(&line);
+        if line.is_multiple_of(ANCHOR_EVERY) {
+            self.anchors.pop();
+        }
+    }
+
+    /// The first line ending at offset or after it: the one with the byte at offset. len() if
+    /// it's past the last line.
+    pub(crate) fn line_at(&self, offset: usize) -> usize {
+        let dense = self.deltas.len();
+        match self.sparse.as_ref() {
+            Some(sparse) if dense == 0 || self.dense_end(dense - 1) < offset => {
+                dense + sparse.line_at(offset, self)
+            }
+            _ => first_ending_at(offset, dense, |line| self.dense_end(line)),
+        }
+    }
+
+    /// The index of the lines of a text, to save it: the byte they end with, where the sparse
+    /// lines start, how many ends are in the deltas, the checkpoints and the tail, and then each
+    /// end, from the end before it. All varints.
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        let (newline, from, checkpoints, tail) = match &self.sparse {
+            Some(sparse) => (
+                sparse.newline,
+                sparse.from,
+                &sparse.checkpoints[..],
+                &sparse.tail[..],
+            ),
+            None => (b'\n', self.deltas.len(), &[][..], &[][..]),
+        };
+        let mut bytes = Vec::new();
+        let counts = [from, self.deltas.len(), checkpoints.len(), tail.len()];
+        for count in [newline as usize].iter().chain(&counts) {
+            write_varint(&mut bytes, *count);
+        }
+        let mut previous = 0;
+        let dense = (0..self.deltas.len()).map(|line| self.dense_end(line));
+        for end in dense.chain(checkpoints.iter().copied().chain(tail.iter().copied())) {
+            write_varint(&mut bytes, end - previous);
+            previous = end;
+        }
+        bytes
+    }
+
+    /// The index saved with to_bytes, of the lines of text ending with newline. None if it
+    /// doesn't make sense, or if it's not of text, or of lines ending with another byte.
+    pub(crate) fn from_bytes(text: Arc<Text>, newline: u8, mut bytes: &[u8]) -> Option<LineIndex> {
+        if read_varint(&mut bytes)? != newline as usize {
+            return None;
+        }
+        let from = read_varint(&mut bytes)?;
+        let dense = read_varint(&mut bytes)?;
+        let checkpoints = read_varint(&mut bytes)?;
+        let tail = read_varint(&mut bytes)?;
+        let sparse_lines = checkpoints > 0 || tail > 0;
+        if dense > from || (sparse_lines && dense < from) || tail >= CHECKPOINT_EVERY {
+            return None;
+        }
+        let len = text.len();
+        let mut index = LineIndex::sparse_from(text, newline, from);
+        let mut end = 0usize;
+        let mut next_end = || {
+            end = end.checked_add(read_varint(&mut bytes)?)?;
+            (end <= len).then_some(end)
+        };
+        for _ in 0..dense {
+            index.push_dense(next_end()?);
+        }
+        let sparse = index.sparse.as_mut()?;
+        for _ in 0..checkpoints {
+            sparse.checkpoints.push(next_end()?);
+        }
+        for _ in 0..tail {
+            sparse.tail.push(next_end()?);
+        }
+        bytes.is_empty().then_some(index)
+    }
+}
+
+/// The first of lines lines whose end is at offset or after it, lines if none is.
+fn first_ending_at(offset: usize, lines: usize, end: impl Fn(usize) -> usize) -> usize {
+    let (mut low, mut high) = (0, lines);
+    while low < high {
+        let middle = low + (high - low) / 2;
+        if end(middle) < offset {
+            low = middle + 1;
+        } else {
+            high = middle;
+        }
+    }
+    low
+}
+
+fn write_varint(bytes: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        bytes.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+/// Reads a varint at the start of bytes, and skips it.
+fn read_varint(bytes: &mut &[u8]) -> Option<usize> {
+    let mut value = 0usize;
+    for shift in (0..usize::BITS).step_by(7) {
+        let (byte, rest) = bytes.split_first()?;
+        *bytes = rest;
+        value |= ((byte & 0x7f) as usize).checked_shl(shift)?;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+    None
+}
+
+impl Sparse {
+    fn len(&self) -> usize {
+        self.checkpoints.len() * CHECKPOINT_EVERY + self.tail.len()
+    }
+
+    /// The end of line, counting from the first sparse one.
+    fn end(&self, line: usize, index: &LineIndex) -> usize {
+        let group = line / CHECKPOINT_EVERY;
+        if group == self.checkpoints.len() {
+            return self.tail[line % CHECKPOINT_EVERY];
+        }
+        self.with_group(group, index, |ends| ends[line % CHECKPOINT_EVERY])
+    }
+
+    /// Calls read with the ends of the lines of group, a group before the tail. They're
+    /// scanned for, unless it's the group scanned last.
+    fn with_group<T>(
+        &self,
+        group: usize,
+        index: &LineIndex,
+        read: impl FnOnce(&[usize]) -> T,
+    ) -> T {
+        let mut scanned = self.scanned.borrow_mut();
+        if !matches!(&*scanned, Some((scanned_group, _)) if *scanned_group == group) {
+            let ends = self.scan(group, index);
+            *scanned = Some((group, ends));
+        }
+        read(&scanned.as_ref().unwrap().1)
+    }
+
+    fn scan(&self, group: usize, index: &LineIndex) -> Vec<usize> {
+        let start = match group.checked_sub(1) {
+            Some(previous) => self.checkpoints[previous] + 1,
+            None => self
+                .from
+                .checked_sub(1)
+                .map_or(0, |last| index.dense_end(last) + 1),
+        };
+        let end = self.checkpoints[group];
+        let mut ends: Vec<usize> = memchr_iter(self.newline, &self.text.bytes(start..end))
+            .map(|newline| start + newline)
+            .collect();
+        // The last line ends at the checkpoint, with or without a newline.
+        ends.push(end);
+        ends
+    }
+
+    fn push(&mut self, end: usize) {
+        self.tail.push(end);
+        if self.tail.len() == CHECKPOINT_EVERY {
+            self.checkpoints.push(end);
+            self.tail.clear();
+        }
+    }
+
+    /// Removes the last line, false if there are no sparse lines.
+    fn pop(&mut self, index: &LineIndex) -> bool {
+        if self.tail.pop().is_some() {
+            return true;
+        }
+        let group = match self.checkpoints.len().checked_sub(1) {
+            Some(group) => group,
+            None => return false,
+        };
+        // The lines of the last group are the tail again, but for its last one.
+        self.tail = self.with_group(group, index, |ends| ends[..ends.len() - 1].to_vec());
+        self.checkpoints.pop();
+        *self.scanned.borrow_mut() = None;
+        true
+    }
+
+    /// The first sparse line ending at offset or after it, len() if none is.
+    fn line_at(&self, offset: usize, index: &LineIndex) -> usize {
+        let group = self.checkpoints.partition_point(|end| *end < offset);
+        if group == self.checkpoints.len() {
+            let line = self.tail.partition_point(|end| *end < offset);
+            return group * CHECKPOINT_EVERY + line;
+        }
+        let line = self.with_group(group, index, |ends| {
+            ends.partition_point(|end| *end < offset)
+        });
+        group * CHECKPOINT_EVERY + line
+    }
+}
+
+impl Extend<usize> for LineIndex {
+    fn extend<T: IntoIterator<Item = usize>>(&mut self, ends: T) {
+        for end in ends {
+            self.push(end);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lesser::line_index::LineIndex;
+    use crate::lesser::text::Text;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_line_index() {
+        let mut index = LineIndex::new(1);
+        // Lines of 10 bytes and their newline, then a line of 5 GiB.
+        index.extend((0..200).map(|line| line * 11 + 10));
+        index.push(199 * 11 + 10 + (5 << 30));
+        index.push(199 * 11 + 20 + (5 << 30));
+        assert_eq!(index.len(), 202);
+        assert_eq!(index.line(0), (0, 10));
+        assert_eq!(index.line(130), (1430, 1440));
+        assert_eq!(index.line(200), (2200, 2199 + (5 << 30)));
+        assert_eq!(index.last(), Some((2200 + (5 << 30), 2209 + (5 << 30))));
+        assert_eq!(index.line_at(1435), 130);
+        assert_eq!(index.line_at(1440), 130);
+        assert_eq!(index.line_at(1441), 131);
+        assert_eq!(index.line_at(usize::MAX), 202);
+        for _ in 0..80 {
+            index.pop();
+        }
+        assert_eq!(index.len(), 122);
+        assert_eq!(index.last(), Some((1331, 1341)));
+        index.push(1345);
+        assert_eq!(index.last(), Some((1342, 1345)));
+        assert_eq!(index.get(123), None);
+
+        let mut hex = LineIndex::new(0);
+        hex.extend(vec![16, 32, 40]);
+        assert_eq!(
+            hex.iter().collect::<Vec<_>>(),
+            [(0, 16), (16, 32), (32, 40)]
+        );
+    }
+
+    #[test]
+    fn test_sparse() {
+        // 3000 lines of 9 bytes and their newline, the last one without it.
+        let mut bytes = b"123456789\n".repeat(3000);
+        bytes.pop();
+        let len = bytes.len();
+        let mut index = LineIndex::sparse_from(Arc::new(Text::from(bytes)), b'\n', 10);
+        index.extend((0..3000).map(|line| line * 10 + 9));
+        assert_eq!(index.len(), 3000);
+        assert_eq!(index.line(9), (90, 99));
+        assert_eq!(index.line(10), (100, 109));
+        // In the first group of checkpoints, its last line, then in the tail.
+        assert_eq!(index.line(500), (5000, 5009));
+        assert_eq!(index.line(1033), (10330, 10339));
+        assert_eq!(index.last(), Some((29990, len)));
+        assert_eq!(index.line_at(5005), 500);
+        assert_eq!(index.line_at(12345), 1234);
+        assert_eq!(index.line_at(29995), 2999);
+        assert_eq!(index.line_at(len + 1), 3000);
+        assert_eq!(index.line_at(50), 5);
+        // Back into the groups of checkpoints.
+        for _ in 0..1000 {
+            index.pop();
+        }
+        assert_eq!(index.len(), 2000);
+        assert_eq!(index.last(), Some((19990, 19999)));
+        index.push(20009);
+        assert_eq!(index.line(2000), (20000, 20009));
+    }
+
+    #[test]
+    fn test_bytes() {
+        let text = Arc::new(Text::from(b"123456789\n".repeat(3000)));
+        let mut index = LineIndex::sparse_from(Arc::clone(&text), b'\n', 10);
+        index.extend((0..2500).map(|line| line * 10 + 9));
+        let bytes = index.to_bytes();
+        let loaded = LineIndex::from_bytes(Arc::clone(&text), b'\n', &bytes).unwrap();
+        assert_eq!(loaded.len(), 2500);
+        assert_eq!(
+            loaded.iter().collect::<Vec<_>>(),
+            index.iter().collect::<Vec<_>>()
+        );
+        // Cut short, or of a shorter text.
+        assert!(
+            LineIndex::from_bytes(Arc::clone(&text), b'\n', &bytes[..bytes.len() - 1]).is_none()
+        );
+        let short = Arc::new(Text::from(b"123456789\n".repeat(100)));
+        assert!(LineIndex::from_bytes(short, b'\n', &bytes).is_none());
+        // Of the records ending with NUL.
+        assert!(LineIndex::from_bytes(Arc::clone(&text), b'\0', &bytes).is_none());
+    }
+}
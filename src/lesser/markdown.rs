@@ -0,0 +1,229 @@
+use crate::lesser::highlight::SyntaxHighlighter;
+use std::borrow::Cow;
+use std::path::Path;
+
+const BOLD: &str = "\x1b[1m";
+/// Ends DIM too.
+const BOLD_OFF: &str = "\x1b[22m";
+const ITALIC: &str = "\x1b[3m";
+const ITALIC_OFF: &str = "\x1b[23m";
+const CODE: &str = "\x1b[36m";
+const CODE_OFF: &str = "\x1b[39m";
+const DIM: &str = "\x1b[2m";
+
+/// If path is a Markdown file.
+pub(crate) fn is_markdown(path: &Path) -> bool {
+    matches!(
+        path.extension().and_then(|extension| extension.to_str()),
+        Some("md") | Some("markdown")
+    )
+}
+
+/// Shows Markdown with terminal styles instead of its markup, or as it is. Each line stays
+/// a line, so switching between the two keeps the position.
+pub(crate) struct MarkdownRenderer {
+    /// Show the styles, instead of the markup.
+    rendered: bool,
+    /// Colors the markup when it's not rendered.
+    plain: Option<SyntaxHighlighter>,
+    /// If each line, from the first, starts in a fenced code block. Filled lazily.
+    in_code: Vec<bool>,
+}
+
+impl MarkdownRenderer {
+    pub(crate) fn new(rendered: bool, plain: Option<SyntaxHighlighter>) -> Self {
+        MarkdownRenderer {
+            rendered,
+            plain,
+            in_code: vec![false],
+        }
+    }
+
+    /// Switches between the rendered and the plain view.
+    pub(crate) fn toggle(&mut self) {
+        self.rendered = !self.rendered;
+    }
+
+    /// The text of line, rendered or not. text_of gives the text of a line, the ones before
+    /// line are needed too to know if it's in a code block.
+    pub(crate) fn highlight<'a>(
+        &mut self,
+        line: usize,
+        text_of: impl Fn(usize) -> Cow<'a, str>,
+    ) -> String {
+        if !self.rendered {
+            return match &mut self.plain {
+                Some(plain) => plain.highlight(line, text_of),
+                None => text_of(line).into_owned(),
+            };
+        }
+        while self.in_code.len() <= line {
+            let last = self.in_code.len() - 1;
+            let in_code = self.in_code[last] != is_fence(&text_of(last));
+            self.in_code.push(in_code);
+        }
+        let text = text_of(line);
+        if is_fence(&text) {
+            format!("{}{}", DIM, text)
+        } else if self.in_code[line] {
+            format!("{}{}", CODE, text)
+        } else {
+            render_block(&text)
+        }
+    }
+}
+
+/// A line starting or ending a code block, like ```` ```rust ````.
+fn is_fence(line: &str) -> bool {
+    let line = line.trim_start();
+    line.starts_with("```") || line.starts_with("~~~")
+}
+
+/// Headings are bold (and underlined, the first level), list items have a bullet and quotes
+/// a bar.
+fn render_block(line: &str) -> String {
+    if let Some((level, text)) = heading(line) {
+        let style = if level == 1 { "\x1b[1;4m" } else { BOLD };
+        return format!("{}{}", style, text);
+    }
+    let text = line.trim_start();
+    let indent = &line[..line.len() - text.len()];
+    let mut chars = text.chars();
+    match (chars.next(), chars.next()) {
+        (Some('-') | Some('*') | Some('+'), Some(' ') | Some('\t')) => {
+            format!("{}• {}", indent, render_inline(text[1..].trim_start()))
+        }
+        (Some('>'), _) => {
+            let quote = &text[1..];
+            let quote = quote.strip_prefix(' ').unwrap_or(quote);
+            format!("{}│{} {}", DIM, BOLD_OFF, render_inline(quote))
+        }
+        _ => render_inline(line),
+    }
+}
+
+/// The level and the text of a heading like `## Title`, up to 3 spaces in.
+fn heading(line: &str) -> Option<(usize, &str)> {
+    let text = line.strip_prefix("   ").or_else(|| line.strip_prefix("  "));
+    let text = text.or_else(|| line.strip_prefix(' ')).unwrap_or(line);
+    let level = text.chars().take_while(|c| *c == '#').count();
+    let rest = &text[level..];
+    if level == 0 || level > 6 || !(rest.is_empty() || rest.starts_with(char::is_whitespace)) {
+        return None;
+    }
+    // The closing #s, like in `## Title ##`, are not shown.
+    let rest = rest.trim();
+    let without_closing = rest.trim_end_matches('#');
+    let rest = if without_closing.is_empty() || without_closing.ends_with(char::is_whitespace) {
+        without_closing.trim_end()
+    } else {
+        rest
+    };
+    Some((level, rest))
+}
+
+/// Bold, italic and code spans. There's no emphasis in code spans.
+fn render_inline(text: &str) -> String {
+    let mut rendered = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find('`') {
+        let end = match rest[start + 1..].find('`') {
+            Some(len) if len > 0 => start + 1 + len,
+            _ => break,
+        };
+        rendered.push_str(&render_emphasis(&rest[..start]));
+        rendered.push_str(&format!("{}{}{}", CODE, &rest[start + 1..end], CODE_OFF));
+        rest = &rest[end + 1..];
+    }
+    rendered.push_str(&render_emphasis(rest));
+    rendered
+}
+
+fn render_emphasis(text: &str) -> String {
+    let text = emphasize(text, "**", BOLD, BOLD_OFF);
+    let text = emphasize(&text, "__", BOLD, BOLD_OFF);
+    let text = emphasize(&text, "*", ITALIC, ITALIC_OFF);
+    emphasize(&text, "_", ITALIC, ITALIC_OFF)
+}
+
+/// Puts the text between two markers, like `**bold**`, between on and off instead. Like in
+/// Markdown the text can't start or end with a space, and `_` only counts at the edges of
+/// words, so snake_case stays as it is.
+fn emphasize(text: &str, marker: &str, on: &str, off: &str) -> String {
+    let word_edges = marker.starts_with('_');
+    let is_word = |c: Option<char>| matches!(c, Some(c) if c.is_alphanumeric());
+    let mut emphasized = String::new();
+    let mut rest = text;
+    loop {
+        let open = rest.match_indices(marker).map(|(i, _)| i).find(|i| {
+            let after = rest[i + marker.len()..].chars().next();
+            matches!(after, Some(c) if !c.is_whitespace())
+                && !(word_edges && is_word(rest[..*i].chars().next_back()))
+        });
+        let open = match open {
+            Some(open) => open,
+            None => break,
+        };
+        let inside = &rest[open + marker.len()..];
+        let close = inside.match_indices(marker).map(|(i, _)| i).find(|i| {
+            let before = inside[..*i].chars().next_back();
+            matches!(before, Some(c) if !c.is_whitespace())
+                && !(word_edges && is_word(inside[i + marker.len()..].chars().next()))
+        });
+        let close = match close {
+            Some(close) => close,
+            None => break,
+        };
+        emphasized.push_str(&rest[..open]);
+        emphasized.push_str(on);
+        emphasized.push_str(&inside[..close]);
+        emphasized.push_str(off);
+        rest = &inside[close + marker.len()..];
+    }
+    emphasized.push_str(rest);
+    emphasized
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lesser::markdown::{heading, MarkdownRenderer};
+    use std::borrow::Cow;
+
+    #[test]
+    fn test_render() {
+        let lines = [
+            "# Title #",
+            "Some **bold**, *italic* and `code *not* italic` in snake_case_name.",
+            "  - item",
+            "```rust",
+            "let x = *y;",
+            "```",
+            "> quoted _text_",
+        ];
+        let mut renderer = MarkdownRenderer::new(true, None);
+        let text_of = |line: usize| Cow::Borrowed(lines[line]);
+        // The code block is found from the lines before.
+        assert_eq!(renderer.highlight(4, text_of), "\x1b[36mlet x = *y;");
+        let rendered: Vec<String> = (0..lines.len())
+            .map(|line| renderer.highlight(line, text_of))
+            .collect();
+        assert_eq!(
+            rendered,
+            [
+                "\x1b[1;4mTitle",
+                "Some \x1b[1mbold\x1b[22m, \x1b[3mitalic\x1b[23m and \
+                 \x1b[36mcode *not* italic\x1b[39m in snake_case_name.",
+                "  • item",
+                "\x1b[2m```rust",
+                "\x1b[36mlet x = *y;",
+                "\x1b[2m```",
+                "\x1b[2m│\x1b[22m quoted \x1b[3mtext\x1b[23m",
+            ]
+        );
+        renderer.toggle();
+        assert_eq!(renderer.highlight(0, text_of), "# Title #");
+
+        assert_eq!(heading("## C# ##"), Some((2, "C#")));
+        assert_eq!(heading("#hashtag"), None);
+    }
+}
@@ -1,38 +1,959 @@
+use crate::lesser::decompress::{decompressed_path, Compression};
+use crate::lesser::directory::{human_size, list};
+use crate::lesser::encoding::detect;
+use crate::lesser::files::FileList;
 use crate::lesser::formats::Message;
-use crate::lesser::reader::PagedReader;
+use crate::lesser::frame::Frame;
+use crate::lesser::hex::{looks_binary, BINARY_CHECK_BYTES};
+use crate::lesser::highlight::{
+    has_log_level, looks_like_diff, Highlighter, LogLevel, SyntaxHighlighter,
+};
+use crate::lesser::json::{looks_like_json, pretty_print};
+use crate::lesser::keys::{help_text, Action, Keymap};
+use crate::lesser::lessopen::{LessClose, LessOpen};
+use crate::lesser::markdown::{is_markdown, MarkdownRenderer};
+use crate::lesser::prompt::{parse_startup_command, Prompt, PromptKind, PromptLine, PromptStatus};
+use crate::lesser::reader::{FilterPattern, PagedReader};
 use crate::lesser::screen_move_handler::ScreenMoveHandler;
-use crossbeam_channel::Sender;
+use crate::lesser::search::{Pattern, RunningSearch};
+use crate::lesser::state::{state_path, FileState, SearchHistory, State};
+use crate::lesser::status_line::{format_prompt, needs_total_lines, StatusInfo};
+use crate::lesser::stream::Stream;
+use crate::lesser::table::{align, guess_separator, separator_of};
+use crate::lesser::text::Text;
+use crossbeam_channel::{RecvError, RecvTimeoutError, Sender};
+use encoding_rs::{Encoding, UTF_8};
 use memmap::{Mmap, MmapMut};
 use signal_hook::{iterator::Signals, SIGINT, SIGWINCH};
+use std::cmp::{max, min};
+use std::collections::HashMap;
 use std::fs::{File, OpenOptions};
-use std::io::{stdin, stdout, ErrorKind, Stdout, Write};
-use std::path::PathBuf;
+use std::io::{stdin, stdout, ErrorKind, Read, Write};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::Duration;
 use std::{fs, thread};
 use termion::event::Key;
 use termion::input::TermRead;
-use termion::raw::{IntoRawMode, RawTerminal};
+use termion::raw::IntoRawMode;
 use termion::screen::AlternateScreen;
 use termion::{is_tty, terminal_size};
 
+mod ansi;
+mod config;
+mod decompress;
+mod directory;
+mod encoding;
+mod files;
 mod formats;
+mod frame;
+mod hex;
+mod highlight;
+mod index_cache;
+mod indexer;
+mod json;
+mod keys;
+mod less_env;
+mod lesskey;
+mod lessopen;
+mod line_index;
+mod markdown;
+mod prompt;
 mod reader;
 mod screen_move_handler;
+mod search;
+mod state;
+mod status_line;
+mod stream;
+mod table;
+mod text;
 
-pub fn run(filename: Option<PathBuf>) -> std::io::Result<()> {
-    let screen = AlternateScreen::from(stdout()).into_raw_mode()?;
+pub use crate::lesser::ansi::{parse_control_notation, ControlChars, ControlNotation};
+pub use crate::lesser::config::{parse_separator, parse_size, Config};
+pub use crate::lesser::encoding::parse_encoding;
+pub use crate::lesser::less_env::{parse_tab_width, IgnoreCase, LessOptions, QuitAtEof};
+pub use crate::lesser::status_line::{PromptStyle, Prompts};
+pub use crate::lesser::stream::Overflow;
+
+/// How often a file being followed, or still coming, is checked for new lines.
+const FOLLOW_INTERVAL: Duration = Duration::from_millis(250);
+/// The terminal is resized when no SIGWINCH came for this long: window managers send many
+/// while the window is dragged, and each page would be drawn.
+const RESIZE_QUIET: Duration = Duration::from_millis(50);
+
+/// Settings coming from the command line and the config file.
+pub struct Settings {
+    /// Patterns are literal strings by default, instead of regexes.
+    pub literal_search: bool,
+    /// When searches and filters ignore the case of the letters.
+    pub ignore_case: IgnoreCase,
+    /// Show the line numbers.
+    pub line_numbers: bool,
+    /// Print the file and exit if it fits in the screen.
+    pub quit_if_one_screen: bool,
+    /// Quit at the end of the last file, once it's shown or going forward from it.
+    pub quit_at_eof: QuitAtEof,
+    /// Don't switch to the alternate screen, so the last page stays on the terminal.
+    pub no_alt_screen: bool,
+    /// Screen row where goto and search put their target, starting from 1. Negative counts
+    /// from the bottom.
+    pub jump_target: i64,
+    /// Rows of the page kept on the screen when paging, for context.
+    pub page_overlap: u64,
+    /// What each key does.
+    pub keys: Keymap,
+    /// Format of the status line, in the less prompt language.
+    pub prompt: String,
+    /// Command to run before showing the first page, without the +. Like G to start at the end.
+    pub startup_command: Option<String>,
+    /// Reopen files where they were left the last time.
+    pub resume: bool,
+    /// How the control chars, like the escape sequences of colors, are shown.
+    pub control_chars: ControlChars,
+    /// The tab stops are this many columns apart.
+    pub tab_width: usize,
+    /// How the control chars shown as text are written.
+    pub control_notation: ControlNotation,
+    /// Show the tabs, trailing spaces and line endings.
+    pub show_whitespace: bool,
+    /// Wrap the long lines on more rows, instead of scrolling them horizontally.
+    pub wrap: bool,
+    /// Wrap the long lines before the word that doesn't fit, instead of in the middle of it.
+    pub wrap_words: bool,
+    /// What the rows a long line is wrapped on start with, after the first.
+    pub wrap_marker: String,
+    /// The lines end with this byte, like NUL for -z, instead of a newline: \n, \r\n or \r,
+    /// found from the text.
+    pub separator: Option<u8>,
+    /// The encoding of the text, instead of the one found from it.
+    pub encoding: Option<&'static Encoding>,
+    /// Color the text by its syntax, if the file type is known.
+    pub syntax_highlighting: bool,
+    /// Colors of the lines of log files, by level.
+    pub log_levels: Vec<LogLevel>,
+    /// Color the text as a unified diff, even if it doesn't look like one.
+    pub diff: bool,
+    /// Page the text as JSON, even if it's not a .json file.
+    pub json: bool,
+    /// Page the text as a CSV or TSV table, even if it's not a .csv or .tsv file.
+    pub table: bool,
+    /// Keep the first line of tables at the top of the screen.
+    pub table_header: bool,
+    /// Show Markdown files with styles instead of their markup.
+    pub render_markdown: bool,
+    /// Show the bytes in hex, like xxd, instead of the text.
+    pub hex: bool,
+    /// Open binary files without asking.
+    pub force: bool,
+    /// The input preprocessor, like in less: a command turning files into text to show.
+    pub lessopen: Option<String>,
+    /// The command to run after showing a file replaced by the input preprocessor.
+    pub lessclose: Option<String>,
+    /// A shell command whose output is paged as it comes, instead of the files.
+    pub exec: Option<String>,
+    /// How many bytes of piped text are kept in memory.
+    pub buffer_max: u64,
+    /// What happens to the piped text past buffer_max.
+    pub overflow: Overflow,
+    /// Save the line index of big files, and reuse it the next time they're opened.
+    pub index_cache: bool,
+}
+
+pub fn run(filenames: Vec<PathBuf>, settings: Settings) -> std::io::Result<()> {
+    if let Some(command) = &settings.startup_command {
+        if parse_startup_command(command, settings.literal_search).is_none() {
+            return Err(std::io::Error::new(
+                ErrorKind::InvalidInput,
+                format!("Unknown command \"+{}\"", command),
+            ));
+        }
+    }
+    let mut files = FileList::new(filenames);
+    let opened = match &settings.exec {
+        Some(command) => Some(open_command(command, &settings)?),
+        None => open_file(files.current(), &settings, true)?,
+    };
+    let (mut file, mut paged_reader) = match opened {
+        Some(opened) => opened,
+        None => return Ok(()),
+    };
+    // The output of a command is yet to come.
+    if settings.quit_if_one_screen && files.position().is_none() && file.command.is_none() {
+        let (cols, rows) = terminal_size().unwrap_or((80, 80));
+        if paged_reader.fits_in(rows, cols) {
+            return paged_reader.write_all(&mut stdout());
+        }
+    }
+
+    let raw_screen = stdout().into_raw_mode()?;
+    let screen: Box<dyn Write> = if settings.no_alt_screen {
+        Box::new(raw_screen)
+    } else {
+        Box::new(AlternateScreen::from(raw_screen))
+    };
     let mut screen = termion::cursor::HideCursor::from(screen);
 
     let (sender, receiver) = crossbeam_channel::bounded(100);
-    let mmap = if let Some(filename) = filename {
-        let file_size = std::fs::metadata(&filename)?.len();
-        if file_size > 0 {
-            let file = File::open(filename)?;
-            unsafe { Mmap::map(&file).expect("failed to map the file") }
+    let mut screen_move_handler = view_of(paged_reader, &file, &settings, settings.line_numbers);
+    let help = help_text(&settings.keys);
+    // The file's screen_move_handler, while the help is shown.
+    let mut file_view: Option<ScreenMoveHandler> = None;
+    // The other files shown, as they were left, by their index in files.
+    let mut left_files: HashMap<usize, (OpenFile, ScreenMoveHandler)> = HashMap::new();
+    spawn_key_pressed_handler(
+        sender.clone(),
+        settings.keys.clone(),
+        settings.literal_search,
+    );
+    spawn_signal_handler(sender.clone());
+    // Asked again only when the terminal is resized: it's a syscall.
+    let (mut cols, mut screen_rows) = terminal_size().unwrap_or((80, 80));
+    let rows = page_rows(screen_rows);
+
+    let initial_screen = first_page(&mut screen_move_handler, &settings, rows, cols)?;
+    // Only the rows that changed are written again.
+    let mut frame = Frame::default();
+    // Each frame is written at once: over ssh, every write is a round trip.
+    let mut output = Vec::new();
+    write_screen(&mut output, &mut frame, initial_screen, (cols, screen_rows))?;
+    write_status_line(
+        &mut output,
+        &mut screen_move_handler,
+        &settings.prompt,
+        file.name.as_deref(),
+        files.position(),
+        screen_rows,
+        cols,
+    )?;
+
+    // Turns the spinner while waiting for the first page of a stream.
+    let mut ticks = 0;
+    let waiting_notice = file.waiting_notice(rows, ticks);
+    if let Some(notice) = &waiting_notice {
+        write_notice(&mut output, notice, screen_rows, cols)?;
+    }
+    write_frame(&mut screen, &mut output)?;
+    // A notice is shown in place of the status line until the next key.
+    let mut notice_shown = waiting_notice.is_some();
+    // Following the file with F.
+    let mut following = false;
+    // When searches and filters ignore the case, with -i or -I.
+    let mut ignore_case = settings.ignore_case;
+    // The search running in the background, in a big file.
+    let mut searching: Option<RunningSearch> = None;
+    // The message received after scrolls that were merged, to handle next.
+    let mut pending = None;
+    loop {
+        // The file is checked for new lines every FOLLOW_INTERVAL.
+        let received = match pending.take() {
+            Some(message) => Ok(message),
+            None if following || file.streaming() || screen_move_handler.indexing_tail() => {
+                receiver
+                    .recv_timeout(FOLLOW_INTERVAL)
+                    .or_else(|error| match error {
+                        RecvTimeoutError::Timeout => Ok(Message::Tick),
+                        RecvTimeoutError::Disconnected => Err(RecvError),
+                    })
+            }
+            None => receiver.recv(),
+        };
+        let mut message = match received {
+            Ok(message) => message,
+            Err(RecvError) => break,
+        };
+        // The scrolls queued while a key is held are one, instead of scrolling long after
+        // it's released.
+        while let Ok(next) = receiver.try_recv() {
+            match message.merge(&next) {
+                Some(merged) => message = merged,
+                None => {
+                    pending = Some(next);
+                    break;
+                }
+            }
+        }
+        // SIGWINCH sends Reload.
+        if let Message::Reload = message {
+            let (new_cols, new_screen_rows) = terminal_size().unwrap_or((80, 80));
+            cols = new_cols;
+            screen_rows = new_screen_rows;
+        }
+        let rows = page_rows(screen_rows);
+        // Moving from the last page of a big file, shown before the lines before it were
+        // indexed, waits for them.
+        if !matches!(message, Message::Tick | Message::Reload | Message::Exit) {
+            screen_move_handler.settle_tail();
+        }
+        // Touching the pages a truncation took away from the mapped file would kill lesser
+        // with SIGBUS, so it's paged again first. F and the ticks look at the file anyway.
+        let mut truncated = None;
+        if !matches!(message, Message::Follow | Message::Tick) {
+            let view = file_view.as_mut().unwrap_or(&mut screen_move_handler);
+            truncated = file
+                .source
+                .as_mut()
+                .and_then(|source| guard_truncation(source, view));
+            // Instead of the key, the page is shown again, where there's still text.
+            if truncated.is_some() && file_view.is_none() {
+                message = Message::Reload;
+            }
+        }
+        // After a resize, or R, the whole screen is written again.
+        if matches!(message, Message::Reload | Message::ReloadFile) {
+            frame.invalidate();
+        }
+        // Any key stops following, but resizing the terminal doesn't.
+        let was_following = following;
+        following =
+            was_following && matches!(message, Message::Follow | Message::Tick | Message::Reload);
+        if let Message::Tick = message {
+            ticks += 1;
+        }
+        // With -e and -E, going forward from the end of the text shows the next file, or
+        // quits after the last one.
+        let forward = file_view.is_none()
+            && settings.quit_at_eof != QuitAtEof::Never
+            && matches!(
+                message,
+                Message::ScrollDown(_)
+                    | Message::ScrollDownPage(_)
+                    | Message::ScrollDownHalfPage(_)
+                    | Message::ScrollToEnd
+            )
+            && file.complete();
+        if forward && screen_move_handler.at_end(rows) {
+            match files.next_index(1) {
+                Some(_) => message = Message::NextFile,
+                None => break,
+            }
+        }
+        let message = ignoring_case(message, ignore_case);
+        let not_moved_notice = not_moved_notice(&message);
+        let mut notice: Option<String> = truncated;
+        // Any key cancels the search, interrupts too, and the page stays where it was. A key
+        // opening the prompt still opens it.
+        let search_cancelled = searching.is_some()
+            && !matches!(
+                message,
+                Message::Reload
+                    | Message::Tick
+                    | Message::SearchProgress(..)
+                    | Message::SearchDone(..)
+            );
+        if search_cancelled {
+            searching = None;
+            notice = Some("Search cancelled".to_owned());
+        }
+        // The file to show instead, and where it is in the list, or goes if it's new.
+        let file_at = |index: usize| (files.get(index).to_path_buf(), index);
+        let file_target = match &message {
+            Message::NextFile => files.next_index(1).map(file_at),
+            Message::PreviousFile => files.previous_index(1).map(file_at),
+            Message::Examine(path) => Some((path.clone(), files.len())),
+            // The file on the jump target, in a directory listing.
+            Message::Open(_) if file_view.is_none() => {
+                let line = screen_move_handler.target_line(rows);
+                let listing = file.listing.as_ref().zip(line);
+                let path = listing.and_then(|(paths, line)| paths.get(line as usize));
+                path.map(|path| (path.clone(), files.len()))
+            }
+            _ => None,
+        };
+        let page = match message {
+            _ if search_cancelled && !matches!(message, Message::Prompt(Some(_))) => {
+                screen_move_handler.redraw(rows, cols)?
+            }
+            Message::ScrollUpPage(count) => screen_move_handler.move_up_page(count, rows, cols)?,
+            Message::ScrollDownPage(count) => {
+                screen_move_handler.move_down_page(count, rows, cols)?
+            }
+            Message::ScrollLeft(count) => screen_move_handler.move_left(count, rows, cols)?,
+            Message::ScrollRight(count) => screen_move_handler.move_right(count, rows, cols)?,
+            Message::ScrollDownHalfPage(size) => {
+                screen_move_handler.move_down_half_page(size, rows, cols)?
+            }
+            Message::ScrollUpHalfPage(size) => {
+                screen_move_handler.move_up_half_page(size, rows, cols)?
+            }
+            Message::ScrollUp(count) => screen_move_handler.move_up(count, rows, cols)?,
+            Message::ScrollDown(count) => screen_move_handler.move_down(count, rows, cols)?,
+            Message::ScrollToBeginning => screen_move_handler.move_to_beginning(rows, cols)?,
+            Message::ScrollToEnd => screen_move_handler.move_to_end(rows, cols)?,
+            Message::GotoLine(line) => screen_move_handler.goto_line(line, rows, cols)?,
+            Message::GotoPercent(percent) => {
+                screen_move_handler.goto_percent(percent, rows, cols)?
+            }
+            // The file could have changed too, while the terminal was being resized.
+            Message::Reload => {
+                if let (Some(source), None, false) = (&mut file.source, &file_view, following) {
+                    notice = reload_notice(source, &mut screen_move_handler).or(notice);
+                }
+                screen_move_handler.reload(rows, cols)?
+            }
+            Message::ToggleLineNumbers => screen_move_handler.toggle_line_numbers(rows, cols)?,
+            Message::Filter(pattern) => {
+                match build_filter(pattern.clone()) {
+                    Ok(Some(filter)) => screen_move_handler.add_filter(filter),
+                    Ok(None) => screen_move_handler.clear_filters(),
+                    Err(error) => notice = Some(format!("Invalid pattern: {}", error)),
+                }
+                screen_move_handler.redraw(rows, cols)?
+            }
+            Message::Search { pattern, .. } if pattern.text.is_empty() => search_next(
+                &mut screen_move_handler,
+                false,
+                1,
+                (rows, cols),
+                &mut searching,
+                &sender,
+            )?,
+            Message::Search { pattern, backwards } => match pattern.to_regex() {
+                Ok(regex) => {
+                    screen_move_handler.set_search(regex, backwards);
+                    search_next(
+                        &mut screen_move_handler,
+                        false,
+                        1,
+                        (rows, cols),
+                        &mut searching,
+                        &sender,
+                    )?
+                }
+                Err(error) => {
+                    notice = Some(format!("Invalid pattern: {}", error));
+                    screen_move_handler.redraw(rows, cols)?
+                }
+            },
+            Message::SearchNext(count) => search_next(
+                &mut screen_move_handler,
+                false,
+                count,
+                (rows, cols),
+                &mut searching,
+                &sender,
+            )?,
+            Message::SearchPrevious(count) => search_next(
+                &mut screen_move_handler,
+                true,
+                count,
+                (rows, cols),
+                &mut searching,
+                &sender,
+            )?,
+            // Only the status line changes.
+            Message::SearchProgress(id, percent) => match &mut searching {
+                Some(search) if search.id() == id => {
+                    search.set_percent(percent);
+                    None
+                }
+                _ => continue,
+            },
+            Message::SearchDone(id, found) => match &searching {
+                Some(search) if search.id() == id => {
+                    searching = None;
+                    match found {
+                        Some(offset) => screen_move_handler.jump_to_offset(offset, rows, cols)?,
+                        None => None,
+                    }
+                }
+                // Cancelled before it was done.
+                _ => continue,
+            },
+            Message::ToggleOption('N') | Message::ToggleOption('n') => {
+                let page = screen_move_handler.toggle_line_numbers(rows, cols)?;
+                let state = if screen_move_handler.line_numbers() {
+                    "on"
+                } else {
+                    "off"
+                };
+                notice = Some(format!("Line numbers {}", state));
+                page
+            }
+            // Like less's -S, chopping the long lines.
+            Message::ToggleOption('S') => {
+                let page = screen_move_handler.toggle_wrap(rows, cols)?;
+                let state = if screen_move_handler.wrap() {
+                    "wrapped"
+                } else {
+                    "cut"
+                };
+                notice = Some(format!("Long lines {}", state));
+                page
+            }
+            // Like less's -R, passing the colors to the terminal.
+            Message::ToggleOption('R') => {
+                let page = screen_move_handler.toggle_raw_control_chars(rows, cols)?;
+                let state = if screen_move_handler.raw_control_chars() {
+                    "shown"
+                } else {
+                    "escaped"
+                };
+                notice = Some(format!("Colors {}", state));
+                page
+            }
+            // The searches and filters done from now on.
+            Message::ToggleOption(option @ ('i' | 'I')) => {
+                let toggled = if option == 'i' {
+                    IgnoreCase::Smart
+                } else {
+                    IgnoreCase::Always
+                };
+                ignore_case = if ignore_case == toggled {
+                    IgnoreCase::Never
+                } else {
+                    toggled
+                };
+                notice = Some(
+                    match ignore_case {
+                        IgnoreCase::Never => "Case matched in searches",
+                        IgnoreCase::Smart => "Case ignored in searches without capitals",
+                        IgnoreCase::Always => "Case ignored in searches",
+                    }
+                    .to_owned(),
+                );
+                screen_move_handler.redraw(rows, cols)?
+            }
+            Message::ToggleOption(option) => {
+                notice = Some(format!("There is no -{} option", option));
+                screen_move_handler.redraw(rows, cols)?
+            }
+            Message::SetMark(mark) if mark.is_ascii_alphabetic() => {
+                screen_move_handler.set_mark(mark);
+                screen_move_handler.redraw(rows, cols)?
+            }
+            Message::SetMark(_) => {
+                notice = Some("Marks are letters".to_owned());
+                screen_move_handler.redraw(rows, cols)?
+            }
+            Message::GotoMark(mark) => screen_move_handler.goto_mark(mark, rows, cols)?,
+            Message::JumpBack(count) => screen_move_handler.jump_back(count, rows, cols)?,
+            Message::JumpForward(count) => screen_move_handler.jump_forward(count, rows, cols)?,
+            Message::FormFeed { count, backwards } => {
+                screen_move_handler.goto_form_feed(count, backwards, rows, cols)?
+            }
+            Message::ToggleFold => screen_move_handler.toggle_fold(rows, cols)?,
+            Message::FoldLevel(level) => screen_move_handler.fold_level(level, rows, cols)?,
+            Message::ToggleMarkdown => screen_move_handler.toggle_markdown(rows, cols)?,
+            Message::ToggleHex => screen_move_handler.toggle_hex(rows, cols)?,
+            Message::ToggleWhitespace => screen_move_handler.toggle_whitespace(rows, cols)?,
+            Message::ToggleWrap => screen_move_handler.toggle_wrap(rows, cols)?,
+            Message::Prompt(Some(prompt)) => {
+                write_prompt(&mut screen, &prompt, screen_rows, cols)?;
+                continue;
+            }
+            Message::Prompt(None) => screen_move_handler.redraw(rows, cols)?,
+            // Enter moves down, out of directory listings.
+            Message::Open(count) if file_target.is_none() => {
+                screen_move_handler.move_down(count, rows, cols)?
+            }
+            Message::NextFile | Message::PreviousFile | Message::Examine(_)
+                if file_view.is_some() =>
+            {
+                continue
+            }
+            Message::NextFile | Message::PreviousFile | Message::Examine(_) | Message::Open(_) => {
+                match file_target {
+                    Some((path, index)) => {
+                        // A file shown before comes back as it was left: position, search and
+                        // filters.
+                        let left = left_files.remove(&index);
+                        let was_left = left.is_some();
+                        let switched = match left {
+                            Some(left) => Ok(Some(left)),
+                            None => open_file(Some(&path), &settings, false).map(|opened| {
+                                opened.map(|(opened, paged_reader)| {
+                                    if paged_reader.hex() && !settings.hex {
+                                        notice =
+                                            Some("May be a binary file, shown in hex".to_owned());
+                                    }
+                                    let line_numbers = screen_move_handler.line_numbers();
+                                    let view =
+                                        view_of(paged_reader, &opened, &settings, line_numbers);
+                                    (opened, view)
+                                })
+                            }),
+                        };
+                        match switched {
+                            Ok(Some((opened, view))) => {
+                                let left = (
+                                    std::mem::replace(&mut file, opened),
+                                    std::mem::replace(&mut screen_move_handler, view),
+                                );
+                                // The standard input has no place in files to come back to.
+                                if files.current().is_some() {
+                                    left_files.insert(files.index(), left);
+                                }
+                                if index == files.len() {
+                                    files.push(path);
+                                }
+                                files.set_current(index);
+                                if was_left {
+                                    // It could have been truncated while it was left.
+                                    let truncated = file.source.as_mut().and_then(|source| {
+                                        guard_truncation(source, &mut screen_move_handler)
+                                    });
+                                    notice = truncated.or(notice);
+                                    screen_move_handler.reload(rows, cols)?
+                                } else {
+                                    first_page(&mut screen_move_handler, &settings, rows, cols)?
+                                }
+                            }
+                            // Only at startup there can be nothing to show.
+                            Ok(None) => screen_move_handler.redraw(rows, cols)?,
+                            Err(error) => {
+                                notice = Some(format!("Can't open {}: {}", path.display(), error));
+                                screen_move_handler.redraw(rows, cols)?
+                            }
+                        }
+                    }
+                    None => None,
+                }
+            }
+            Message::Follow | Message::Tick | Message::ReloadFile if file_view.is_some() => {
+                continue
+            }
+            // The lines before the last page are indexed: its position is known.
+            Message::Tick if screen_move_handler.tail_indexed() => {
+                screen_move_handler.redraw(rows, cols)?
+            }
+            // New text coming to a stream shows up, and the page stays where it is.
+            Message::Tick if !following => match &mut file.source {
+                Some(source) => match recheck(source, &mut screen_move_handler) {
+                    // The spinner turns while waiting.
+                    Ok(FileChange::Same) if file.waiting_notice(rows, ticks).is_some() => None,
+                    Ok(FileChange::Same) => continue,
+                    Ok(_) => screen_move_handler.redraw(rows, cols)?,
+                    Err(error) => {
+                        debug!("Can't read the stream: {}", error);
+                        continue;
+                    }
+                },
+                None => continue,
+            },
+            Message::Follow | Message::Tick => match &mut file.source {
+                Some(source) => match recheck(source, &mut screen_move_handler) {
+                    // Nothing new since the last look.
+                    Ok(FileChange::Same) if was_following => continue,
+                    Ok(change) => {
+                        following = true;
+                        notice = match change {
+                            FileChange::Truncated => Some("File truncated".to_owned()),
+                            FileChange::Replaced => {
+                                Some("File replaced, following the new one".to_owned())
+                            }
+                            FileChange::Same | FileChange::Grown | FileChange::Dropped => None,
+                        };
+                        match screen_move_handler.follow_end(rows, cols)? {
+                            Some(page) => Some(page),
+                            None => screen_move_handler.redraw(rows, cols)?,
+                        }
+                    }
+                    Err(error) => {
+                        following = false;
+                        notice = Some(format!("Can't follow the file: {}", error));
+                        screen_move_handler.redraw(rows, cols)?
+                    }
+                },
+                None => {
+                    notice = Some("Only files shown as they are can be followed".to_owned());
+                    screen_move_handler.redraw(rows, cols)?
+                }
+            },
+            Message::ReloadFile => match file.command.clone() {
+                Some(command) => match open_command(&command, &settings) {
+                    Ok((opened, paged_reader)) => {
+                        let line_numbers = screen_move_handler.line_numbers();
+                        screen_move_handler =
+                            view_of(paged_reader, &opened, &settings, line_numbers);
+                        // The command is killed, if it's still running.
+                        file = opened;
+                        screen_move_handler.redraw(rows, cols)?
+                    }
+                    Err(error) => {
+                        notice = Some(format!("Can't run {}: {}", command, error));
+                        screen_move_handler.redraw(rows, cols)?
+                    }
+                },
+                None => {
+                    notice = match &mut file.source {
+                        Some(source) => reload_notice(source, &mut screen_move_handler)
+                            .or_else(|| Some("File unchanged".to_owned())),
+                        None => Some("Only files shown as they are can be reloaded".to_owned()),
+                    };
+                    screen_move_handler.reload(rows, cols)?
+                }
+            },
+            Message::Help if file_view.is_some() => continue,
+            Message::Help => {
+                let help_reader = PagedReader::new(mmap_of(&help)?);
+                let help_view = ScreenMoveHandler::new(help_reader);
+                file_view = Some(std::mem::replace(&mut screen_move_handler, help_view));
+                screen_move_handler.initial_screen(rows, cols)?
+            }
+            // Any key clears the notice.
+            Message::Empty if notice_shown => screen_move_handler.redraw(rows, cols)?,
+            Message::Empty => continue,
+            // Like in less, an interrupt stops following instead of quitting.
+            Message::Exit if was_following => screen_move_handler.redraw(rows, cols)?,
+            // Quitting the help goes back to the file, where it was.
+            Message::Exit => match file_view.take() {
+                Some(view) => {
+                    screen_move_handler = view;
+                    screen_move_handler.redraw(rows, cols)?
+                }
+                None => break,
+            },
+        };
+        // The cursor is only shown while typing in the prompt.
+        write!(output, "{}", termion::cursor::Hide)?;
+        let notice = notice.or_else(|| not_moved_notice.filter(|_| page.is_none()));
+        let notice = notice
+            .or_else(|| following.then(|| "Waiting for data... (any key to stop)".to_owned()))
+            .or_else(|| {
+                searching
+                    .as_ref()
+                    .map(|search| format!("Searching... {}% (any key to stop)", search.percent()))
+            })
+            .or_else(|| {
+                file_view
+                    .is_none()
+                    .then(|| file.waiting_notice(rows, ticks))
+                    .flatten()
+            });
+        // The notice says why nothing happened, no need for the bell too.
+        if page.is_some() || notice.is_none() {
+            write_screen(&mut output, &mut frame, page, (cols, screen_rows))?;
+        }
+        let (status_name, file_position) = match file_view {
+            Some(_) => (Some("Help"), None),
+            None => (file.name.as_deref(), files.position()),
+        };
+        write_status_line(
+            &mut output,
+            &mut screen_move_handler,
+            &settings.prompt,
+            status_name,
+            file_position,
+            screen_rows,
+            cols,
+        )?;
+        if let Some(notice) = &notice {
+            write_notice(&mut output, notice, screen_rows, cols)?;
+        }
+        write_frame(&mut screen, &mut output)?;
+        notice_shown = notice.is_some();
+        // -E doesn't wait: the end of the last file was just shown.
+        if forward
+            && settings.quit_at_eof == QuitAtEof::First
+            && files.next_index(1).is_none()
+            && screen_move_handler.at_end(rows)
+        {
+            break;
+        }
+    }
+    if let Some(view) = file_view {
+        screen_move_handler = view;
+    }
+    save_position(&file, &mut screen_move_handler);
+    if settings.index_cache {
+        save_index(&file, &screen_move_handler);
+    }
+    for (file, mut screen_move_handler) in left_files.into_values() {
+        save_position(&file, &mut screen_move_handler);
+        if settings.index_cache {
+            save_index(&file, &screen_move_handler);
+        }
+    }
+    if settings.no_alt_screen {
+        // The page stays on the terminal: leave the last row for the shell prompt.
+        write_last_row(&mut screen, screen_rows, "")?;
+        screen.flush()?;
+    }
+    Ok(())
+}
+
+/// A file being paged.
+struct OpenFile {
+    /// None for the standard input.
+    name: Option<String>,
+    /// Where the marks and the position are saved for the next time. Not for pipes.
+    state_key: Option<PathBuf>,
+    /// For a directory, the file on each line of its listing, to open with Enter.
+    listing: Option<Vec<PathBuf>>,
+    /// The file paged as it is, or the text still coming, to page it again as it grows. None
+    /// for the text made from a file (decompressed, JSON, tables...).
+    source: Option<Source>,
+    /// The --exec command, to run it again.
+    command: Option<String>,
+    /// Runs LESSCLOSE when the file is closed.
+    _less_close: Option<LessClose>,
+}
+
+impl OpenFile {
+    /// The stream its text comes from, if it's still coming, from a pipe or a command.
+    fn stream(&self) -> Option<&Stream> {
+        match &self.source {
+            Some(Source {
+                input: Input::Stream { stream, .. },
+                ..
+            }) => Some(stream),
+            _ => None,
+        }
+    }
+
+    fn streaming(&self) -> bool {
+        self.stream().is_some()
+    }
+
+    /// If there's no more text to come: it's not piped, or the pipe ended and all of it is
+    /// paged.
+    fn complete(&self) -> bool {
+        match &self.source {
+            Some(Source {
+                input: Input::Stream { stream, dropped },
+                size,
+            }) => stream.done() && stream.len() as u64 == size + *dropped as u64,
+            _ => true,
+        }
+    }
+
+    /// While its text hasn't filled the page yet, a spinner and how much came, so that it
+    /// doesn't look hung. ticks turns the spinner.
+    fn waiting_notice(&self, rows: u16, ticks: usize) -> Option<String> {
+        let received = self.stream()?.waiting_for(rows as usize)?;
+        let spinner = ['|', '/', '-', '\\'][ticks % 4];
+        Some(format!(
+            "{} Waiting for data... {}B received",
+            spinner,
+            human_size(received as u64)
+        ))
+    }
+}
+
+/// Shows the text of paged_reader as the settings say: how the control chars, the tabs, the
+/// whitespace and the long lines look. The same for the files, the listings and the commands.
+fn apply_settings(paged_reader: &mut PagedReader, settings: &Settings) {
+    paged_reader.set_control_chars(settings.control_chars);
+    paged_reader.set_tab_width(settings.tab_width);
+    paged_reader.set_control_notation(settings.control_notation);
+    paged_reader.set_whitespace(settings.show_whitespace);
+    // The width of the screen is known when the first page is read.
+    paged_reader.set_wrap(settings.wrap.then_some(0));
+    paged_reader.set_wrap_words(settings.wrap_words);
+    paged_reader.set_wrap_marker(settings.wrap_marker.clone());
+}
+
+/// Opens filename, or the standard input if None or `-`, to page it. None if there's nothing to
+/// show. At startup, before the pager takes the screen, binary files are shown only if the
+/// user wants to, and empty files not at all with -F. Later binary files are shown in hex.
+fn open_file(
+    filename: Option<&Path>,
+    settings: &Settings,
+    startup: bool,
+) -> std::io::Result<Option<(OpenFile, PagedReader)>> {
+    let filename = filename.filter(|filename| *filename != Path::new("-"));
+    let mut path = filename.map(Path::to_path_buf);
+    let name = filename.map(|filename| filename.display().to_string());
+    let state_key = filename.and_then(|filename| fs::canonicalize(filename).ok());
+    // Directories are paged as a listing of their files, like in a file browser.
+    if let Some(dir) = filename.filter(|filename| filename.is_dir()) {
+        let listing = list(dir)?;
+        let mut paged_reader = PagedReader::new(mmap_of(&listing.text)?);
+        apply_settings(&mut paged_reader, settings);
+        let file = OpenFile {
+            name,
+            state_key,
+            listing: Some(listing.paths),
+            source: None,
+            command: None,
+            _less_close: None,
+        };
+        return Ok(Some((file, paged_reader)));
+    }
+    let lessopen = settings.lessopen.as_deref().and_then(LessOpen::parse);
+    let preprocessed = match (&lessopen, &name) {
+        (Some(lessopen), Some(name)) => preprocess(lessopen, name, settings.buffer_max)?,
+        (Some(lessopen), None) if lessopen.stdin && !is_tty(&stdin()) => {
+            preprocess(lessopen, "-", settings.buffer_max)?
+        }
+        _ => None,
+    };
+    // Runs LESSCLOSE when lesser is done with the replacement, on any return.
+    let _less_close = match (&preprocessed, &settings.lessclose) {
+        (Some(preprocessed), Some(lessclose)) => Some(LessClose::new(
+            lessclose,
+            name.as_deref().unwrap_or("-"),
+            &preprocessed.name(),
+        )),
+        _ => None,
+    };
+    let (preprocessed_text, filename) = match preprocessed {
+        Some(Preprocessed::Text(text)) => (Some(text), None),
+        Some(Preprocessed::File(replacement)) => (None, Some(replacement)),
+        None => (None, path.clone()),
+    };
+    let mut source = None;
+    let mut mmap = if let Some(text) = preprocessed_text {
+        text
+    } else if let Some(filename) = filename {
+        let metadata = std::fs::metadata(&filename)?;
+        // Like less, a FIFO is waited for until it has a writer.
+        let file = File::open(&filename)?;
+        let mapped = if metadata.is_file() && metadata.len() > 0 {
+            Text::map(&file).ok()
         } else {
-            MmapMut::map_anon(1).expect("Anon mmap").make_read_only()?
+            None
+        };
+        if let Some(mapped) = mapped {
+            match Compression::of(&mapped.head(BINARY_CHECK_BYTES)) {
+                // Paged like zless does. The format of the text in it is known by the name
+                // without the extension of the compression.
+                Some(compression) => {
+                    path = path.map(|path| decompressed_path(&path));
+                    match decompress(file, compression, settings.buffer_max)? {
+                        Some(text) => text,
+                        None => mmap_of("\n")?.into(),
+                    }
+                }
+                None => {
+                    source = Some(Source {
+                        input: Input::File {
+                            path: filename,
+                            id: file_id(&metadata),
+                        },
+                        size: mapped.len() as u64,
+                    });
+                    mapped
+                }
+            }
+        } else if !metadata.is_file() || metadata.len() > 0 {
+            // FIFOs, devices, and files that can't be mapped, like on some network
+            // filesystems, are read as they come, like a pipe.
+            let (text, stream_source) = open_stream(file, settings, startup)?;
+            source = Some(stream_source);
+            text
+        } else if let Some(text) = read_all(file, settings.buffer_max)? {
+            // Files in /proc say they're empty, and they're not.
+            text
+        } else if settings.quit_if_one_screen && startup {
+            // Nothing to show.
+            return Ok(None);
+        } else {
+            source = Some(Source {
+                input: Input::File {
+                    path: filename,
+                    id: file_id(&metadata),
+                },
+                size: 0,
+            });
+            // A newline, or the byte of an empty map would be shown.
+            mmap_of("\n")?.into()
         }
     } else if !is_tty(&stdin()) {
-        read_all_from_pipe()
+        let (text, piped_source) = open_stream(stdin(), settings, startup)?;
+        source = Some(piped_source);
+        text
     } else {
         // Error, must specify an input!
         return Err(std::io::Error::new(
@@ -40,68 +961,696 @@ pub fn run(filename: Option<PathBuf>) -> std::io::Result<()> {
             "Missing filename (\"lesser --help\" for help)",
         ));
     };
+    // Text in another encoding, like UTF-16 or Latin-1, is paged as UTF-8, made from the whole
+    // text.
+    let encoding = match settings.encoding {
+        Some(encoding) => Some(encoding),
+        None => detect(&mmap.head(BINARY_CHECK_BYTES)),
+    };
+    if let Some(encoding) = encoding.filter(|encoding| *encoding != UTF_8) {
+        if let Some(Source {
+            input: Input::Stream { stream: piped, .. },
+            ..
+        }) = &source
+        {
+            piped.wait_for_end();
+            if let Some(text) = piped.text()? {
+                mmap = text;
+            }
+        }
+        debug!("Paging the text as {}", encoding.name());
+        let decoded = mmap_of(&encoding.decode_with_bom_removal(&mmap.all()).0)?;
+        mmap = decoded.into();
+        source = None;
+    }
+    // Like less, ask before showing a binary file: its control chars could mess up the
+    // terminal.
+    let hex = if !settings.hex && !settings.force && looks_binary(&mmap.head(BINARY_CHECK_BYTES)) {
+        if startup {
+            match ask_about_binary(name.as_deref().unwrap_or("Standard input"))? {
+                Some(hex) => hex,
+                None => return Ok(None),
+            }
+        } else {
+            true
+        }
+    } else {
+        settings.hex
+    };
+
+    // JSON is paged pretty printed, and its objects and arrays can be folded.
+    let is_json = match &path {
+        Some(path) => path.extension() == Some("json".as_ref()),
+        None => looks_like_json(&mmap.head(BINARY_CHECK_BYTES)),
+    };
+    // JSON and tables are made from the whole text.
+    if let Some(Source {
+        input: Input::Stream { stream: piped, .. },
+        ..
+    }) = &source
+    {
+        if settings.json || is_json || settings.table {
+            piped.wait_for_end();
+            if let Some(text) = piped.text()? {
+                mmap = text;
+            }
+        }
+    }
+    let pretty_json = if settings.json || is_json {
+        match pretty_print(&mmap.all()) {
+            Ok(pretty_json) => Some(pretty_json),
+            Err(error) if settings.json => {
+                return Err(std::io::Error::new(
+                    ErrorKind::InvalidData,
+                    format!("Not valid JSON: {}", error),
+                ))
+            }
+            Err(error) => {
+                debug!("Not paging as JSON: {}", error);
+                None
+            }
+        }
+    } else {
+        None
+    };
+    let json = pretty_json.is_some();
+    if json {
+        source = None;
+    }
+    // CSV and TSV are paged with their columns aligned.
+    let separator = match path.as_deref().and_then(separator_of) {
+        Some(separator) if !json => Some(separator),
+        _ if settings.table && !json => Some(guess_separator(&mmap.all())),
+        _ => None,
+    };
+    let mut paged_reader = match (pretty_json, separator) {
+        (Some(pretty_json), _) => {
+            let mut paged_reader = PagedReader::new(mmap_of(&pretty_json.text)?);
+            paged_reader.set_blocks(pretty_json.blocks);
+            paged_reader
+        }
+        (None, Some(separator)) => {
+            source = None;
+            let table = align(&mmap.all(), separator);
+            let mut paged_reader = PagedReader::new(mmap_of(&table.text)?);
+            paged_reader.set_column_starts(table.column_starts);
+            paged_reader.set_pinned_header(settings.table_header);
+            paged_reader
+        }
+        (None, None) => PagedReader::new(mmap),
+    };
+    apply_settings(&mut paged_reader, settings);
+    paged_reader.set_separator(settings.separator);
+    let first_line = paged_reader.first_line().into_owned();
+    let syntax_highlighter = if settings.syntax_highlighting && !settings.diff && !json {
+        SyntaxHighlighter::for_file(path.as_deref(), &first_line)
+    } else {
+        None
+    };
+    // .log files, or piped text that looks like a log.
+    let is_log = match &path {
+        Some(path) => path.extension() == Some("log".as_ref()),
+        None => has_log_level(&settings.log_levels, &first_line),
+    };
+    let markdown = matches!(path.as_deref(), Some(path) if is_markdown(path));
+    let highlighter = match syntax_highlighter {
+        // The markup can be shown as it is, colored by its syntax, or rendered.
+        plain if markdown => Some(Highlighter::Markdown(MarkdownRenderer::new(
+            settings.render_markdown,
+            plain,
+        ))),
+        Some(highlighter) => Some(Highlighter::Syntax(highlighter)),
+        None if json => settings.syntax_highlighting.then_some(Highlighter::Json),
+        None if settings.diff || looks_like_diff(&first_line) => Some(Highlighter::Diff),
+        None if is_log && !settings.log_levels.is_empty() => {
+            Some(Highlighter::LogLevels(settings.log_levels.clone()))
+        }
+        None => None,
+    };
+    paged_reader.set_highlighter(highlighter);
+    paged_reader.set_hex(hex);
+    // A big file indexed the last time it was opened isn't indexed again.
+    if let (true, false, Some(source)) = (settings.index_cache, hex, &source) {
+        if let Input::File { path, .. } = &source.input {
+            if let Some(index) = index_cache::load(
+                path,
+                source.size,
+                paged_reader.text(),
+                paged_reader.newline(),
+            ) {
+                paged_reader.set_line_index(index);
+            }
+        }
+    }
+    Ok(Some((
+        OpenFile {
+            name,
+            state_key,
+            listing: None,
+            source,
+            command: None,
+            _less_close,
+        },
+        paged_reader,
+    )))
+}
 
-    let paged_reader = PagedReader::new(mmap);
-    let mut screen_move_handler: ScreenMoveHandler = ScreenMoveHandler::new(paged_reader);
-    spawn_key_pressed_handler(sender.clone());
-    spawn_signal_handler(sender);
-    let (cols, rows) = terminal_size().unwrap_or_else(|_| (80, 80));
+/// Text that can be paged again as it grows, like a log file or a pipe.
+struct Source {
+    input: Input,
+    /// How many bytes of it are paged.
+    size: u64,
+}
 
-    let initial_screen = screen_move_handler.initial_screen(rows, cols)?;
-    write_screen(&mut screen, initial_screen)?;
+enum Input {
+    /// A file paged as it is, looked at again by its name.
+    File {
+        path: PathBuf,
+        /// The device and inode of the file: when they change, another file took its name,
+        /// like when logs are rotated.
+        id: (u64, u64),
+    },
+    /// Text still coming, from a pipe or a command.
+    Stream {
+        stream: Stream,
+        /// How many bytes came before the paged text, and were dropped.
+        dropped: usize,
+    },
+}
 
-    for message in receiver {
-        let (cols, rows) = terminal_size().unwrap_or_else(|_| (80, 80));
-        let page = match message {
-            Message::ScrollUpPage => screen_move_handler.move_up_page(rows, cols)?,
-            Message::ScrollDownPage => screen_move_handler.move_down_page(rows, cols)?,
-            Message::ScrollLeft => screen_move_handler.move_left(rows, cols)?,
-            Message::ScrollRight => screen_move_handler.move_right(rows, cols)?,
-            Message::ScrollUp => screen_move_handler.move_up(rows, cols)?,
-            Message::ScrollDown => screen_move_handler.move_down(rows, cols)?,
-            Message::ScrollToBeginning => screen_move_handler.move_to_beginning(rows, cols)?,
-            Message::ScrollToEnd => screen_move_handler.move_to_end(rows, cols)?,
-            Message::Reload => screen_move_handler.reload(rows, cols)?,
-            Message::Empty => continue,
-            Message::Exit => break,
+/// How a followed file changed since the last look.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FileChange {
+    Same,
+    Grown,
+    /// It got smaller, it was rewritten.
+    Truncated,
+    /// Another file has its name now.
+    Replaced,
+    /// More text came to a stream, and the oldest was dropped to make room for it.
+    Dropped,
+}
+
+fn file_id(metadata: &fs::Metadata) -> (u64, u64) {
+    (metadata.dev(), metadata.ino())
+}
+
+/// Looks at source again, a file by its name like `tail -F`, and pages it again if it
+/// changed. After a rotation the new file is reopened, once it's there.
+fn recheck(
+    source: &mut Source,
+    screen_move_handler: &mut ScreenMoveHandler,
+) -> std::io::Result<FileChange> {
+    let (change, text) = match &mut source.input {
+        Input::Stream { stream, dropped } => {
+            if stream.len() as u64 == source.size + *dropped as u64 {
+                return Ok(FileChange::Same);
+            }
+            let (text, now_dropped) = stream.kept()?;
+            let change = if now_dropped == *dropped {
+                FileChange::Grown
+            } else {
+                FileChange::Dropped
+            };
+            *dropped = now_dropped;
+            (change, text)
+        }
+        Input::File { path, id } => {
+            let file = match File::open(path) {
+                Ok(file) => file,
+                // Moved away by a rotation: the new one is yet to come.
+                Err(error) if error.kind() == ErrorKind::NotFound => return Ok(FileChange::Same),
+                Err(error) => return Err(error),
+            };
+            let metadata = file.metadata()?;
+            let new_id = file_id(&metadata);
+            let change = if new_id != *id {
+                FileChange::Replaced
+            } else if metadata.len() < source.size {
+                FileChange::Truncated
+            } else if metadata.len() > source.size {
+                FileChange::Grown
+            } else {
+                return Ok(FileChange::Same);
+            };
+            *id = new_id;
+            let text = if metadata.len() > 0 {
+                Some(Text::map(&file)?)
+            } else {
+                None
+            };
+            (change, text)
+        }
+    };
+    // Only the lines of a file that grew are still the same.
+    let grown = change == FileChange::Grown && source.size > 0;
+    source.size = text.as_ref().map_or(0, |text| text.len() as u64);
+    let text = match text {
+        Some(text) => text,
+        None => mmap_of("\n")?.into(),
+    };
+    screen_move_handler.set_text(text, grown);
+    Ok(change)
+}
+
+/// If the file of source got shorter than the text paged, pages it again before the pages
+/// it lost are touched. The notice says so. The indexer and the searches in the background
+/// stop on their own, looking before each chunk they read: only a truncation while a chunk is
+/// read, or a page is shown, can still kill lesser.
+fn guard_truncation(
+    source: &mut Source,
+    screen_move_handler: &mut ScreenMoveHandler,
+) -> Option<String> {
+    let (path, id) = match &source.input {
+        Input::File { path, id } => (path, *id),
+        Input::Stream { .. } => return None,
+    };
+    // After a rotation the file paged is not the one with its name anymore: it's left as
+    // it is.
+    match fs::metadata(path) {
+        Ok(metadata) if file_id(&metadata) == id && metadata.len() < source.size => {}
+        _ => return None,
+    }
+    Some(match recheck(source, screen_move_handler) {
+        Ok(_) => "File truncated, shown again".to_owned(),
+        Err(error) => {
+            // Better nothing than the pages that are gone.
+            source.size = 0;
+            if let Ok(empty) = mmap_of("\n") {
+                screen_move_handler.set_text(empty.into(), false);
+            }
+            format!("File truncated, and it can't be read again: {}", error)
+        }
+    })
+}
+
+/// Looks at source again, for R or a resize, and says how it changed. None if it didn't.
+fn reload_notice(
+    source: &mut Source,
+    screen_move_handler: &mut ScreenMoveHandler,
+) -> Option<String> {
+    let old_size = source.size;
+    match recheck(source, screen_move_handler) {
+        Ok(FileChange::Grown) => Some(format!("File grew by {} bytes", source.size - old_size)),
+        Ok(FileChange::Truncated) => Some("File truncated".to_owned()),
+        Ok(FileChange::Replaced) => Some("File replaced, showing the new one".to_owned()),
+        Ok(FileChange::Same | FileChange::Dropped) => None,
+        Err(error) => Some(format!("Can't reload the file: {}", error)),
+    }
+}
+
+/// Reads input in the background, like a pipe. It's shown as soon as there's a page of it,
+/// the rest as it comes. A page and a line, to know if it fits in the screen for -F.
+/// Otherwise slow input shows what came so far, and that it's waiting for more.
+fn open_stream(
+    input: impl Read + Send + 'static,
+    settings: &Settings,
+    startup: bool,
+) -> std::io::Result<(Text, Source)> {
+    let newline = settings.separator.unwrap_or(b'\n');
+    let stream = Stream::spawn(input, settings.buffer_max, settings.overflow, newline);
+    let (_cols, rows) = terminal_size().unwrap_or((80, 80));
+    let timeout = (!(startup && settings.quit_if_one_screen)).then_some(FOLLOW_INTERVAL);
+    stream.wait_for_lines(rows as usize, timeout);
+    stream_source(stream)
+}
+
+/// The text that came from stream so far, and the stream to page the rest as it comes.
+fn stream_source(stream: Stream) -> std::io::Result<(Text, Source)> {
+    let (text, dropped) = stream.kept()?;
+    let size = text.as_ref().map_or(0, |text| text.len() as u64);
+    let text = match text {
+        Some(text) => text,
+        None => mmap_of("\n")?.into(),
+    };
+    let source = Source {
+        input: Input::Stream { stream, dropped },
+        size,
+    };
+    Ok((text, source))
+}
+
+/// Runs command, for --exec, to page its output as it comes.
+fn open_command(command: &str, settings: &Settings) -> std::io::Result<(OpenFile, PagedReader)> {
+    let newline = settings.separator.unwrap_or(b'\n');
+    let stream = Stream::command(command, settings.buffer_max, settings.overflow, newline)?;
+    // What came already, if anything: the rest shows up as it comes.
+    let (text, source) = stream_source(stream)?;
+    let mut paged_reader = PagedReader::new(text);
+    apply_settings(&mut paged_reader, settings);
+    paged_reader.set_separator(settings.separator);
+    paged_reader.set_hex(settings.hex);
+    let file = OpenFile {
+        name: Some(command.to_owned()),
+        state_key: None,
+        listing: None,
+        source: Some(source),
+        command: Some(command.to_owned()),
+        _less_close: None,
+    };
+    Ok((file, paged_reader))
+}
+
+/// Pages paged_reader, with the marks saved for file, from where it was left if resuming.
+fn view_of(
+    mut paged_reader: PagedReader,
+    file: &OpenFile,
+    settings: &Settings,
+    line_numbers: bool,
+) -> ScreenMoveHandler {
+    paged_reader.set_line_numbers(line_numbers);
+    let mut screen_move_handler = ScreenMoveHandler::new(paged_reader);
+    screen_move_handler.set_jump_target(settings.jump_target);
+    screen_move_handler.set_page_overlap(settings.page_overlap);
+    let saved_state = file
+        .state_key
+        .as_ref()
+        .and_then(|path| load_state().file(path).cloned())
+        .unwrap_or_default();
+    screen_move_handler.restore_line_marks(&saved_state.marks);
+    // A +command says where to start instead.
+    if settings.resume && settings.startup_command.is_none() {
+        if let Some((line, col)) = saved_state.position {
+            screen_move_handler.restore_position(line, col);
+        }
+    }
+    screen_move_handler
+}
+
+/// The first page of a file: where the +command says, if there's one.
+fn first_page(
+    screen_move_handler: &mut ScreenMoveHandler,
+    settings: &Settings,
+    rows: u16,
+    cols: u16,
+) -> std::io::Result<Option<String>> {
+    let startup_message = settings
+        .startup_command
+        .as_deref()
+        .and_then(|command| parse_startup_command(command, settings.literal_search));
+    match startup_message {
+        Some(message) => {
+            let message = ignoring_case(message, settings.ignore_case);
+            startup_page(screen_move_handler, message, rows, cols)
+        }
+        None => screen_move_handler.initial_screen(rows, cols),
+    }
+}
+
+/// Saves the marks and the position in file, for the next time it's opened.
+fn save_position(file: &OpenFile, screen_move_handler: &mut ScreenMoveHandler) {
+    if let Some(path) = &file.state_key {
+        let file_state = FileState {
+            marks: screen_move_handler.line_marks(),
+            position: screen_move_handler.top_position(),
         };
-        write_screen(&mut screen, page)?;
+        save_file_state(path.clone(), file_state);
+    }
+}
+
+/// Saves the lines indexed in file, if it's a big one, so that it's not indexed again the next
+/// time it's opened.
+fn save_index(file: &OpenFile, screen_move_handler: &ScreenMoveHandler) {
+    if let (Some(source), Some(index)) = (&file.source, screen_move_handler.line_index()) {
+        if let Input::File { path, .. } = &source.input {
+            index_cache::save(path, source.size, index);
+        }
+    }
+}
+
+/// Asks if the binary file called name should be shown anyway: Some(true) to show it in
+/// hex, Some(false) as text, None to quit.
+fn ask_about_binary(name: &str) -> std::io::Result<Option<bool>> {
+    let tty = OpenOptions::new().read(true).write(true).open("/dev/tty")?;
+    let mut tty_output = tty.try_clone()?.into_raw_mode()?;
+    write!(
+        tty_output,
+        "\"{}\" may be a binary file.  See it anyway? (y/n, x for hex) ",
+        name
+    )?;
+    tty_output.flush()?;
+    let key = tty.keys().next().transpose()?;
+    write!(tty_output, "\r\n")?;
+    Ok(match key {
+        Some(Key::Char('y')) | Some(Key::Char('Y')) => Some(false),
+        Some(Key::Char('x')) | Some(Key::Char('X')) => Some(true),
+        _ => None,
+    })
+}
+
+/// The state file. Not being able to read it only means starting without the saved marks
+/// and position.
+fn load_state() -> State {
+    match state_path().map(|path| State::load(&path)).transpose() {
+        Ok(state) => state.unwrap_or_default(),
+        Err(error) => {
+            debug!("Can't read the state file: {}", error);
+            State::default()
+        }
     }
-    Ok(())
+}
+
+/// Saves what to remember about the file at path, keeping the other files in the state file.
+fn save_file_state(path: PathBuf, file_state: FileState) {
+    // Read again, another lesser could have saved it in the meantime.
+    let mut state = load_state();
+    if state.file(&path).is_none() && file_state == FileState::default() {
+        return;
+    }
+    state.set_file(path, file_state);
+    if let Some(state_path) = state_path() {
+        if let Err(error) = state.save(&state_path) {
+            debug!("Can't write the state file: {}", error);
+        }
+    }
+}
+
+/// What to tell the user if message didn't move the page.
+fn not_moved_notice(message: &Message) -> Option<String> {
+    let notice = match message {
+        Message::Search { .. }
+        | Message::SearchNext(_)
+        | Message::SearchPrevious(_)
+        | Message::SearchDone(..) => "Pattern not found",
+        Message::ScrollDown(_)
+        | Message::ScrollDownPage(_)
+        | Message::ScrollDownHalfPage(_)
+        | Message::ScrollToEnd => "Already at the end of the file",
+        Message::ScrollUp(_)
+        | Message::ScrollUpPage(_)
+        | Message::ScrollUpHalfPage(_)
+        | Message::ScrollToBeginning => "Already at the beginning of the file",
+        Message::ScrollRight(_) => "Already at the end of the lines",
+        Message::GotoMark(_) => "Mark not set",
+        Message::JumpBack(_) => "Already at the oldest jump",
+        Message::JumpForward(_) => "Already at the newest jump",
+        Message::FormFeed { .. } => "No more form feeds",
+        Message::ToggleFold | Message::FoldLevel(_) => "Nothing to fold",
+        Message::ToggleMarkdown => "Not a Markdown file",
+        Message::NextFile => "No next file",
+        Message::PreviousFile => "No previous file",
+        _ => return None,
+    };
+    Some(notice.to_owned())
+}
+
+/// Repeats the last search count times, the other way if reverse. In a big file it's left
+/// searching in the background instead, and the page stays where it is until it's done.
+fn search_next(
+    screen_move_handler: &mut ScreenMoveHandler,
+    reverse: bool,
+    count: u64,
+    (rows, cols): (u16, u16),
+    searching: &mut Option<RunningSearch>,
+    sender: &Sender<Message>,
+) -> std::io::Result<Option<String>> {
+    match screen_move_handler.big_search(reverse, count, rows) {
+        Some(search) => {
+            *searching = Some(search.spawn(sender.clone()));
+            screen_move_handler.redraw(rows, cols)
+        }
+        None => screen_move_handler.search_next(reverse, count, rows, cols),
+    }
+}
+
+/// Runs the command given with a +, like +G, for the first page. If it can't move there (e.g.
+/// the pattern is not found), the first page is the beginning of the file.
+fn startup_page(
+    screen_move_handler: &mut ScreenMoveHandler,
+    message: Message,
+    rows: u16,
+    cols: u16,
+) -> std::io::Result<Option<String>> {
+    debug!("Startup command: {:?}", message);
+    let page = match message {
+        Message::ScrollToEnd => screen_move_handler.move_to_end(rows, cols)?,
+        Message::GotoLine(line) => screen_move_handler.goto_line(line, rows, cols)?,
+        Message::GotoPercent(percent) => screen_move_handler.goto_percent(percent, rows, cols)?,
+        Message::Search { pattern, backwards } => match pattern.to_regex() {
+            Ok(regex) => screen_move_handler.search(regex, backwards, rows, cols)?,
+            Err(error) => {
+                debug!("Invalid search pattern {:?}: {}", pattern, error);
+                None
+            }
+        },
+        _ => None,
+    };
+    match page {
+        Some(page) => Ok(Some(page)),
+        None => screen_move_handler.initial_screen(rows, cols),
+    }
+}
+
+/// How many rows are available for the page: the last one is taken by the status line.
+fn page_rows(screen_rows: u16) -> u16 {
+    max(screen_rows.saturating_sub(1), 1)
+}
+
+/// message, with the pattern of a search or a filter ignoring the case as ignore_case says.
+fn ignoring_case(mut message: Message, ignore_case: IgnoreCase) -> Message {
+    if let Message::Search { pattern, .. } | Message::Filter(pattern) = &mut message {
+        pattern.ignore_case = ignore_case;
+    }
+    message
+}
+
+/// `pattern` shows only the matching lines, `!pattern` hides them.
+/// An empty pattern means removing all the filters.
+fn build_filter(pattern: Pattern) -> Result<Option<FilterPattern>, regex::Error> {
+    if pattern.text.is_empty() {
+        return Ok(None);
+    }
+    let (text, exclude) = match pattern.text.strip_prefix('!') {
+        Some(text) => (text.to_owned(), true),
+        None => (pattern.text.clone(), false),
+    };
+    let pattern = Pattern { text, ..pattern };
+    Ok(Some(FilterPattern {
+        pattern: pattern.to_regex()?,
+        exclude,
+    }))
 }
 
 fn spawn_signal_handler(sender: Sender<Message>) {
-    let signals = Signals::new(&[SIGWINCH, SIGINT]).expect("Signal handler");
+    let signals = Signals::new([SIGWINCH, SIGINT]).expect("Signal handler");
 
     thread::spawn(move || {
         for sig in signals.forever() {
-            let msg = match sig {
+            debug!("Received signal {:?}", sig);
+            let mut msg = match sig {
                 signal_hook::SIGWINCH => Message::Reload,
                 _ => Message::Exit,
             };
+            // Waits for the resizing to stop.
+            while let Message::Reload = msg {
+                thread::sleep(RESIZE_QUIET);
+                let pending: Vec<_> = signals.pending().collect();
+                if pending.is_empty() {
+                    break;
+                }
+                if pending.contains(&SIGINT) {
+                    msg = Message::Exit;
+                }
+            }
             sender.send(msg).unwrap();
-            debug!("Received signal {:?}", sig);
         }
     });
 }
 
-/// TODO: reading everything from the pipe is easy but not smart / efficient.
-fn read_all_from_pipe() -> Mmap {
-    //let (sender, receiver) = crossbeam_channel::unbounded();
-    let tempdir = tempdir::TempDir::new("lesser").expect("Tempdir");
-    let path: PathBuf = tempdir.path().join("map_mut");
-    let mut file = OpenOptions::new()
-        .read(true)
-        .write(true)
-        .create(true)
-        .open(&path)
-        .expect("Create file");
-    let mut stdin = stdin();
-    std::io::copy(&mut stdin, &mut file).expect("copy pipe input");
-    unsafe { Mmap::map(&file).expect("mmap") }
+/// An in memory copy of text, to page it like a file.
+fn mmap_of(text: &str) -> std::io::Result<Mmap> {
+    let mut mmap = MmapMut::map_anon(max(text.len(), 1))?;
+    (&mut mmap[..]).write_all(text.as_bytes())?;
+    mmap.make_read_only()
+}
+
+/// What the input preprocessor made of a file.
+enum Preprocessed {
+    /// The text to show instead of the file.
+    Text(Text),
+    /// The file to show instead.
+    File(PathBuf),
+}
+
+impl Preprocessed {
+    /// The name of the replacement, for LESSCLOSE.
+    fn name(&self) -> String {
+        match self {
+            Preprocessed::Text(_) => "-".to_owned(),
+            Preprocessed::File(path) => path.display().to_string(),
+        }
+    }
+}
+
+/// Runs the LESSOPEN command for the file called filename (`-` for the standard input). None
+/// if the file is shown as it is.
+fn preprocess(
+    lessopen: &LessOpen,
+    filename: &str,
+    buffer_max: u64,
+) -> std::io::Result<Option<Preprocessed>> {
+    let mut command = Command::new("sh");
+    command
+        .arg("-c")
+        .arg(lessopen.command_for(filename))
+        .stdout(Stdio::piped());
+    if !lessopen.pipe {
+        let output = command.output()?;
+        let replacement = String::from_utf8_lossy(&output.stdout);
+        let replacement = replacement.trim_end_matches('\n');
+        return Ok((!replacement.is_empty()).then(|| Preprocessed::File(replacement.into())));
+    }
+    let mut child = command.spawn()?;
+    let text = read_all(child.stdout.take().expect("Piped stdout"), buffer_max)?;
+    let status = child.wait()?;
+    Ok(match text {
+        Some(text) => Some(Preprocessed::Text(text)),
+        // With ||, no text is the text to show if the command succeeded.
+        None if lessopen.empty_ok && status.success() => {
+            Some(Preprocessed::Text(mmap_of("\n")?.into()))
+        }
+        None => None,
+    })
+}
+
+/// The text decompressed from file, by the command for its compression. None if it's empty.
+fn decompress(
+    file: File,
+    compression: Compression,
+    buffer_max: u64,
+) -> std::io::Result<Option<Text>> {
+    let (program, args) = compression.command();
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::from(file))
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|error| {
+            std::io::Error::new(
+                error.kind(),
+                format!("Can't run {} to decompress the file: {}", program, error),
+            )
+        })?;
+    let text = read_all(child.stdout.take().expect("Piped stdout"), buffer_max)?;
+    let status = child.wait()?;
+    if !status.success() {
+        return Err(std::io::Error::new(
+            ErrorKind::InvalidData,
+            format!("{} failed to decompress the file: {}", program, status),
+        ));
+    }
+    Ok(text)
+}
+
+/// All of input, in memory or past buffer_max in a temporary file. None if there was nothing
+/// to read.
+fn read_all(input: impl Read + Send + 'static, buffer_max: u64) -> std::io::Result<Option<Text>> {
+    let stream = Stream::spawn(input, buffer_max, Overflow::Spill, b'\n');
+    stream.wait_for_end();
+    stream.text()
 }
 
-fn spawn_key_pressed_handler(sender: Sender<Message>) {
+fn spawn_key_pressed_handler(sender: Sender<Message>, keys: Keymap, literal_search: bool) {
     thread::spawn(move || {
         let tty = fs::OpenOptions::new()
             .read(true)
@@ -116,53 +1665,226 @@ fn spawn_key_pressed_handler(sender: Sender<Message>) {
             .into_raw_mode()
             .expect("Into raw mode");
 
+        // Set while the user is typing in the prompt line.
+        let mut prompt: Option<Prompt> = None;
+        // Number typed before a command, like the 10 in "10g".
+        let mut count: Option<u64> = None;
+        // Rows moved by d and u, set by typing a count before them. Half the page if None.
+        let mut half_page: Option<u64> = None;
+        let mut search_history = SearchHistory::load();
+
         for c in tty_input.try_clone().unwrap().keys() {
-            let message = match c.expect("read keys") {
-                Key::Char('q') => Message::Exit,
-                Key::PageUp => Message::ScrollUpPage,
-                Key::PageDown => Message::ScrollDownPage,
-                Key::Left => Message::ScrollLeft,
-                Key::Down => Message::ScrollDown,
-                Key::Up => Message::ScrollUp,
-                Key::Right => Message::ScrollRight,
-
-                Key::Char('g') => Message::ScrollToBeginning,
-                Key::Home => Message::ScrollToBeginning,
-                Key::Char('G') => Message::ScrollToEnd,
-                Key::End => Message::ScrollToEnd,
-
-                // Enter goes down
-                Key::Char('\n') => Message::ScrollDown,
-                Key::Char('e') => Message::ScrollDown,
-                Key::Char('j') => Message::ScrollDown,
-
-                Key::Char('y') => Message::ScrollUp,
-                Key::Char('k') => Message::ScrollUp,
-
-                Key::Char('b') => Message::ScrollUpPage,
-                Key::Char(' ') => Message::ScrollDownPage,
-                Key::Char('f') => Message::ScrollDownPage,
-
-                // Not-implemented keys do nothing
-                _ => Message::Empty,
+            let key = c.expect("read keys");
+            if let Some(mut current) = prompt.take() {
+                let message = match current.handle_key(key) {
+                    PromptStatus::Editing(line) => {
+                        prompt = Some(current);
+                        Message::Prompt(Some(line))
+                    }
+                    PromptStatus::Cancelled => Message::Prompt(None),
+                    PromptStatus::Done(message) => {
+                        if let Message::Search { pattern, .. } | Message::Filter(pattern) = &message
+                        {
+                            search_history.add(&pattern.text);
+                        }
+                        message
+                    }
+                };
+                sender.send(message).unwrap();
+                continue;
+            }
+            if let Key::Char(digit @ '0'..='9') = key {
+                let digit = digit.to_digit(10).unwrap() as u64;
+                count = Some(count.unwrap_or(0).saturating_mul(10).saturating_add(digit));
+                continue;
+            }
+            let count = count.take();
+            // Motions are repeated count times.
+            let times = count.unwrap_or(1);
+            let action = keys.get(&key).copied().unwrap_or(Action::Nothing);
+            let prompt_kind = match action {
+                Action::Filter => Some(PromptKind::Filter),
+                Action::Search => Some(PromptKind::Search { backwards: false }),
+                Action::SearchBackwards => Some(PromptKind::Search { backwards: true }),
+                Action::Command => Some(PromptKind::Command),
+                Action::ToggleOption => Some(PromptKind::ToggleOption),
+                Action::SetMark => Some(PromptKind::SetMark),
+                Action::GotoMark => Some(PromptKind::GotoMark),
+                _ => None,
+            };
+            if let Some(kind) = prompt_kind {
+                let new_prompt = Prompt::new(kind, literal_search, search_history.patterns());
+                sender
+                    .send(Message::Prompt(Some(new_prompt.display())))
+                    .unwrap();
+                prompt = Some(new_prompt);
+                continue;
+            }
+            let message = match action {
+                Action::Quit => Message::Exit,
+                Action::PageUp => Message::ScrollUpPage(times),
+                Action::PageDown => Message::ScrollDownPage(times),
+                Action::Left => Message::ScrollLeft(times),
+                Action::Down => Message::ScrollDown(times),
+                Action::Open => Message::Open(times),
+                Action::Up => Message::ScrollUp(times),
+                Action::Right => Message::ScrollRight(times),
+                Action::HalfPageDown => {
+                    half_page = count.or(half_page);
+                    Message::ScrollDownHalfPage(half_page)
+                }
+                Action::HalfPageUp => {
+                    half_page = count.or(half_page);
+                    Message::ScrollUpHalfPage(half_page)
+                }
+
+                Action::Top => count.map_or(Message::ScrollToBeginning, Message::GotoLine),
+                Action::Bottom => count.map_or(Message::ScrollToEnd, Message::GotoLine),
+                Action::Percent => Message::GotoPercent(count.unwrap_or(0)),
+
+                Action::ToggleLineNumbers => Message::ToggleLineNumbers,
+
+                Action::SearchNext => Message::SearchNext(times),
+                Action::SearchPrevious => Message::SearchPrevious(times),
+
+                // Unbound keys do nothing
+                Action::Nothing
+                | Action::Filter
+                | Action::Search
+                | Action::SearchBackwards
+                | Action::Command
+                | Action::ToggleOption
+                | Action::SetMark
+                | Action::GotoMark => Message::Empty,
+                Action::JumpBack => Message::JumpBack(times),
+                Action::JumpForward => Message::JumpForward(times),
+                Action::NextFormFeed => Message::FormFeed {
+                    count: times,
+                    backwards: false,
+                },
+                Action::PreviousFormFeed => Message::FormFeed {
+                    count: times,
+                    backwards: true,
+                },
+                Action::ToggleFold => Message::ToggleFold,
+                Action::FoldLevel => Message::FoldLevel(count),
+                Action::ToggleMarkdown => Message::ToggleMarkdown,
+                Action::ToggleHex => Message::ToggleHex,
+                Action::ToggleWhitespace => Message::ToggleWhitespace,
+                Action::ToggleWrap => Message::ToggleWrap,
+                Action::Follow => Message::Follow,
+                Action::Reload => Message::ReloadFile,
+                Action::Help => Message::Help,
             };
             sender.send(message).unwrap();
         }
     });
 }
 
+/// Writes the prompt line over the last row of the screen, showing the cursor.
+fn write_prompt(
+    screen: &mut dyn Write,
+    prompt: &PromptLine,
+    screen_rows: u16,
+    cols: u16,
+) -> std::io::Result<()> {
+    write_last_row(screen, screen_rows, &prompt.text)?;
+    let cursor_col = min(prompt.cursor + 1, cols as usize) as u16;
+    write!(
+        screen,
+        "{}{}",
+        termion::cursor::Goto(cursor_col, screen_rows),
+        termion::cursor::Show
+    )?;
+    screen.flush()
+}
+
+/// Writes text (e.g. the prompt) over the last row of the screen.
+fn write_last_row(screen: &mut dyn Write, screen_rows: u16, text: &str) -> std::io::Result<()> {
+    write!(
+        screen,
+        "{}{}{}",
+        termion::cursor::Goto(1, screen_rows),
+        termion::clear::CurrentLine,
+        text
+    )
+}
+
+/// The prompt, by default the file name, the lines shown and how far in the file they are,
+/// like "file.txt lines 1-42 10%". Plus how many filters are active, if any.
+fn write_status_line(
+    screen: &mut dyn Write,
+    screen_move_handler: &mut ScreenMoveHandler,
+    prompt: &str,
+    name: Option<&str>,
+    files: Option<(usize, usize)>,
+    screen_rows: u16,
+    cols: u16,
+) -> std::io::Result<()> {
+    // Not waiting for the lines before the last page of a big file to be counted.
+    let total_lines = if needs_total_lines(prompt) && !screen_move_handler.indexing_tail() {
+        Some(screen_move_handler.count_lines())
+    } else {
+        None
+    };
+    let info = StatusInfo {
+        name,
+        files,
+        position: screen_move_handler.position(page_rows(screen_rows)),
+        total_lines,
+    };
+    let mut status = format_prompt(prompt, &info);
+    match screen_move_handler.filters_count() {
+        0 => (),
+        1 => status.push_str(" (1 filter active)"),
+        n => status.push_str(&format!(" ({} filters active)", n)),
+    }
+    // A longer line would wrap, scrolling the whole screen.
+    let status: String = status.chars().take(cols as usize).collect();
+    let status_line = format!(
+        "{}{}{}",
+        termion::style::Invert,
+        status,
+        termion::style::Reset
+    );
+    write_last_row(screen, screen_rows, &status_line)
+}
+
+/// Writes a notice, like "Pattern not found", over the status line.
+fn write_notice(
+    screen: &mut dyn Write,
+    notice: &str,
+    screen_rows: u16,
+    cols: u16,
+) -> std::io::Result<()> {
+    let notice: String = notice.chars().take(cols as usize).collect();
+    let notice = format!(
+        "{}{}{}",
+        termion::style::Bold,
+        notice,
+        termion::style::Reset
+    );
+    write_last_row(screen, screen_rows, &notice)
+}
+
 /// If page is None, then we made a read which didn't return anything.
 fn write_screen(
-    screen: &mut RawTerminal<AlternateScreen<Stdout>>,
+    screen: &mut dyn Write,
+    frame: &mut Frame,
     page: Option<String>,
+    size: (u16, u16),
 ) -> std::io::Result<()> {
     if let Some(page) = page {
-        write!(screen, "{}", termion::clear::All)?;
-        write!(screen, "{}", termion::cursor::Goto(1, 1))?;
-        write!(screen, "{}", page)?;
+        frame.write_page(screen, &page, size)?;
     } else {
         write!(screen, "\x07")?;
     }
-    screen.flush().expect("Failed to flush");
     Ok(())
 }
+
+/// Writes the frame in output to the screen with a single write, and empties output.
+fn write_frame(screen: &mut dyn Write, output: &mut Vec<u8>) -> std::io::Result<()> {
+    screen.write_all(output)?;
+    output.clear();
+    screen.flush()
+}
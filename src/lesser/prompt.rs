@@ -0,0 +1,478 @@
+use crate::lesser::formats::Message;
+use crate::lesser::less_env::IgnoreCase;
+use crate::lesser::search::Pattern;
+use std::cmp::min;
+use std::fs;
+use std::path::{Path, PathBuf};
+use termion::event::Key;
+
+/// What the prompt is asking for.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum PromptKind {
+    /// `&pattern`: only show the lines matching pattern.
+    Filter,
+    /// `/pattern` or `?pattern`: search forward or backwards.
+    Search { backwards: bool },
+    /// `:command`, like `:42` to go to line 42 or `:e file` to open a file.
+    Command,
+    /// `-` and an option letter, like `-N` to show or hide the line numbers.
+    ToggleOption,
+    /// `m` and a letter: mark the current position.
+    SetMark,
+    /// `'` and a letter: go to a mark.
+    GotoMark,
+}
+
+impl PromptKind {
+    fn prefix(self) -> &'static str {
+        match self {
+            PromptKind::Filter => "&",
+            PromptKind::Search { backwards: false } => "/",
+            PromptKind::Search { backwards: true } => "?",
+            PromptKind::Command => ":",
+            PromptKind::ToggleOption => "-",
+            PromptKind::SetMark => "mark: ",
+            PromptKind::GotoMark => "goto mark: ",
+        }
+    }
+
+    fn takes_pattern(self) -> bool {
+        match self {
+            PromptKind::Filter | PromptKind::Search { .. } => true,
+            PromptKind::Command
+            | PromptKind::ToggleOption
+            | PromptKind::SetMark
+            | PromptKind::GotoMark => false,
+        }
+    }
+
+    /// The message sent by the single char typed, if this prompt doesn't need Enter.
+    fn single_char_message(self, c: char) -> Option<Message> {
+        match self {
+            PromptKind::ToggleOption => Some(Message::ToggleOption(c)),
+            PromptKind::SetMark => Some(Message::SetMark(c)),
+            PromptKind::GotoMark => Some(Message::GotoMark(c)),
+            PromptKind::Filter | PromptKind::Search { .. } | PromptKind::Command => None,
+        }
+    }
+}
+
+/// What to show in the prompt line.
+#[derive(Debug, PartialEq)]
+pub(crate) struct PromptLine {
+    pub(crate) text: String,
+    /// Column of the cursor in text, in chars.
+    pub(crate) cursor: usize,
+}
+
+pub(crate) enum PromptStatus {
+    /// Still typing: this is what to show in the prompt line.
+    Editing(PromptLine),
+    Cancelled,
+    /// Enter was pressed, this is the message to send.
+    Done(Message),
+}
+
+/// The line typed after a command key, like `&`.
+pub(crate) struct Prompt {
+    kind: PromptKind,
+    input: String,
+    /// Where the next char typed goes in input, in chars.
+    cursor: usize,
+    /// The pattern is a literal string, not a regex. Toggled with Ctrl-R.
+    literal: bool,
+    /// The pattern only matches whole words. Toggled with Ctrl-B.
+    whole_word: bool,
+    /// The past patterns, oldest first, recalled with Up and Down. Empty if the prompt
+    /// doesn't take a pattern.
+    history: Vec<String>,
+    /// The pattern of history shown, or its length while typing a new one.
+    history_index: usize,
+    /// What was being typed before going up in the history.
+    typed: String,
+}
+
+impl Prompt {
+    pub(crate) fn new(kind: PromptKind, literal: bool, history: &[String]) -> Self {
+        let history = if kind.takes_pattern() {
+            history.to_vec()
+        } else {
+            Vec::new()
+        };
+        Prompt {
+            kind,
+            input: String::new(),
+            cursor: 0,
+            literal,
+            whole_word: false,
+            history_index: history.len(),
+            history,
+            typed: String::new(),
+        }
+    }
+
+    /// What to show in the prompt line.
+    pub(crate) fn display(&self) -> PromptLine {
+        let literal = if self.literal { "Regex-off " } else { "" };
+        let whole_word = if self.whole_word { "Word " } else { "" };
+        let before_input = format!("{}{}{}", literal, whole_word, self.kind.prefix());
+        PromptLine {
+            cursor: before_input.chars().count() + self.cursor,
+            text: before_input + &self.input,
+        }
+    }
+
+    /// The index in input of the char at cursor.
+    fn byte_index(&self, cursor: usize) -> usize {
+        self.input
+            .char_indices()
+            .nth(cursor)
+            .map_or(self.input.len(), |(index, _c)| index)
+    }
+
+    /// Replaces the input, with the cursor at its end.
+    fn set_input(&mut self, input: String) {
+        self.cursor = input.chars().count();
+        self.input = input;
+    }
+
+    /// Where the word before the cursor starts, for Ctrl-W.
+    fn word_start(&self) -> usize {
+        let before: Vec<char> = self.input.chars().take(self.cursor).collect();
+        let mut start = before.len();
+        while start > 0 && before[start - 1].is_whitespace() {
+            start -= 1;
+        }
+        while start > 0 && !before[start - 1].is_whitespace() {
+            start -= 1;
+        }
+        start
+    }
+
+    pub(crate) fn handle_key(&mut self, key: Key) -> PromptStatus {
+        // Options and marks are a single letter, no need to press enter.
+        if let Key::Char(c) = key {
+            if let Some(message) = self.kind.single_char_message(c) {
+                return PromptStatus::Done(message);
+            }
+            // Like in less, :n and :p don't need Enter.
+            if self.kind == PromptKind::Command && self.input.is_empty() {
+                match c {
+                    'n' => return PromptStatus::Done(Message::NextFile),
+                    'p' => return PromptStatus::Done(Message::PreviousFile),
+                    _ => (),
+                }
+            }
+        }
+        match key {
+            Key::Char('\n') => {
+                let pattern = Pattern {
+                    text: std::mem::take(&mut self.input),
+                    literal: self.literal,
+                    whole_word: self.whole_word,
+                    // Set by the pager, where -i can be toggled.
+                    ignore_case: IgnoreCase::Never,
+                };
+                let message = match self.kind {
+                    PromptKind::Filter => Message::Filter(pattern),
+                    PromptKind::Search { backwards } => Message::Search { pattern, backwards },
+                    PromptKind::Command => parse_command(&pattern.text),
+                    PromptKind::ToggleOption | PromptKind::SetMark | PromptKind::GotoMark => {
+                        Message::Prompt(None)
+                    }
+                };
+                PromptStatus::Done(message)
+            }
+            Key::Ctrl('r') if self.kind.takes_pattern() => {
+                self.literal = !self.literal;
+                PromptStatus::Editing(self.display())
+            }
+            // Completes the name of the file to open.
+            Key::Char('\t') if self.kind == PromptKind::Command => {
+                if let Some(name) = examined_name(&self.input) {
+                    let command = &self.input[..self.input.len() - name.len()];
+                    let input = format!("{}{}", command, complete_path(name));
+                    self.set_input(input);
+                }
+                PromptStatus::Editing(self.display())
+            }
+            Key::Ctrl('b') if self.kind.takes_pattern() => {
+                self.whole_word = !self.whole_word;
+                PromptStatus::Editing(self.display())
+            }
+            Key::Up if self.history_index > 0 => {
+                if self.history_index == self.history.len() {
+                    self.typed = std::mem::take(&mut self.input);
+                }
+                self.history_index -= 1;
+                self.set_input(self.history[self.history_index].clone());
+                PromptStatus::Editing(self.display())
+            }
+            Key::Down if self.history_index < self.history.len() => {
+                self.history_index += 1;
+                let input = match self.history.get(self.history_index) {
+                    Some(pattern) => pattern.clone(),
+                    None => std::mem::take(&mut self.typed),
+                };
+                self.set_input(input);
+                PromptStatus::Editing(self.display())
+            }
+            Key::Esc | Key::Ctrl('c') => PromptStatus::Cancelled,
+            // Like less, deleting past the beginning leaves the prompt.
+            Key::Backspace if self.input.is_empty() => PromptStatus::Cancelled,
+            Key::Backspace => {
+                if self.cursor > 0 {
+                    self.cursor -= 1;
+                    self.input.remove(self.byte_index(self.cursor));
+                }
+                PromptStatus::Editing(self.display())
+            }
+            Key::Delete => {
+                if self.cursor < self.input.chars().count() {
+                    self.input.remove(self.byte_index(self.cursor));
+                }
+                PromptStatus::Editing(self.display())
+            }
+            Key::Left => {
+                self.cursor = self.cursor.saturating_sub(1);
+                PromptStatus::Editing(self.display())
+            }
+            Key::Right => {
+                self.cursor = min(self.cursor + 1, self.input.chars().count());
+                PromptStatus::Editing(self.display())
+            }
+            Key::Home | Key::Ctrl('a') => {
+                self.cursor = 0;
+                PromptStatus::Editing(self.display())
+            }
+            Key::End | Key::Ctrl('e') => {
+                self.cursor = self.input.chars().count();
+                PromptStatus::Editing(self.display())
+            }
+            // Deletes up to the cursor.
+            Key::Ctrl('u') => {
+                let end = self.byte_index(self.cursor);
+                self.input.drain(..end);
+                self.cursor = 0;
+                PromptStatus::Editing(self.display())
+            }
+            // Deletes the word before the cursor.
+            Key::Ctrl('w') => {
+                let start = self.word_start();
+                let range = self.byte_index(start)..self.byte_index(self.cursor);
+                self.input.drain(range);
+                self.cursor = start;
+                PromptStatus::Editing(self.display())
+            }
+            Key::Char(c) => {
+                self.input.insert(self.byte_index(self.cursor), c);
+                self.cursor += 1;
+                PromptStatus::Editing(self.display())
+            }
+            _ => PromptStatus::Editing(self.display()),
+        }
+    }
+}
+
+/// Unknown commands just close the prompt.
+fn parse_command(command: &str) -> Message {
+    if let Some(name) = examined_name(command) {
+        let name = name.trim_end();
+        if !name.is_empty() {
+            return Message::Examine(PathBuf::from(name));
+        }
+    }
+    match command.trim().parse() {
+        Ok(line) => Message::GotoLine(line),
+        Err(_) => Message::Prompt(None),
+    }
+}
+
+/// The file name typed after `e`, in the `:e file` command.
+fn examined_name(command: &str) -> Option<&str> {
+    let name = command.trim_start().strip_prefix('e')?;
+    name.starts_with(char::is_whitespace)
+        .then(|| name.trim_start())
+}
+
+/// Completes the file name partial as far as it's the same for all the files starting with
+/// it: to the whole name if there's only one, with a `/` if it's a directory. Hidden files
+/// only if partial starts with a dot.
+fn complete_path(partial: &str) -> String {
+    let (dir, prefix) = match partial.rfind('/') {
+        Some(slash) => partial.split_at(slash + 1),
+        None => ("", partial),
+    };
+    let entries = match fs::read_dir(if dir.is_empty() { "." } else { dir }) {
+        Ok(entries) => entries,
+        Err(_) => return partial.to_owned(),
+    };
+    let names: Vec<String> = entries
+        .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+        .filter(|name| {
+            name.starts_with(prefix) && (prefix.starts_with('.') || !name.starts_with('.'))
+        })
+        .collect();
+    let completed = match names.as_slice() {
+        [] => return partial.to_owned(),
+        [name] if Path::new(dir).join(name).is_dir() => format!("{}/", name),
+        [name] => name.clone(),
+        [first, rest @ ..] => {
+            let mut common = first.as_str();
+            for name in rest {
+                let len = common
+                    .char_indices()
+                    .zip(name.chars())
+                    .find(|((_i, a), b)| a != b)
+                    .map_or(common.len().min(name.len()), |((i, _a), _b)| i);
+                common = &common[..len];
+            }
+            common.to_owned()
+        }
+    };
+    format!("{}{}", dir, completed)
+}
+
+/// Parses a command given on the command line after a `+`, like `+G`, `+42` or `+/pattern`.
+pub(crate) fn parse_startup_command(command: &str, literal: bool) -> Option<Message> {
+    let search = |text: &str, backwards| Message::Search {
+        pattern: Pattern {
+            text: text.to_owned(),
+            literal,
+            whole_word: false,
+            ignore_case: IgnoreCase::Never,
+        },
+        backwards,
+    };
+    if let Some(text) = command.strip_prefix('/') {
+        return Some(search(text, false));
+    }
+    if let Some(text) = command.strip_prefix('?') {
+        return Some(search(text, true));
+    }
+    let digits = command.len()
+        - command
+            .trim_start_matches(|c: char| c.is_ascii_digit())
+            .len();
+    let (number, command) = command.split_at(digits);
+    let number = number.parse().ok();
+    let message = match (number, command) {
+        (None, "g") => Message::ScrollToBeginning,
+        (None, "G") => Message::ScrollToEnd,
+        (Some(line), "") | (Some(line), "g") | (Some(line), "G") => Message::GotoLine(line),
+        (Some(percent), "p") | (Some(percent), "%") => Message::GotoPercent(percent),
+        _ => return None,
+    };
+    Some(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lesser::formats::Message;
+    use crate::lesser::prompt::{
+        complete_path, parse_command, parse_startup_command, Prompt, PromptKind, PromptStatus,
+    };
+    use std::fs;
+    use termion::event::Key;
+
+    #[test]
+    fn test_parse_startup_command() {
+        let parse = |command| format!("{:?}", parse_startup_command(command, false));
+        assert_eq!(parse("G"), format!("{:?}", Some(Message::ScrollToEnd)));
+        assert_eq!(parse("42"), format!("{:?}", Some(Message::GotoLine(42))));
+        assert_eq!(parse("42g"), format!("{:?}", Some(Message::GotoLine(42))));
+        assert_eq!(
+            parse("50%"),
+            format!("{:?}", Some(Message::GotoPercent(50)))
+        );
+        match parse_startup_command("/err", true) {
+            Some(Message::Search { pattern, backwards }) => {
+                assert_eq!(pattern.text, "err");
+                assert!(pattern.literal);
+                assert!(!backwards);
+            }
+            other => panic!("Unexpected {:?}", other),
+        }
+        assert!(parse_startup_command("x", false).is_none());
+        assert!(parse_startup_command("", false).is_none());
+    }
+
+    #[test]
+    fn test_toggle_option() {
+        for option in ['N', 'S', 'R', 'i'] {
+            let mut prompt = Prompt::new(PromptKind::ToggleOption, false, &[]);
+            match prompt.handle_key(Key::Char(option)) {
+                PromptStatus::Done(Message::ToggleOption(c)) => assert_eq!(c, option),
+                _ => panic!("-{} wasn't toggled", option),
+            }
+        }
+    }
+
+    #[test]
+    fn test_examine() {
+        match parse_command(" e  notes.txt ") {
+            Message::Examine(path) => assert_eq!(path.to_str(), Some("notes.txt")),
+            other => panic!("Unexpected {:?}", other),
+        }
+        assert!(matches!(parse_command("e"), Message::Prompt(None)));
+        assert!(matches!(parse_command("else"), Message::Prompt(None)));
+
+        let dir = tempdir::TempDir::new("lesser").unwrap();
+        fs::write(dir.path().join("alpha.txt"), "").unwrap();
+        fs::write(dir.path().join(".alpha"), "").unwrap();
+        fs::create_dir(dir.path().join("alpine")).unwrap();
+        let dir = format!("{}/", dir.path().display());
+        let complete =
+            |name: &str| complete_path(&format!("{}{}", dir, name))[dir.len()..].to_owned();
+        assert_eq!(complete("a"), "alp");
+        assert_eq!(complete("alpi"), "alpine/");
+        assert_eq!(complete("alph"), "alpha.txt");
+        assert_eq!(complete(".a"), ".alpha");
+        assert_eq!(complete("x"), "x");
+    }
+
+    #[test]
+    fn test_history() {
+        let history = vec!["first".to_owned(), "second".to_owned()];
+        let mut prompt = Prompt::new(PromptKind::Search { backwards: false }, false, &history);
+        let mut press = |key| match prompt.handle_key(key) {
+            PromptStatus::Editing(line) => line.text,
+            _ => panic!("The prompt was closed"),
+        };
+        press(Key::Char('x'));
+        assert_eq!(press(Key::Up), "/second");
+        assert_eq!(press(Key::Up), "/first");
+        assert_eq!(press(Key::Up), "/first");
+        assert_eq!(press(Key::Down), "/second");
+        assert_eq!(press(Key::Down), "/x");
+        assert_eq!(press(Key::Down), "/x");
+    }
+
+    #[test]
+    fn test_line_editing() {
+        let mut prompt = Prompt::new(PromptKind::Search { backwards: true }, false, &[]);
+        let mut press = |key| match prompt.handle_key(key) {
+            PromptStatus::Editing(line) => line,
+            _ => panic!("The prompt was closed"),
+        };
+        for c in "foo barz".chars() {
+            press(Key::Char(c));
+        }
+        press(Key::Backspace);
+        press(Key::Left);
+        press(Key::Left);
+        press(Key::Char('X'));
+        let line = press(Key::Ctrl('w'));
+        assert_eq!(line.text, "?foo ar");
+        assert_eq!(line.cursor, 5);
+        press(Key::Home);
+        press(Key::Delete);
+        let line = press(Key::End);
+        assert_eq!(line.text, "?oo ar");
+        assert_eq!(line.cursor, 6);
+        press(Key::Left);
+        let line = press(Key::Ctrl('u'));
+        assert_eq!(line.text, "?r");
+        assert_eq!(line.cursor, 1);
+    }
+}
@@ -1,26 +1,453 @@
-use memmap::Mmap;
+use crate::lesser::ansi::{
+    dimmed, line_width, overstrike, render_line, text_width, wrap_columns, ControlChars,
+    ControlNotation, Wrapping,
+};
+use crate::lesser::encoding::utf_8_bom_len;
+use crate::lesser::hex::{hex_row, HEX_ROW_BYTES};
+use crate::lesser::highlight::Highlighter;
+use crate::lesser::indexer::Indexer;
+use crate::lesser::line_index::LineIndex;
+use crate::lesser::search::{find_line, Progress};
+use crate::lesser::text::Text;
+use memchr::memrchr_iter;
+use regex::bytes::Regex;
+use std::borrow::Cow;
 use std::cmp::{max, min};
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
 use std::io;
-use std::usize::MAX;
+use std::ops::Range;
+use std::sync::Arc;
 
 type StartIndex = usize;
 type EndIndex = usize;
 
+/// A pattern used to filter the lines of the file.
+#[derive(Debug)]
+pub struct FilterPattern {
+    pub pattern: Regex,
+    /// Hide the matching lines, instead of showing only them.
+    pub exclude: bool,
+}
+
+impl FilterPattern {
+    fn keeps(&self, line: &[u8]) -> bool {
+        self.pattern.is_match(line) != self.exclude
+    }
+}
+
+/// Lines that can be folded, like a JSON object: when folded only the first one is shown.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Block {
+    pub(crate) first_line: usize,
+    pub(crate) last_line: usize,
+    /// How many blocks it's in.
+    pub(crate) depth: usize,
+}
+
+impl Block {
+    fn contains(&self, line: usize) -> bool {
+        self.first_line <= line && line <= self.last_line
+    }
+}
+
+/// Rows of the file kept by all the patterns and not in a folded block. Like rows_indexes,
+/// it's filled lazily.
+struct Filter {
+    patterns: Vec<FilterPattern>,
+    /// The last line of the folded blocks, by their first line.
+    folded: BTreeMap<usize, usize>,
+    /// Indexes in rows_indexes of the rows kept by the patterns.
+    matching_rows: Vec<usize>,
+    /// How many rows of rows_indexes have been tested against the patterns.
+    checked_rows: usize,
+}
+
+impl Filter {
+    fn keeps(&self, line: &[u8]) -> bool {
+        self.patterns.iter().all(|pattern| pattern.keeps(line))
+    }
+}
+
+/// The rows of the screen the long lines are wrapped on. Like the filter, it's filled lazily,
+/// as the rows are needed.
+struct Wrap {
+    /// How wide the screen is, 0 until it's known: then each line takes a row.
+    cols: u16,
+    /// The first row of each visible row, for the ones wrapped so far.
+    first_rows: Vec<usize>,
+    /// How many rows the visible rows wrapped so far take.
+    rows: usize,
+}
+
+impl Wrap {
+    fn new(cols: u16) -> Wrap {
+        Wrap {
+            cols,
+            first_rows: vec![],
+            rows: 0,
+        }
+    }
+
+    /// Forgets how the visible rows from row on were wrapped.
+    fn truncate(&mut self, row: usize) {
+        if let Some(first_row) = self.first_rows.get(row) {
+            self.rows = *first_row;
+            self.first_rows.truncate(row);
+        }
+    }
+}
+
+/// A "line" is a row of the file, a "row" is a row of the view that gets paged: they're the same
+/// thing unless a filter is set, in which case only the lines matching the filter are rows.
+/// When the long lines are wrapped, the rows paged are the ones of the screen instead, and the
+/// rows of the view are the "visible rows": each of them takes one or more rows.
 pub struct PagedReader {
     /// Start-end row indexes. A row is delimited by an EOL char.
-    /// This vector referes to the file, so it's independent from the screen-size.
-    rows_indexes: Vec<(StartIndex, EndIndex)>,
-    mmap: Mmap,
+    /// They refer to the file, so they're independent from the screen-size.
+    rows_indexes: LineIndex,
+    /// Finds the next rows_indexes in the background, started when they're first needed.
+    indexer: Option<Indexer>,
+    /// Where the last page read starts in the text, to know which way it's scrolled.
+    page_start: usize,
+    text: Arc<Text>,
+    /// The byte the lines of the text end with.
+    newline: u8,
+    /// The byte the lines end with instead of the newline found in the text, like NUL for -z.
+    separator: Option<u8>,
+    filter: Option<Filter>,
+    /// Show the number of each line in a gutter on the left.
+    line_numbers: bool,
+    /// How the control chars, like the escape sequences of colors, are shown.
+    control_chars: ControlChars,
+    /// The tab stops are this many columns apart.
+    tab_width: usize,
+    /// How the control chars shown as text are written.
+    control_notation: ControlNotation,
+    /// Show the tabs, trailing spaces and line endings.
+    whitespace: bool,
+    /// Colors the text, e.g. by its syntax if the file type is known.
+    highlighter: Option<Highlighter>,
+    /// The blocks that can be folded, sorted by first line.
+    blocks: Vec<Block>,
+    /// The first line stays at the top of the screen, like the header of a table.
+    pinned_header: bool,
+    /// Where each column of a table starts: horizontal scrolling goes by whole columns.
+    column_starts: Vec<u64>,
+    /// Show the bytes in hex: the "lines" are HEX_ROW_BYTES bytes long instead of ending
+    /// with a newline.
+    hex: bool,
+    /// Wrap the long lines on more rows, instead of cutting them at the edge of the screen.
+    wrap: Option<Wrap>,
+    /// Wrap the long lines before the word that doesn't fit, instead of in the middle of it.
+    wrap_words: bool,
+    /// What the rows a long line is wrapped on start with, after the first.
+    wrap_marker: String,
+    /// The pages read last, the newest last, by their row and column offset and size: going
+    /// back to them doesn't render them again. Forgotten when anything changing how they look
+    /// changes.
+    pages: Vec<(PageKey, (String, usize, usize))>,
 }
 
+type PageKey = (u64, u64, u16, u16);
+
+/// Where a page is in the file, for the status line.
+#[derive(Debug, PartialEq)]
+pub struct Position {
+    /// First line shown, starting from 1.
+    pub first_line: u64,
+    /// Last line shown, starting from 1.
+    pub last_line: u64,
+    /// How much of the file (by bytes) is before the first line shown.
+    pub first_percent: u64,
+    /// How much of the file (by bytes) is up to the end of the last line shown.
+    pub percent: u64,
+}
+
+/// Line numbers take at least this many columns, like in less. Plus one for the separator.
+const LINE_NUMBER_MIN_WIDTH: usize = 7;
+/// What the rows of a page break are made of.
+const PAGE_BREAK: &str = "─";
+/// How many of the pages read last are kept.
+const CACHED_PAGES: usize = 16;
+/// Bigger texts are searched on all the cores.
+const PARALLEL_SEARCH_MIN: usize = 64 << 20;
+/// The last page of bigger texts is found going back from their end, without indexing them.
+const TAIL_MIN: usize = 64 << 20;
+/// How many bytes are looked at a time for the newlines, going back from the end of the text.
+const TAIL_CHUNK: usize = 64 << 10;
+
 impl PagedReader {
-    pub fn new(mmap: Mmap) -> PagedReader {
+    pub fn new(text: impl Into<Text>) -> PagedReader {
+        let text = Arc::new(text.into());
+        let newline = text.newline();
         PagedReader {
-            rows_indexes: vec![],
-            mmap,
+            rows_indexes: LineIndex::lines(Arc::clone(&text), newline),
+            indexer: None,
+            page_start: 0,
+            text,
+            newline,
+            separator: None,
+            filter: None,
+            line_numbers: false,
+            control_chars: ControlChars::Caret,
+            tab_width: 8,
+            control_notation: ControlNotation::Caret,
+            whitespace: false,
+            highlighter: None,
+            blocks: vec![],
+            pinned_header: false,
+            column_starts: vec![],
+            hex: false,
+            wrap: None,
+            wrap_words: false,
+            wrap_marker: String::new(),
+            pages: vec![],
         }
     }
 
+    /// Pages text instead, the file after it changed. If grown, the new text starts with the
+    /// old one, so the lines already indexed are kept: all but the last one, if the end of the
+    /// file cut it short. Otherwise everything is indexed again, the lines ending with the
+    /// newline of the new text.
+    pub(crate) fn set_text(&mut self, text: impl Into<Text>, grown: bool) {
+        let old_len = self.text.len();
+        self.pages.clear();
+        self.text = Arc::new(text.into());
+        self.rows_indexes.set_text(Arc::clone(&self.text));
+        self.indexer = None;
+        if !grown {
+            self.newline = self.separator.unwrap_or_else(|| self.text.newline());
+            self.rows_indexes = self.new_rows_indexes();
+        } else if matches!(self.rows_indexes.last(), Some((_start, end)) if end == old_len) {
+            self.rows_indexes.pop();
+        }
+        let lines = self.rows_indexes.len();
+        if let Some(filter) = &mut self.filter {
+            filter.matching_rows.retain(|line| *line < lines);
+            filter.checked_rows = min(filter.checked_rows, lines);
+        }
+        let visible_rows = self.visible_rows();
+        if let Some(wrap) = &mut self.wrap {
+            wrap.truncate(visible_rows);
+        }
+    }
+
+    pub(crate) fn hex(&self) -> bool {
+        self.hex
+    }
+
+    /// Shows the bytes in hex, or the lines of text. The rows are indexed again, and so are
+    /// the filters. The folds are undone.
+    pub(crate) fn set_hex(&mut self, hex: bool) {
+        self.hex = hex;
+        self.rows_indexes = self.new_rows_indexes();
+        self.indexer = None;
+        let patterns = match self.filter.take() {
+            Some(filter) => filter.patterns,
+            None => vec![],
+        };
+        self.set_filter(patterns, BTreeMap::new());
+    }
+
+    fn new_rows_indexes(&self) -> LineIndex {
+        if self.hex {
+            // The hex rows have no newline between them.
+            LineIndex::new(0)
+        } else {
+            LineIndex::lines(Arc::clone(&self.text), self.newline)
+        }
+    }
+
+    /// The byte the lines end with: \n, \r for old Mac files, or the separator set.
+    pub(crate) fn newline(&self) -> u8 {
+        self.newline
+    }
+
+    /// Ends the lines with separator, like NUL for the output of find -print0, or with the
+    /// newline found in the text if None. They're indexed again, like for set_hex.
+    pub(crate) fn set_separator(&mut self, separator: Option<u8>) {
+        self.separator = separator;
+        self.newline = separator.unwrap_or_else(|| self.text.newline());
+        self.set_hex(self.hex);
+    }
+
+    /// The offset in the file of the first byte of line.
+    pub(crate) fn offset_of_line(&mut self, line: u64) -> usize {
+        self.fetch_rows((line as usize).saturating_add(1));
+        match self.rows_indexes.get(line as usize) {
+            Some((start, _end)) => start,
+            None => self.text.len(),
+        }
+    }
+
+    pub(crate) fn set_pinned_header(&mut self, pinned_header: bool) {
+        self.forget_pages();
+        self.pinned_header = pinned_header;
+    }
+
+    pub(crate) fn pinned_header(&self) -> bool {
+        self.pinned_header
+    }
+
+    pub(crate) fn set_column_starts(&mut self, column_starts: Vec<u64>) {
+        self.column_starts = column_starts;
+    }
+
+    /// Where scrolling right count times from col goes: the start of the count-th next
+    /// column of a table, or count columns on. Past the last column of a table, too.
+    pub(crate) fn scroll_right(&self, col: u64, count: u64) -> u64 {
+        let next_start = self
+            .column_starts
+            .iter()
+            .filter(|start| **start > col)
+            .nth(count.saturating_sub(1) as usize);
+        match next_start {
+            Some(start) => *start,
+            None => col.saturating_add(count),
+        }
+    }
+
+    /// Where scrolling left count times from col goes: the start of the count-th previous
+    /// column of a table, or count columns back.
+    pub(crate) fn scroll_left(&self, col: u64, count: u64) -> u64 {
+        if self.column_starts.is_empty() {
+            return col.saturating_sub(count);
+        }
+        self.column_starts
+            .iter()
+            .rev()
+            .filter(|start| **start < col)
+            .nth(count.saturating_sub(1) as usize)
+            .copied()
+            .unwrap_or(0)
+    }
+
+    pub(crate) fn set_blocks(&mut self, blocks: Vec<Block>) {
+        self.blocks = blocks;
+    }
+
+    pub(crate) fn set_highlighter(&mut self, highlighter: Option<Highlighter>) {
+        self.forget_pages();
+        self.highlighter = highlighter;
+    }
+
+    /// Switches between the rendered Markdown and its markup. False if it's not Markdown.
+    pub(crate) fn toggle_markdown(&mut self) -> bool {
+        match &mut self.highlighter {
+            Some(Highlighter::Markdown(renderer)) => renderer.toggle(),
+            _ => return false,
+        }
+        self.forget_pages();
+        true
+    }
+
+    pub(crate) fn text(&self) -> Arc<Text> {
+        Arc::clone(&self.text)
+    }
+
+    /// The lines indexed so far, to save them. None for the rows of the hex dump.
+    pub(crate) fn line_index(&self) -> Option<&LineIndex> {
+        (!self.hex).then_some(&self.rows_indexes)
+    }
+
+    /// Pages the lines in index, found in the text before, instead of indexing them again.
+    pub(crate) fn set_line_index(&mut self, index: LineIndex) {
+        self.rows_indexes = index;
+        self.indexer = None;
+    }
+
+    /// The beginning of the file, up to the first newline, without a byte order mark. Used to
+    /// find the file type.
+    pub fn first_line(&self) -> Cow<'_, str> {
+        let head = self.text.head(256);
+        let start = utf_8_bom_len(&head);
+        let end = head
+            .iter()
+            .position(|c| *c == self.newline)
+            .unwrap_or(head.len());
+        self.text.lossy(start..max(start, end))
+    }
+
+    pub fn set_control_chars(&mut self, control_chars: ControlChars) {
+        self.forget_pages();
+        self.control_chars = control_chars;
+    }
+
+    pub(crate) fn control_chars(&self) -> ControlChars {
+        self.control_chars
+    }
+
+    pub(crate) fn set_tab_width(&mut self, tab_width: usize) {
+        self.forget_pages();
+        self.tab_width = tab_width;
+    }
+
+    pub(crate) fn set_control_notation(&mut self, control_notation: ControlNotation) {
+        self.forget_pages();
+        self.control_notation = control_notation;
+    }
+
+    pub(crate) fn set_whitespace(&mut self, whitespace: bool) {
+        self.forget_pages();
+        self.whitespace = whitespace;
+    }
+
+    pub(crate) fn whitespace(&self) -> bool {
+        self.whitespace
+    }
+
+    /// Wraps the long lines on the rows of a screen cols wide, or cuts them at its edge if
+    /// None.
+    pub(crate) fn set_wrap(&mut self, cols: Option<u16>) {
+        self.pages.clear();
+        self.wrap = cols.map(Wrap::new);
+    }
+
+    /// How wide the screen the long lines are wrapped for is, if they are.
+    pub(crate) fn wrap(&self) -> Option<u16> {
+        self.wrap.as_ref().map(|wrap| wrap.cols)
+    }
+
+    pub(crate) fn set_wrap_words(&mut self, wrap_words: bool) {
+        self.forget_pages();
+        self.wrap_words = wrap_words;
+    }
+
+    pub(crate) fn set_wrap_marker(&mut self, wrap_marker: String) {
+        self.forget_pages();
+        self.wrap_marker = wrap_marker;
+    }
+
+    /// How the lines are wrapped on rows cols wide. The marker is left out if there would be
+    /// no room for the text.
+    fn wrapping(&self, cols: usize) -> Wrapping {
+        let marker_width = text_width(&self.wrap_marker);
+        Wrapping {
+            cols,
+            words: self.wrap_words,
+            marker_width: if marker_width < cols { marker_width } else { 0 },
+        }
+    }
+
+    /// Forgets the pages read, and the rows the lines were wrapped on, after how they're
+    /// shown changed.
+    fn forget_pages(&mut self) {
+        self.pages.clear();
+        if let Some(wrap) = &mut self.wrap {
+            wrap.truncate(0);
+        }
+    }
+
+    pub fn set_line_numbers(&mut self, line_numbers: bool) {
+        self.forget_pages();
+        self.line_numbers = line_numbers;
+    }
+
+    pub fn line_numbers(&self) -> bool {
+        self.line_numbers
+    }
+
     /// rows_to_read = term height
     /// columns_to_read = term width
     /// Returns a page. Will start reading from row_offset / column offset and will read
@@ -31,37 +458,125 @@ impl PagedReader {
         column_offset: u64,
         rows_to_read: u16,
         columns_to_read: u16,
+    ) -> std::io::Result<(String, usize, usize)> {
+        let key = (row_offset, column_offset, rows_to_read, columns_to_read);
+        if let Some(cached) = self.pages.iter().position(|(page_key, _)| *page_key == key) {
+            let page = self.pages.remove(cached);
+            let read = page.1.clone();
+            self.pages.push(page);
+            return Ok(read);
+        }
+        let read = self.render_page(row_offset, column_offset, rows_to_read, columns_to_read)?;
+        if self.pages.len() == CACHED_PAGES {
+            self.pages.remove(0);
+        }
+        self.pages.push((key, read.clone()));
+        Ok(read)
+    }
+
+    fn render_page(
+        &mut self,
+        row_offset: u64,
+        column_offset: u64,
+        rows_to_read: u16,
+        columns_to_read: u16,
     ) -> std::io::Result<(String, usize, usize)> {
         let indexes = self.get_rows_indexes(rows_to_read, row_offset)?;
         let indexes_len = indexes.len();
-        let mut res = "".to_owned();
+        if let (Some((start, _)), Some((_, end))) = (indexes.first(), indexes.last()) {
+            self.text.advise(*start..*end, *start >= self.page_start);
+            self.page_start = *start;
+        }
+        let gutter_width = match indexes_len.checked_sub(1) {
+            Some(_last) if self.wrap.is_some() => self.wrapped_gutter_width(),
+            Some(last) => {
+                let (last_line, _row_in_line) = self.line_of_screen_row(row_offset as usize + last);
+                self.gutter_width(last_line + 1)
+            }
+            None => 0,
+        };
+        let columns_to_read = (columns_to_read as usize).saturating_sub(gutter_width);
+        // The header covers the first row of the page, the one scrolling under it.
+        let pin_header = self.pinned_header && indexes_len > 1 && self.line_of_visible_row(0) == 0;
+        let (tab_width, notation, whitespace) =
+            (self.tab_width, self.control_notation, self.whitespace);
+        let wrap = self.wrap.is_some();
+        let wrapping = self.wrapping(columns_to_read);
+        let marker = match wrapping.marker_width {
+            0 => String::new(),
+            _ => dimmed(&self.wrap_marker),
+        };
+        // The columns the rows of the last line wrapped start at: a long line takes many rows.
+        let mut wrapped: Option<(usize, Vec<usize>)> = None;
+        // Allocated once for the page, most rows are borrowed from the text.
+        let mut res = String::with_capacity(indexes_len * (gutter_width + columns_to_read + 2));
         let mut has_text = false;
-        for (i, (start_row, end_row)) in indexes.iter().cloned().enumerate() {
-            let end = std::cmp::min(
-                end_row,
-                start_row + column_offset as usize + columns_to_read as usize,
-            )
-            .to_owned();
-
-            let start = std::cmp::min(start_row + column_offset as usize, end);
-
-            let row = &self.mmap[start..end];
-
-            //res.push_str(format!("start:{}, end:{}", start_row, end_row).as_ref());
-            // \t takes more then one char space. Not sure what the correct behaviour should be here.
-            // TODO: this should be configurable, and default to 4.
-            let as_string = String::from_utf8_lossy(row).to_string().replace("\t", " ");
-
-            has_text = has_text || !as_string.is_empty();
+        for i in 0..indexes_len {
+            let (line, row_in_line) = if i == 0 && pin_header {
+                (0, 0)
+            } else {
+                self.line_of_screen_row(row_offset as usize + i)
+            };
+            let (start_row, end_row) = self.rows_indexes.line(line);
+            if gutter_width > 0 && row_in_line == 0 {
+                let _ = write!(res, "{:>width$} ", line + 1, width = gutter_width - 1);
+            } else if gutter_width > 0 {
+                res.push_str(&" ".repeat(gutter_width));
+            }
+            if row_in_line > 0 {
+                res.push_str(&marker);
+            }
+            // A form feed, starting a page, is shown as a rule across the screen.
+            if !self.hex && is_page_break(&self.text.bytes(start_row..end_row)) {
+                res.push_str(&PAGE_BREAK.repeat(columns_to_read));
+            } else {
+                let (row, control_chars) = self.row_text(line);
+                // A wrapped row shows the columns of the line up to where the next one starts.
+                let (col_offset, cols) = if wrap {
+                    let starts = match wrapped.take() {
+                        Some((wrapped_line, starts)) if wrapped_line == line => starts,
+                        _ => wrap_columns(
+                            &row,
+                            control_chars,
+                            tab_width,
+                            notation,
+                            whitespace,
+                            wrapping,
+                        ),
+                    };
+                    let start = starts[min(row_in_line, starts.len() - 1)];
+                    let row_cols = wrapping.row_cols(row_in_line);
+                    // The spaces past the end of the row, when wrapping words, are cut.
+                    let cols = match starts.get(row_in_line + 1) {
+                        Some(next_start) => min(next_start - start, row_cols),
+                        None => row_cols,
+                    };
+                    wrapped = Some((line, starts));
+                    (start, cols)
+                } else {
+                    (column_offset as usize, columns_to_read)
+                };
+                let (rendered, row_has_text) = render_line(
+                    &row,
+                    col_offset,
+                    cols,
+                    control_chars,
+                    tab_width,
+                    notation,
+                    whitespace,
+                );
+                has_text = has_text || row_has_text;
 
-            res.push_str(as_string.as_ref());
+                res.push_str(&rendered);
+            }
             if i < indexes_len - 1 {
                 res.push_str("\n\r");
             }
         }
-        // If horizontal scrolling hasn't returned any char, then won't scroll.
-        let cols_red = if has_text {
-            columns_to_read as usize
+        // If horizontal scrolling hasn't returned any char, then won't scroll. Wrapped lines
+        // don't scroll.
+        let cols_red = if has_text && !wrap {
+            columns_to_read
         } else {
             0
         };
@@ -69,100 +584,891 @@ impl PagedReader {
         Ok((res, indexes_len, cols_red))
     }
 
-    /// find the next "rows" new lines, starting from row_offset position in self.mmap.
+    /// The last page of a big text, rows long, found going back from its end: it's shown at
+    /// once, while the lines before it are indexed. None if the text is small or already
+    /// indexed, or if the page depends on the lines before it, like when they're filtered,
+    /// wrapped or colored by their syntax.
+    pub(crate) fn read_tail(
+        &self,
+        column_offset: u64,
+        rows: u16,
+        cols: u16,
+    ) -> Option<(String, usize, usize)> {
+        if self.text.len() < TAIL_MIN || self.file_is_all_read() {
+            return None;
+        }
+        self.render_tail(column_offset, rows, cols)
+    }
+
+    fn render_tail(
+        &self,
+        column_offset: u64,
+        rows: u16,
+        cols: u16,
+    ) -> Option<(String, usize, usize)> {
+        if self.filter.is_some() || self.wrap.is_some() || self.hex || self.pinned_header {
+            return None;
+        }
+        let lines = self.last_lines(rows as usize);
+        if lines.is_empty() {
+            return None;
+        }
+        // The numbers of the lines aren't known yet: the gutter is blank.
+        let gutter_width = self.gutter_width(0);
+        let columns_to_read = (cols as usize).saturating_sub(gutter_width);
+        let mut res = String::with_capacity(lines.len() * (gutter_width + columns_to_read + 2));
+        let mut has_text = false;
+        for (i, (start, end)) in lines.iter().enumerate() {
+            if i > 0 {
+                res.push_str("\n\r");
+            }
+            res.push_str(&" ".repeat(gutter_width));
+            if is_page_break(&self.text.bytes(*start..*end)) {
+                res.push_str(&PAGE_BREAK.repeat(columns_to_read));
+                continue;
+            }
+            let (row, control_chars) = match &self.highlighter {
+                Some(highlighter) => {
+                    let line = self.text.lossy(*start..*end);
+                    (
+                        Cow::Owned(highlighter.highlight_alone(&line)?),
+                        ControlChars::Raw,
+                    )
+                }
+                None => self.plain_line(*start, *end),
+            };
+            let row = self.with_line_end(row, *end);
+            let (rendered, row_has_text) = render_line(
+                &row,
+                column_offset as usize,
+                columns_to_read,
+                control_chars,
+                self.tab_width,
+                self.control_notation,
+                self.whitespace,
+            );
+            has_text = has_text || row_has_text;
+            res.push_str(&rendered);
+        }
+        let cols_red = if has_text { columns_to_read } else { 0 };
+        Some((res, lines.len(), cols_red))
+    }
+
+    /// Where the last count lines of the text start and end, found going back from its end.
+    fn last_lines(&self, count: usize) -> Vec<(StartIndex, EndIndex)> {
+        let len = self.text.len();
+        let mut lines = Vec::with_capacity(count);
+        // The newline at the end of the text doesn't start another line.
+        let mut end = match len.checked_sub(1) {
+            Some(last) if self.text.bytes(last..len)[0] == self.newline => last,
+            Some(_) => len,
+            None => return lines,
+        };
+        let mut chunk_end = end;
+        while lines.len() < count {
+            let chunk_start = chunk_end.saturating_sub(TAIL_CHUNK);
+            let chunk = self.text.bytes(chunk_start..chunk_end);
+            for newline in memrchr_iter(self.newline, &chunk) {
+                lines.push((chunk_start + newline + 1, end));
+                end = chunk_start + newline;
+                if lines.len() == count {
+                    break;
+                }
+            }
+            if chunk_start == 0 {
+                if lines.len() < count {
+                    lines.push((utf_8_bom_len(&self.text.head(3)), end));
+                }
+                break;
+            }
+            chunk_end = chunk_start;
+        }
+        lines.reverse();
+        lines
+    }
+
+    /// Indexes the whole text in the background, like after read_tail. Doesn't wait for it.
+    pub(crate) fn index_in_background(&mut self) {
+        if !self.hex && !self.file_is_all_read() {
+            self.spawn_indexer();
+            if let Some(indexer) = &self.indexer {
+                indexer.request(usize::MAX);
+            }
+        }
+    }
+
+    /// If the whole text is indexed, with the lines found in the background so far.
+    pub(crate) fn all_indexed(&mut self) -> bool {
+        if let Some(indexer) = &self.indexer {
+            while let Some(lines) = indexer.next_lines(false) {
+                self.rows_indexes.extend(lines);
+            }
+        }
+        self.file_is_all_read()
+    }
+
+    /// How wide the line numbers gutter is, for a page whose last line has number
+    /// last_line: as wide as it plus a space.
+    fn gutter_width(&self, last_line: usize) -> usize {
+        // The hex rows start with their offset instead.
+        if !self.line_numbers || self.hex {
+            return 0;
+        }
+        max(last_line.to_string().len(), LINE_NUMBER_MIN_WIDTH) + 1
+    }
+
+    /// The gutter_width of all the pages when the lines are wrapped, so they're wrapped the
+    /// same way on all of them: for the biggest number a line of the text could have.
+    fn wrapped_gutter_width(&self) -> usize {
+        self.gutter_width(self.text.len() + 1)
+    }
+
+    /// What is shown for line: its text, colored by the highlighter, followed by what's
+    /// hidden if its block is folded, and its end if the whitespace is shown.
+    fn row_text(&mut self, line: usize) -> (Cow<'_, str>, ControlChars) {
+        let (start, end) = self.rows_indexes.line(line);
+        let (row, control_chars) = match &mut self.highlighter {
+            Some(highlighter) if !self.hex => {
+                let text = &self.text;
+                let rows_indexes = &self.rows_indexes;
+                let highlighted = highlighter.highlight(line, |line| {
+                    let (start, end) = rows_indexes.line(line);
+                    text.lossy(start..end)
+                });
+                // Only the highlighter's colors are passed to the terminal.
+                (Cow::Owned(highlighted), ControlChars::Raw)
+            }
+            _ => self.displayed_line(start, end),
+        };
+        let row = match self.fold_suffix(line) {
+            Some(suffix) => Cow::Owned(format!("{}{}", row, suffix)),
+            None => row,
+        };
+        (self.with_line_end(row, end), control_chars)
+    }
+
+    /// How a folded block shows what's hidden after its first line: `…` and the last line,
+    /// like `"key": {…},`.
+    fn fold_suffix(&self, line: usize) -> Option<String> {
+        let last_line = *self.filter.as_ref()?.folded.get(&line)?;
+        let (start, end) = self.rows_indexes.line(last_line);
+        let last = self.text.lossy(start..end);
+        Some(format!("…{}", last.trim_start()))
+    }
+
+    /// Folds the innermost block with line, or unfolds it if line is the first of a folded
+    /// one. The first line of the block, None if line is in no block.
+    pub(crate) fn toggle_fold(&mut self, line: usize) -> Option<usize> {
+        let block = *self
+            .blocks
+            .iter()
+            .filter(|block| block.contains(line))
+            .max_by_key(|block| block.first_line)?;
+        let mut folded = self.take_folds();
+        if folded.remove(&block.first_line).is_none() {
+            folded.insert(block.first_line, block.last_line);
+        }
+        self.set_folds(folded);
+        Some(block.first_line)
+    }
+
+    /// Folds all the blocks at depth or deeper, unfolding the others. False if there are no
+    /// blocks.
+    pub(crate) fn fold_from_depth(&mut self, depth: usize) -> bool {
+        let folded = self
+            .blocks
+            .iter()
+            .filter(|block| block.depth >= depth)
+            .map(|block| (block.first_line, block.last_line))
+            .collect();
+        self.take_folds();
+        self.set_folds(folded);
+        !self.blocks.is_empty()
+    }
+
+    /// Unfolds all the blocks. False if none was folded.
+    pub(crate) fn unfold_all(&mut self) -> bool {
+        !self.take_folds().is_empty()
+    }
+
+    /// The line shown for line: the first of the outermost folded block hiding it, or line
+    /// itself.
+    pub(crate) fn shown_line(&self, line: u64) -> u64 {
+        let folded = match &self.filter {
+            Some(filter) => &filter.folded,
+            None => return line,
+        };
+        folded
+            .iter()
+            .find(|(first, last)| **first < line as usize && line as usize <= **last)
+            .map(|(first, _last)| *first as u64)
+            .unwrap_or(line)
+    }
+
+    /// Removes the folds, keeping the patterns.
+    fn take_folds(&mut self) -> BTreeMap<usize, usize> {
+        self.forget_pages();
+        let mut filter = match self.filter.take() {
+            Some(filter) => filter,
+            None => return BTreeMap::new(),
+        };
+        let folded = std::mem::take(&mut filter.folded);
+        if !filter.patterns.is_empty() {
+            self.set_filter(filter.patterns, BTreeMap::new());
+        }
+        folded
+    }
+
+    fn set_folds(&mut self, folded: BTreeMap<usize, usize>) {
+        let patterns = match self.filter.take() {
+            Some(filter) => filter.patterns,
+            None => vec![],
+        };
+        self.set_filter(patterns, folded);
+    }
+
+    /// Starts filtering again from the first line.
+    fn set_filter(&mut self, patterns: Vec<FilterPattern>, folded: BTreeMap<usize, usize>) {
+        self.forget_pages();
+        self.filter = if patterns.is_empty() && folded.is_empty() {
+            None
+        } else {
+            Some(Filter {
+                patterns,
+                folded,
+                matching_rows: vec![],
+                checked_rows: 0,
+            })
+        };
+    }
+
+    /// Stacks a pattern on top of the current filters: only the lines kept by all of them
+    /// will be paged.
+    pub fn add_filter(&mut self, pattern: FilterPattern) {
+        self.forget_pages();
+        let rows_indexes = &self.rows_indexes;
+        let text = &self.text;
+        match &mut self.filter {
+            Some(filter) => {
+                // A new pattern can only hide more lines, so the lines already checked
+                // don't need to go through the other patterns again.
+                filter.matching_rows.retain(|line| {
+                    let (start, end) = rows_indexes.line(*line);
+                    pattern.keeps(&text.bytes(start..end))
+                });
+                filter.patterns.push(pattern);
+            }
+            None => self.set_filter(vec![pattern], BTreeMap::new()),
+        }
+    }
+
+    /// Back to paging all the lines, but the folded ones.
+    pub fn clear_filters(&mut self) {
+        let folded = self
+            .filter
+            .take()
+            .map(|filter| filter.folded)
+            .unwrap_or_default();
+        self.set_filter(vec![], folded);
+    }
+
+    pub fn filters_count(&self) -> usize {
+        self.filter
+            .as_ref()
+            .map(|filter| filter.patterns.len())
+            .unwrap_or(0)
+    }
+
+    /// The line shown as `row`, if there's such a row.
+    pub fn line_of_row(&mut self, row: u64) -> Option<u64> {
+        let row = row as usize;
+        self.fetch_screen_rows(row.saturating_add(1));
+        if row < self.screen_rows() {
+            Some(self.line_of_screen_row(row).0 as u64)
+        } else {
+            None
+        }
+    }
+
+    /// The row showing `line`, or the first one after it if the line is filtered out.
+    /// None if there are no rows from `line` on.
+    pub fn row_of_line(&mut self, line: u64) -> Option<u64> {
+        let row = self.visible_row_of_line(line as usize)?;
+        Some(self.screen_row_of_visible_row(row) as u64)
+    }
+
+    /// The line shown as `row` and the column its row starts at, 0 unless the line is
+    /// wrapped on more rows. None if there's no such row.
+    pub fn position_of_row(&mut self, row: u64) -> Option<(u64, usize)> {
+        let row = row as usize;
+        self.fetch_screen_rows(row.saturating_add(1));
+        if row >= self.screen_rows() {
+            return None;
+        }
+        let (line, row_in_line) = self.line_of_screen_row(row);
+        let col = match (&self.wrap, row_in_line) {
+            (Some(wrap), 1..) => {
+                let cols = wrap.cols;
+                self.wrap_starts(line, cols)
+                    .get(row_in_line)
+                    .copied()
+                    .unwrap_or(0)
+            }
+            _ => 0,
+        };
+        Some((line as u64, col))
+    }
+
+    /// The row showing column `col` of `line`, see position_of_row. The first row after it
+    /// if the line is filtered out, None if there are no rows from `line` on.
+    pub fn row_of_position(&mut self, line: u64, col: usize) -> Option<u64> {
+        let row = self.row_of_line(line)?;
+        if col == 0 || self.line_of_row(row) != Some(line) {
+            return Some(row);
+        }
+        let wrapped = match &self.wrap {
+            Some(wrap) => {
+                let cols = wrap.cols;
+                self.wrap_starts(line as usize, cols)
+                    .iter()
+                    .filter(|start| **start <= col)
+                    .count()
+            }
+            None => 1,
+        };
+        Some(row + wrapped.saturating_sub(1) as u64)
+    }
+
+    /// The visible row showing line, or the first one after it.
+    fn visible_row_of_line(&mut self, line: usize) -> Option<usize> {
+        loop {
+            let row = match &self.filter {
+                None => {
+                    self.fetch_rows(line.saturating_add(1));
+                    return if line < self.rows_indexes.len() {
+                        Some(line)
+                    } else {
+                        None
+                    };
+                }
+                Some(filter) => match filter.matching_rows.binary_search(&line) {
+                    Ok(row) | Err(row) => row,
+                },
+            };
+            let visible_rows = self.visible_rows();
+            if row < visible_rows {
+                return Some(row);
+            }
+            self.fetch_visible_rows(visible_rows.saturating_mul(2).max(visible_rows + 1));
+            if self.visible_rows() == visible_rows {
+                // Nothing new was found, we've reached the end of the file.
+                return None;
+            }
+        }
+    }
+
+    /// Big texts are searched a chunk of bytes at a time instead of a row at a time, without
+    /// indexing them first: without filters, the rows are the lines.
+    pub(crate) fn searched_in_chunks(&self) -> bool {
+        self.filter.is_none() && !self.hex && self.text.len() >= PARALLEL_SEARCH_MIN
+    }
+
+    /// The first row from from_row on (or before it, if backwards) with a match for pattern.
+    pub fn find_row(&mut self, pattern: &Regex, from_row: u64, backwards: bool) -> Option<u64> {
+        let from_row = self.visible_row_to_search(from_row as usize, backwards)?;
+        let row = self.find_visible_row(pattern, from_row, backwards)?;
+        Some(self.screen_row_of_visible_row(row) as u64)
+    }
+
+    /// The visible row a search from row starts at: the one shown on it. Going forward, the
+    /// next one if row isn't its first, not to find the same line again. None past the end.
+    fn visible_row_to_search(&mut self, row: usize, backwards: bool) -> Option<usize> {
+        if self.wrap.is_none() {
+            return Some(row);
+        }
+        self.fetch_screen_rows(row.saturating_add(1));
+        if row >= self.screen_rows() {
+            return None;
+        }
+        match self.visible_row_of_screen_row(row) {
+            (visible_row, row_in_line) if row_in_line > 0 && !backwards => Some(visible_row + 1),
+            (visible_row, _row_in_line) => Some(visible_row),
+        }
+    }
+
+    /// The first visible row from from_row on (or before it, if backwards) with a match for
+    /// pattern.
+    fn find_visible_row(
+        &mut self,
+        pattern: &Regex,
+        from_row: usize,
+        backwards: bool,
+    ) -> Option<usize> {
+        if self.searched_in_chunks() {
+            let len = self.text.len();
+            let from = self.offset_of_line(from_row as u64);
+            let lines = if backwards {
+                0..min(from + 1, len)
+            } else {
+                from..len
+            };
+            let progress = Progress::default();
+            let found = find_line(
+                &self.text,
+                pattern,
+                self.newline,
+                lines,
+                backwards,
+                &progress,
+            )?;
+            return Some(self.line_at_offset(found) as usize);
+        }
+        let mut row = from_row;
+        loop {
+            if row >= self.visible_rows() {
+                let visible_rows = self.visible_rows();
+                self.fetch_visible_rows(visible_rows.saturating_mul(2).max(row + 1));
+                if row >= self.visible_rows() {
+                    return None;
+                }
+            }
+            let (start, end) = self.rows_indexes.line(self.line_of_visible_row(row));
+            if pattern.is_match(&self.text.bytes(start..end)) {
+                return Some(row);
+            }
+            if backwards {
+                row = row.checked_sub(1)?;
+            } else {
+                row += 1;
+            }
+        }
+    }
+
+    /// The line at percent (0 to 100) of the file, by bytes.
+    pub fn line_at_percent(&mut self, percent: u64) -> u64 {
+        let offset = (self.text.len() as u64 * min(percent, 100) / 100) as usize;
+        self.line_at_offset(offset)
+    }
+
+    /// The line with the byte at offset, or the last one if it's past the end.
+    pub(crate) fn line_at_offset(&mut self, offset: usize) -> u64 {
+        if self.hex {
+            let last_line = self.text.len().saturating_sub(1) / HEX_ROW_BYTES;
+            return min(offset / HEX_ROW_BYTES, last_line) as u64;
+        }
+        // Index until the line containing offset.
+        while !self.file_is_all_read()
+            && !matches!(self.rows_indexes.last(), Some((_start, end)) if end >= offset)
+        {
+            let rows = self.rows_indexes.len();
+            self.fetch_rows(rows.saturating_mul(2).max(rows + 1));
+        }
+        let line = self.rows_indexes.line_at(offset);
+        // Past the end of the file, the last line.
+        min(line, self.rows_indexes.len().saturating_sub(1)) as u64
+    }
+
+    /// How many lines the file has, filtered or not. This will read the whole file.
+    pub fn count_lines(&mut self) -> u64 {
+        self.fetch_rows(usize::MAX);
+        self.rows_indexes.len() as u64
+    }
+
+    /// How many rows there are. This will read the whole file.
+    pub fn count_rows(&mut self) -> u64 {
+        // Index everything in a single pass first, rather than in growing chunks.
+        self.fetch_rows(usize::MAX);
+        self.fetch_screen_rows(usize::MAX);
+        self.screen_rows() as u64
+    }
+
+    /// Where the page starting at row_offset, rows long, is. None if it's empty.
+    pub fn position(&mut self, row_offset: u64, rows: u16) -> Option<Position> {
+        let shown_rows = self.page_rows(rows, row_offset).len();
+        let last_row = (row_offset as usize).checked_add(shown_rows.checked_sub(1)?)?;
+        let (first_line, _row_in_line) = self.line_of_screen_row(row_offset as usize);
+        let (last_line, _row_in_line) = self.line_of_screen_row(last_row);
+        let (start, _end) = self.rows_indexes.line(first_line);
+        let (_start, end) = self.rows_indexes.line(last_line);
+        Some(Position {
+            first_line: first_line as u64 + 1,
+            last_line: last_line as u64 + 1,
+            first_percent: (start * 100 / self.text.len()) as u64,
+            percent: (end * 100 / self.text.len()) as u64,
+        })
+    }
+
+    /// If the whole file fits in rows rows of cols columns. Long lines take more than one row,
+    /// like when the terminal prints them.
+    pub fn fits_in(&mut self, rows: u16, cols: u16) -> bool {
+        self.fetch_rows(rows as usize + 1);
+        if !self.file_is_all_read() || self.rows_indexes.len() > rows as usize {
+            return false;
+        }
+        let cols = max(cols as usize, 1);
+        let used_rows: usize = self
+            .rows_indexes
+            .iter()
+            .map(|(start, end)| {
+                let (line, control_chars) = self.displayed_line(start, end);
+                let line = self.with_line_end(line, end);
+                let width = line_width(
+                    &line,
+                    control_chars,
+                    self.tab_width,
+                    self.control_notation,
+                    self.whitespace,
+                );
+                max(width.div_ceil(cols), 1)
+            })
+            .sum();
+        used_rows <= rows as usize
+    }
+
+    /// What is shown for the line between start and end: the hex row in hex mode, its text
+    /// otherwise.
+    fn displayed_line(&self, start: usize, end: usize) -> (Cow<'_, str>, ControlChars) {
+        if self.hex {
+            let row = hex_row(start, &self.text.bytes(start..end));
+            return (Cow::Owned(row), self.control_chars);
+        }
+        self.plain_line(start, end)
+    }
+
+    /// row, the line ending at end, with the \n it ends with when the whitespace is shown: the
+    /// end of the line is shown too. The last line of the text may have none.
+    fn with_line_end<'a>(&self, row: Cow<'a, str>, end: usize) -> Cow<'a, str> {
+        if self.whitespace && !self.hex && end < self.text.len() {
+            Cow::Owned(format!("{}\n", row))
+        } else {
+            row
+        }
+    }
+
+    /// The text between start and end, and how to show its control chars. The overstrikes of
+    /// man pages are turned into bold and underline.
+    fn plain_line(&self, start: usize, end: usize) -> (Cow<'_, str>, ControlChars) {
+        let line = self.text.lossy(start..end);
+        match overstrike(&line) {
+            Some(styled) => (Cow::Owned(styled), ControlChars::Raw),
+            None => (line, self.control_chars),
+        }
+    }
+
+    /// Writes the file as it is.
+    pub fn write_all(&self, out: &mut impl io::Write) -> io::Result<()> {
+        if self.hex {
+            for (i, bytes) in self.text.all().chunks(HEX_ROW_BYTES).enumerate() {
+                writeln!(out, "{}", hex_row(i * HEX_ROW_BYTES, bytes))?;
+            }
+        } else {
+            out.write_all(&self.text.all())?;
+        }
+        out.flush()
+    }
+
+    /// find the next "rows" rows, starting from row_offset.
     fn get_rows_indexes(
         &mut self,
         rows: u16,
         row_offset: u64,
     ) -> io::Result<Vec<(StartIndex, EndIndex)>> {
-        // we need to take `row` lines, starting after `row_offset` lines.
-        // since row_offset get increased by row lines, but the count is 0-based, let's handle the special case when row_offset != 0:
-        let to_row = match (row_offset as usize).checked_add(rows as usize) {
-            Some(v) => v,
-            None => max(0, row_offset as i64 - (rows as i64)) as usize,
-        };
-        let file_is_all_read = self
-            .rows_indexes
+        Ok(self
+            .page_rows(rows, row_offset)
+            .map(|row| self.rows_indexes.line(self.line_of_screen_row(row).0))
+            .collect())
+    }
+
+    /// The rows of the page starting at row_offset, rows long: fewer at the end of the file.
+    /// Only the range, not their indexes, that would be copied.
+    fn page_rows(&mut self, rows: u16, row_offset: u64) -> Range<usize> {
+        let to_row = (row_offset as usize).saturating_add(rows as usize);
+        self.fetch_screen_rows(to_row);
+
+        let screen_rows = self.screen_rows();
+        let from_row = min(row_offset as usize, screen_rows);
+        let to_row = min(to_row, screen_rows);
+        from_row..to_row
+    }
+
+    /// How many rows are known: the visible rows, or the rows they're wrapped on.
+    fn screen_rows(&self) -> usize {
+        match &self.wrap {
+            Some(wrap) => wrap.rows,
+            None => self.visible_rows(),
+        }
+    }
+
+    /// The line shown on row, and which of its rows it is when it's wrapped. row must be
+    /// already fetched.
+    fn line_of_screen_row(&self, row: usize) -> (usize, usize) {
+        let (visible_row, row_in_line) = self.visible_row_of_screen_row(row);
+        (self.line_of_visible_row(visible_row), row_in_line)
+    }
+
+    /// The visible row shown on row, and which of its rows it is. row must be already
+    /// fetched.
+    fn visible_row_of_screen_row(&self, row: usize) -> (usize, usize) {
+        match &self.wrap {
+            Some(wrap) => {
+                let visible_row = match wrap.first_rows.binary_search(&row) {
+                    Ok(visible_row) => visible_row,
+                    Err(next_row) => next_row - 1,
+                };
+                (visible_row, row - wrap.first_rows[visible_row])
+            }
+            None => (row, 0),
+        }
+    }
+
+    /// The first row the visible row is shown on. row must be a visible row.
+    fn screen_row_of_visible_row(&mut self, row: usize) -> usize {
+        loop {
+            let (wrapped, rows) = match &self.wrap {
+                Some(wrap) => match wrap.first_rows.get(row) {
+                    Some(first_row) => return *first_row,
+                    None => (wrap.first_rows.len(), wrap.rows),
+                },
+                None => return row,
+            };
+            // At least a row each.
+            self.fetch_screen_rows(rows + row + 1 - wrapped);
+            if self.screen_rows() == rows {
+                return rows;
+            }
+        }
+    }
+
+    fn visible_rows(&self) -> usize {
+        match &self.filter {
+            Some(filter) => filter.matching_rows.len(),
+            None => self.rows_indexes.len(),
+        }
+    }
+
+    /// row must be already fetched.
+    fn line_of_visible_row(&self, row: usize) -> usize {
+        match &self.filter {
+            Some(filter) => filter.matching_rows[row],
+            None => row,
+        }
+    }
+
+    /// If rows_indexes has all the lines of the text.
+    fn file_is_all_read(&self) -> bool {
+        // The last line ends at the end of the text, or at the newline the text ends with.
+        self.rows_indexes
             .last()
-            .map(|(_start, end)| {
-                // if the file is empty. mmap is at least 1. But if the file is non-empty, then end and mmap.len() should match.
-                *end >= self.mmap.len() - 1
-            })
-            .unwrap_or(false);
+            .map(|(_start, end)| end >= self.text.len().saturating_sub(1))
+            .unwrap_or(false)
+    }
 
+    /// Makes sure the first to_row lines are in rows_indexes, if the file has that many.
+    fn fetch_rows(&mut self, to_row: usize) {
         let indexes_are_known = to_row <= self.rows_indexes.len();
-        if !file_is_all_read && !indexes_are_known {
+        if !self.file_is_all_read() && !indexes_are_known {
             self.fetch_missing_rows_indexes(to_row);
         }
+    }
 
-        let skip_offset = match min(self.rows_indexes.len(), row_offset as usize).checked_sub(1) {
-            Some(v) => v,
-            None => 0,
+    /// Makes sure the first to_row rows of the view are known, if the view has that many.
+    fn fetch_visible_rows(&mut self, to_row: usize) {
+        let mut filter = match self.filter.take() {
+            Some(filter) => filter,
+            None => return self.fetch_rows(to_row),
         };
-        Ok(self
-            .rows_indexes
-            .clone()
-            .into_iter()
-            .skip(skip_offset)
-            .take(rows as usize)
-            .collect())
+        while filter.matching_rows.len() < to_row {
+            if filter.checked_rows >= self.rows_indexes.len() {
+                let missing_rows = to_row - filter.matching_rows.len();
+                self.fetch_rows(filter.checked_rows.saturating_add(missing_rows));
+                if filter.checked_rows >= self.rows_indexes.len() {
+                    // Whole file checked.
+                    break;
+                }
+            }
+            let line = filter.checked_rows;
+            let (start, end) = self.rows_indexes.line(line);
+            if filter.keeps(&self.text.bytes(start..end)) {
+                filter.matching_rows.push(line);
+            }
+            // The lines of a folded block are hidden.
+            filter.checked_rows = match filter.folded.get(&line) {
+                Some(last_line) => last_line + 1,
+                None => line + 1,
+            };
+        }
+        self.filter = Some(filter);
     }
-    fn fetch_missing_rows_indexes(&mut self, to_row: usize) {
-        let last_found = self
-            .rows_indexes
-            .last()
-            .map(|(_start, end)| end + 1) // end is the newline char, we need to start looking after it.
-            .unwrap_or(0)
-            .to_owned();
 
-        let missing_indexes = to_row - self.rows_indexes.len();
+    /// Makes sure the first to_row rows are known, wrapping the visible rows on them if the
+    /// lines are wrapped.
+    fn fetch_screen_rows(&mut self, to_row: usize) {
+        let mut wrap = match self.wrap.take() {
+            Some(wrap) => wrap,
+            None => return self.fetch_visible_rows(to_row),
+        };
+        while wrap.rows < to_row {
+            let row = wrap.first_rows.len();
+            if row >= self.visible_rows() {
+                // At least a row each.
+                self.fetch_visible_rows(row.saturating_add(to_row - wrap.rows));
+                if row >= self.visible_rows() {
+                    // All wrapped.
+                    break;
+                }
+            }
+            let line = self.line_of_visible_row(row);
+            wrap.first_rows.push(wrap.rows);
+            wrap.rows += self.wrapped_rows(line, wrap.cols);
+        }
+        self.wrap = Some(wrap);
+    }
 
-        let mut res = vec![];
-        // Left side, is inclusive.
-        let mut last = last_found;
+    /// How many rows line takes, wrapped on a screen cols wide.
+    fn wrapped_rows(&mut self, line: usize, cols: u16) -> usize {
+        self.wrap_starts(line, cols).len()
+    }
 
-        let limit = match missing_indexes.checked_mul(2) {
-            Some(v) => v,
-            None => MAX,
-        };
+    /// The columns the rows of line start at, wrapped on a screen cols wide.
+    fn wrap_starts(&mut self, line: usize, cols: u16) -> Vec<usize> {
+        let (start, end) = self.rows_indexes.line(line);
+        if cols == 0 || (!self.hex && is_page_break(&self.text.bytes(start..end))) {
+            return vec![0];
+        }
+        let wrapping = self.wrapping((cols as usize).saturating_sub(self.wrapped_gutter_width()));
+        let (tab_width, notation, whitespace) =
+            (self.tab_width, self.control_notation, self.whitespace);
+        let (row, control_chars) = self.row_text(line);
+        wrap_columns(
+            &row,
+            control_chars,
+            tab_width,
+            notation,
+            whitespace,
+            wrapping,
+        )
+    }
 
-        let nl = b"\n"[0];
-        for (i, c) in self.mmap[last_found..] // start looking from the lastly found nl
-            .iter()
-            .enumerate()
-        {
-            if *c == nl {
-                let found = i + last_found;
-                res.push((last, found as usize));
-                last = found + 1 as usize;
-                // If I've searched for enough indexes, let's defer the search of other nl for later
-                if res.len() >= limit {
+    fn fetch_missing_rows_indexes(&mut self, to_row: usize) {
+        if self.hex {
+            let missing_indexes = to_row - self.rows_indexes.len();
+            // If to_row is far away (e.g. usize::MAX when going to the end) this is the whole
+            // file.
+            let limit = self
+                .rows_indexes
+                .len()
+                .saturating_add(missing_indexes.saturating_mul(2));
+            let len = self.text.len();
+            while self.rows_indexes.len() < limit {
+                let start = self.rows_indexes.len() * HEX_ROW_BYTES;
+                if start >= len {
                     break;
                 }
-            // Last line. -1 because mmap is 1 even if the file is empty.
-            } else if i == self.mmap.len() - 1 {
-                res.push((last, self.mmap.len()));
+                self.rows_indexes.push(min(start + HEX_ROW_BYTES, len));
             }
+            return;
+        }
+        self.spawn_indexer();
+        let indexer = match &self.indexer {
+            Some(indexer) => indexer,
+            None => return,
+        };
+        indexer.request(to_row);
+        // The lines found in the background so far, then the ones still missing as they're
+        // found. The indexes go straight into rows_indexes: when indexing a huge file up to
+        // the end, a temporary vector would double the memory needed.
+        while let Some(lines) = indexer.next_lines(self.rows_indexes.len() < to_row) {
+            self.rows_indexes.extend(lines);
         }
-        self.rows_indexes.extend(res);
     }
 
-    pub fn cached_rows(&self) -> usize {
-        self.rows_indexes.len()
+    /// Starts indexing the lines after the ones in rows_indexes, if it's not started yet.
+    fn spawn_indexer(&mut self) {
+        let rows_indexes = &self.rows_indexes;
+        let text = &self.text;
+        let newline = self.newline;
+        self.indexer.get_or_insert_with(|| {
+            let last_found = rows_indexes
+                .last()
+                .map(|(_start, end)| end + 1) // end is the newline char, we need to start looking after it.
+                .unwrap_or(0);
+            Indexer::spawn(Arc::clone(text), newline, last_found, rows_indexes.len())
+        });
     }
 }
 
+/// A line with only form feeds, that troff and report generators start their pages with.
+fn is_page_break(line: &[u8]) -> bool {
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+    !line.is_empty() && line.iter().all(|c| *c == b'\x0c')
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::lesser::reader::PagedReader;
-    use memmap::MmapMut;
+    use crate::lesser::reader::{Block, FilterPattern, PagedReader, Position};
+    use memmap::{Mmap, MmapMut};
+    use regex::bytes::Regex;
     use std::io::Write;
 
+    fn include(pattern: &str) -> FilterPattern {
+        FilterPattern {
+            pattern: Regex::new(pattern).unwrap(),
+            exclude: false,
+        }
+    }
+
+    fn exclude(pattern: &str) -> FilterPattern {
+        FilterPattern {
+            pattern: Regex::new(pattern).unwrap(),
+            exclude: true,
+        }
+    }
+
+    fn mmap_for(text: &[u8]) -> Mmap {
+        let mut mmap = MmapMut::map_anon(text.len()).expect("Anon mmap");
+        (&mut mmap[..]).write_all(text).unwrap();
+        mmap.make_read_only().unwrap()
+    }
+
+    fn paged_reader_for(text: &[u8]) -> PagedReader {
+        PagedReader::new(mmap_for(text))
+    }
+
+    #[test]
+    fn test_position() {
+        let mut paged_reader = paged_reader_for(b"first\nsecond\nthird\nfourth");
+        assert_eq!(
+            paged_reader.position(1, 2),
+            Some(Position {
+                first_line: 2,
+                last_line: 3,
+                first_percent: 6 * 100 / 25,
+                percent: 18 * 100 / 25,
+            })
+        );
+        assert_eq!(paged_reader.position(3, 2).unwrap().percent, 100);
+        assert_eq!(paged_reader.position(4, 2), None);
+
+        paged_reader.add_filter(include("i"));
+        let position = paged_reader.position(1, 2).unwrap();
+        assert_eq!((position.first_line, position.last_line), (3, 3));
+    }
+
+    #[test]
+    fn test_fits_in() {
+        let mut paged_reader = paged_reader_for(b"first\nsecond\nthird\n");
+        assert!(paged_reader.fits_in(3, 80));
+        assert!(!paged_reader.fits_in(2, 80));
+        // "second" is printed over two rows.
+        assert!(!paged_reader.fits_in(3, 5));
+        assert!(paged_reader.fits_in(4, 5));
+    }
+
     #[test]
     fn test_read_file_columned() {
         let test = b"firsts\nsecond\nthird";
         let mut mmap = MmapMut::map_anon(test.len()).expect("Anon mmap");
-        (&mut mmap[..]).write(test).unwrap();
+        (&mut mmap[..]).write_all(test).unwrap();
         let mmap = mmap.make_read_only().unwrap();
         let mut paged_reader = PagedReader::new(mmap);
         let expected_rows = 2;
@@ -175,11 +1481,41 @@ mod tests {
         assert_eq!(1, cols_red);
     }
 
+    #[test]
+    fn test_read_multibyte_columns() {
+        let mut paged_reader = paged_reader_for("añb€c\nαβγδε".as_bytes());
+        // The columns are chars, not bytes.
+        let (res, _rows_red, cols_red) = paged_reader.read_file_paged(0, 1, 2, 2).unwrap();
+        assert_eq!(res, "ñb\n\rβγ");
+        assert_eq!(cols_red, 2);
+        let (res, _rows_red, _cols_red) = paged_reader.read_file_paged(0, 4, 2, 2).unwrap();
+        assert_eq!(res, "c\n\rε");
+    }
+
+    #[test]
+    fn test_line_endings() {
+        // Windows line endings: the \r isn't shown.
+        let mut paged_reader = paged_reader_for(b"one\r\ntwo\r\n");
+        let (res, rows_red, _cols_red) = paged_reader.read_file_paged(0, 0, 3, 10).unwrap();
+        assert_eq!(res, "one\n\rtwo");
+        assert_eq!(rows_red, 2);
+        // Old Mac line endings.
+        let mut paged_reader = paged_reader_for(b"one\rtwo\rthree");
+        assert_eq!(paged_reader.newline(), b'\r');
+        assert_eq!(paged_reader.first_line(), "one");
+        let (res, rows_red, _cols_red) = paged_reader.read_file_paged(1, 0, 3, 10).unwrap();
+        assert_eq!(res, "two\n\rthree");
+        assert_eq!(rows_red, 2);
+        let pattern = Regex::new("^t").unwrap();
+        assert_eq!(paged_reader.find_row(&pattern, 2, true), Some(2));
+        assert_eq!(paged_reader.find_row(&pattern, 0, false), Some(1));
+    }
+
     #[test]
     fn test_read_half_file() {
         let test = b"firsts\nsecond\nthird";
         let mut mmap = MmapMut::map_anon(test.len()).expect("Anon mmap");
-        (&mut mmap[..]).write(test).unwrap();
+        (&mut mmap[..]).write_all(test).unwrap();
         let mmap = mmap.make_read_only().unwrap();
         let mut paged_reader = PagedReader::new(mmap);
         let expected_rows = 2;
@@ -196,7 +1532,7 @@ mod tests {
     fn test_read_whole_file() {
         let test = b"firsts\nsecond\nthird";
         let mut mmap = MmapMut::map_anon(test.len()).expect("Anon mmap");
-        (&mut mmap[..]).write(test).unwrap();
+        (&mut mmap[..]).write_all(test).unwrap();
         let mmap = mmap.make_read_only().unwrap();
         let mut paged_reader = PagedReader::new(mmap);
         let expected_rows = 3;
@@ -216,7 +1552,7 @@ abc"#;
         let expected = vec![(0, 0), (1, 4)];
 
         let mut mmap = MmapMut::map_anon(test.len()).expect("Anon mmap");
-        (&mut mmap[..]).write(test).unwrap();
+        (&mut mmap[..]).write_all(test).unwrap();
         let mut paged_reader = PagedReader::new(mmap.make_read_only().unwrap());
         let res = paged_reader
             .get_rows_indexes(10, 0)
@@ -226,11 +1562,388 @@ abc"#;
         let no_newlines = br#""#;
         let expected = vec![(0, 1)];
         let mut mmap = MmapMut::map_anon(1).expect("Anon mmap");
-        (&mut mmap[..]).write(no_newlines).unwrap();
+        (&mut mmap[..]).write_all(no_newlines).unwrap();
         let mut paged_reader = PagedReader::new(mmap.make_read_only().unwrap());
         let res = paged_reader
             .get_rows_indexes(10, 0)
             .expect("No newlines found.");
         assert_eq!(res, expected);
     }
+
+    #[test]
+    fn test_fetch_last_row_incrementally() {
+        let mut paged_reader = paged_reader_for(b"a\nb\nc\nd");
+        assert_eq!(paged_reader.get_rows_indexes(1, 0).unwrap(), vec![(0, 1)]);
+        let res = paged_reader.get_rows_indexes(10, 0).unwrap();
+        assert_eq!(res, vec![(0, 1), (2, 3), (4, 5), (6, 7)]);
+    }
+
+    #[test]
+    fn test_tail() {
+        let paged_reader = paged_reader_for(b"a\nb\nc\nd");
+        assert_eq!(paged_reader.last_lines(2), vec![(4, 5), (6, 7)]);
+        assert_eq!(paged_reader.last_lines(9).len(), 4);
+        let paged_reader = paged_reader_for(b"a\nb\n\n");
+        assert_eq!(paged_reader.last_lines(2), vec![(2, 3), (4, 4)]);
+        // The lines go back over more than a chunk, and are shown like the last page.
+        let text: String = (0..20_000).map(|i| format!("line {}\n", i)).collect();
+        let mut paged_reader = paged_reader_for(text.as_bytes());
+        let lines = paged_reader.last_lines(10_000);
+        assert_eq!(
+            lines,
+            paged_reader.get_rows_indexes(10_000, 10_000).unwrap()
+        );
+        paged_reader.set_line_numbers(true);
+        let tail = paged_reader.render_tail(0, 10, 80).unwrap();
+        let last_page = paged_reader.count_rows() - 10;
+        let (page, rows_red, cols_red) =
+            paged_reader.read_file_paged(last_page, 0, 10, 80).unwrap();
+        assert_eq!((tail.1, tail.2), (rows_red, cols_red));
+        // The same, but with a blank gutter.
+        for (tail_row, row) in tail.0.split("\n\r").zip(page.split("\n\r")) {
+            assert_eq!(tail_row[..8].trim(), "");
+            assert_eq!(tail_row[8..], row[8..]);
+        }
+    }
+
+    #[test]
+    fn test_set_text() {
+        let mut paged_reader = paged_reader_for(b"apple\nban");
+        paged_reader.add_filter(include("a"));
+        assert_eq!(2, paged_reader.count_rows());
+        // The last line was cut short, it's read again.
+        paged_reader.set_text(mmap_for(b"apple\nbanana\ncherry\navocado\n"), true);
+        let (res, rows_red, _cols_red) = paged_reader.read_file_paged(0, 0, 10, 10).unwrap();
+        assert_eq!("apple\n\rbanana\n\ravocado", res);
+        assert_eq!(3, rows_red);
+
+        paged_reader.set_text(mmap_for(b"kiwi\nmango"), false);
+        assert_eq!(1, paged_reader.count_rows());
+        assert_eq!(2, paged_reader.count_lines());
+    }
+
+    #[test]
+    fn test_byte_order_mark() {
+        let mut paged_reader = paged_reader_for(b"\xef\xbb\xbf#!/bin/sh\necho");
+        assert_eq!(paged_reader.first_line(), "#!/bin/sh");
+        let (res, _rows_red, _cols_red) = paged_reader.read_file_paged(0, 0, 2, 20).unwrap();
+        assert_eq!(res, "#!/bin/sh\n\recho");
+        // Shown in hex.
+        paged_reader.set_hex(true);
+        let (res, _rows_red, _cols_red) = paged_reader.read_file_paged(0, 0, 1, 80).unwrap();
+        assert!(res.starts_with("00000000: efbb bf23"));
+    }
+
+    #[test]
+    fn test_page_breaks() {
+        let mut paged_reader = paged_reader_for(b"one\n\x0c\n\x0ctwo\n\x0c\r\nthree");
+        let (res, _rows_red, _cols_red) = paged_reader.read_file_paged(0, 0, 5, 4).unwrap();
+        assert_eq!(res, "one\n\r────\n\r\x1b[7m^L\x1b[27mtw\n\r────\n\rthre");
+        // Scrolled right, the rules are still there.
+        let (res, _rows_red, cols_red) = paged_reader.read_file_paged(0, 3, 5, 4).unwrap();
+        assert_eq!(res, "\n\r────\n\rwo\n\r────\n\ree");
+        assert_eq!(cols_red, 4);
+        let form_feed = Regex::new(r"^\f").unwrap();
+        assert_eq!(paged_reader.find_row(&form_feed, 2, false), Some(2));
+        assert_eq!(paged_reader.find_row(&form_feed, 3, false), Some(3));
+    }
+
+    #[test]
+    fn test_separator() {
+        let mut paged_reader = paged_reader_for(b"./a b\0./c\nd\0./e\0");
+        paged_reader.set_separator(Some(b'\0'));
+        let (res, rows_red, _cols_red) = paged_reader.read_file_paged(0, 0, 10, 10).unwrap();
+        assert_eq!(res, "./a b\n\r./c\x1b[7m^J\x1b[27md\n\r./e");
+        assert_eq!(rows_red, 3);
+        let pattern = Regex::new("e").unwrap();
+        assert_eq!(paged_reader.find_row(&pattern, 0, false), Some(2));
+        // Still records after the file changed.
+        paged_reader.set_text(mmap_for(b"x\0y\nz"), false);
+        assert_eq!(paged_reader.count_lines(), 2);
+        paged_reader.set_separator(None);
+        assert_eq!(paged_reader.count_lines(), 2);
+        assert_eq!(paged_reader.first_line(), "x\u{0}y");
+    }
+
+    #[test]
+    fn test_whitespace() {
+        let mut paged_reader = paged_reader_for(b"a\tb \nc");
+        paged_reader.set_whitespace(true);
+        let (res, _rows_red, _cols_red) = paged_reader.read_file_paged(0, 0, 10, 20).unwrap();
+        // The last line has no line ending.
+        assert_eq!(
+            res,
+            "a\x1b[2m→      \x1b[22mb\x1b[2m·\x1b[22m\x1b[2m¶\x1b[22m\n\rc"
+        );
+        paged_reader.set_whitespace(false);
+        let (res, _rows_red, _cols_red) = paged_reader.read_file_paged(0, 0, 10, 20).unwrap();
+        assert_eq!(res, "a       b \n\rc");
+    }
+
+    #[test]
+    fn test_wrap() {
+        let mut paged_reader = paged_reader_for(b"short\nabcdefghijklmnopqrstuvwxy\nend\n");
+        paged_reader.set_wrap(Some(10));
+        let (res, rows_red, cols_red) = paged_reader.read_file_paged(0, 0, 10, 10).unwrap();
+        assert_eq!(res, "short\n\rabcdefghij\n\rklmnopqrst\n\ruvwxy\n\rend");
+        assert_eq!((rows_red, cols_red), (5, 0));
+        let (res, _rows_red, _cols_red) = paged_reader.read_file_paged(2, 0, 2, 10).unwrap();
+        assert_eq!(res, "klmnopqrst\n\ruvwxy");
+        assert_eq!(paged_reader.count_rows(), 5);
+        assert_eq!(paged_reader.line_of_row(3), Some(1));
+        assert_eq!(paged_reader.row_of_line(2), Some(4));
+        let position = paged_reader.position(1, 2).unwrap();
+        assert_eq!((position.first_line, position.last_line), (2, 2));
+        // From a row in the middle of a line, the search goes on from the next one.
+        let pattern = Regex::new("[ae]").unwrap();
+        assert_eq!(paged_reader.find_row(&pattern, 1, false), Some(1));
+        assert_eq!(paged_reader.find_row(&pattern, 2, false), Some(4));
+        assert_eq!(paged_reader.find_row(&pattern, 3, true), Some(1));
+
+        // The line numbers are on the first row of the line.
+        paged_reader.set_line_numbers(true);
+        paged_reader.set_wrap(Some(18));
+        let (res, _rows_red, _cols_red) = paged_reader.read_file_paged(1, 0, 2, 18).unwrap();
+        assert_eq!(res, "      2 abcdefghij\n\r        klmnopqrst");
+
+        paged_reader.set_line_numbers(false);
+        paged_reader.add_filter(include("[ad]"));
+        assert_eq!(paged_reader.count_rows(), 3);
+        assert_eq!(paged_reader.row_of_line(2), Some(2));
+        paged_reader.set_wrap(None);
+        assert_eq!(paged_reader.count_rows(), 2);
+
+        let mut paged_reader = paged_reader_for(b"one two three");
+        paged_reader.set_wrap(Some(6));
+        paged_reader.set_wrap_words(true);
+        paged_reader.set_wrap_marker("> ".to_owned());
+        let (res, rows_red, _cols_red) = paged_reader.read_file_paged(0, 0, 10, 6).unwrap();
+        let marker = "\x1b[2m> \x1b[22m";
+        assert_eq!(res, format!("one \n\r{0}two \n\r{0}thre\n\r{0}e", marker));
+        assert_eq!(rows_red, 4);
+    }
+
+    #[test]
+    fn test_filter() {
+        let mut paged_reader = paged_reader_for(b"apple\nbanana\ncherry\navocado");
+        paged_reader.add_filter(include("^a"));
+        let (res, rows_red, _cols_red) = paged_reader.read_file_paged(0, 0, 10, 10).unwrap();
+        assert_eq!("apple\n\ravocado", res);
+        assert_eq!(2, rows_red);
+        let (res, rows_red, _cols_red) = paged_reader.read_file_paged(1, 0, 10, 10).unwrap();
+        assert_eq!("avocado", res);
+        assert_eq!(1, rows_red);
+
+        paged_reader.clear_filters();
+        paged_reader.add_filter(include("nothing"));
+        let (res, rows_red, _cols_red) = paged_reader.read_file_paged(0, 0, 10, 10).unwrap();
+        assert_eq!("", res);
+        assert_eq!(0, rows_red);
+
+        paged_reader.clear_filters();
+        assert_eq!(4, paged_reader.count_rows());
+    }
+
+    #[test]
+    fn test_filtered_positions() {
+        let mut paged_reader = paged_reader_for(b"apple\nbanana\ncherry\navocado\n");
+        paged_reader.add_filter(include("^a"));
+        assert_eq!(Some(0), paged_reader.line_of_row(0));
+        assert_eq!(Some(3), paged_reader.line_of_row(1));
+        assert_eq!(None, paged_reader.line_of_row(2));
+        // banana is filtered out: avocado is the first row after it.
+        assert_eq!(Some(1), paged_reader.row_of_line(1));
+        assert_eq!(Some(1), paged_reader.row_of_line(3));
+        assert_eq!(None, paged_reader.row_of_line(4));
+        assert_eq!(2, paged_reader.count_rows());
+
+        paged_reader.clear_filters();
+        assert_eq!(Some(2), paged_reader.row_of_line(2));
+        assert_eq!(Some(3), paged_reader.line_of_row(3));
+    }
+
+    #[test]
+    fn test_stacked_filters() {
+        let mut paged_reader = paged_reader_for(b"ERROR a\nINFO b\nERROR noise\nWARN c\nERROR d");
+        paged_reader.add_filter(exclude("INFO"));
+        let (res, _rows_red, _cols_red) = paged_reader.read_file_paged(0, 0, 2, 20).unwrap();
+        assert_eq!("ERROR a\n\rERROR noise", res);
+
+        paged_reader.add_filter(include("ERROR"));
+        paged_reader.add_filter(exclude("noise"));
+        assert_eq!(3, paged_reader.filters_count());
+        let (res, rows_red, _cols_red) = paged_reader.read_file_paged(0, 0, 10, 20).unwrap();
+        assert_eq!("ERROR a\n\rERROR d", res);
+        assert_eq!(2, rows_red);
+
+        paged_reader.clear_filters();
+        assert_eq!(0, paged_reader.filters_count());
+        assert_eq!(5, paged_reader.count_rows());
+    }
+
+    #[test]
+    fn test_find_row() {
+        let mut paged_reader = paged_reader_for(b"one\ntwo\nthree\nfour\nfive");
+        let pattern = Regex::new("o").unwrap();
+        assert_eq!(Some(0), paged_reader.find_row(&pattern, 0, false));
+        assert_eq!(Some(1), paged_reader.find_row(&pattern, 1, false));
+        assert_eq!(Some(3), paged_reader.find_row(&pattern, 2, false));
+        assert_eq!(None, paged_reader.find_row(&pattern, 4, false));
+        assert_eq!(Some(1), paged_reader.find_row(&pattern, 2, true));
+        assert_eq!(
+            None,
+            paged_reader.find_row(&Regex::new("x").unwrap(), 4, true)
+        );
+
+        // Rows are counted in the filtered view.
+        paged_reader.add_filter(exclude("^t"));
+        assert_eq!(
+            Some(1),
+            paged_reader.find_row(&Regex::new("ou").unwrap(), 0, false)
+        );
+    }
+
+    #[test]
+    fn test_overstrike() {
+        let mut paged_reader = paged_reader_for(b"N\x08NA\x08AME\n_\x08x");
+        let (res, _rows_red, _cols_red) = paged_reader.read_file_paged(0, 0, 2, 3).unwrap();
+        assert_eq!("\x1b[1mNA\x1b[0mM\x1b[0m\n\r\x1b[4mx\x1b[0m\x1b[0m", res);
+        assert!(paged_reader.fits_in(2, 4));
+    }
+
+    #[test]
+    fn test_folds() {
+        let mut paged_reader = paged_reader_for(b"{\n  \"a\": [\n    1\n  ],\n  \"b\": 2\n}");
+        paged_reader.set_blocks(vec![
+            Block {
+                first_line: 0,
+                last_line: 5,
+                depth: 0,
+            },
+            Block {
+                first_line: 1,
+                last_line: 3,
+                depth: 1,
+            },
+        ]);
+        // The innermost block with the line.
+        assert_eq!(Some(1), paged_reader.toggle_fold(2));
+        let (res, _rows_red, _cols_red) = paged_reader.read_file_paged(0, 0, 10, 20).unwrap();
+        assert_eq!("{\n\r  \"a\": […],\n\r  \"b\": 2\n\r}", res);
+        assert_eq!(Some(2), paged_reader.row_of_line(4));
+        assert_eq!(1, paged_reader.shown_line(2));
+
+        // Folds stay when the filters go.
+        paged_reader.add_filter(exclude("b"));
+        assert_eq!(3, paged_reader.count_rows());
+        paged_reader.clear_filters();
+        assert_eq!(4, paged_reader.count_rows());
+
+        assert_eq!(Some(1), paged_reader.toggle_fold(1));
+        assert_eq!(6, paged_reader.count_rows());
+        assert!(paged_reader.fold_from_depth(0));
+        assert_eq!(1, paged_reader.count_rows());
+        assert_eq!(0, paged_reader.shown_line(2));
+        assert!(paged_reader.unfold_all());
+        assert!(!paged_reader.unfold_all());
+        assert_eq!(None, paged_reader.toggle_fold(6));
+    }
+
+    #[test]
+    fn test_table() {
+        let mut paged_reader = paged_reader_for(b"id | name\n1  | a\n2  | b\n3  | c");
+        paged_reader.set_pinned_header(true);
+        paged_reader.set_column_starts(vec![0, 5]);
+        // The second line scrolled under the header.
+        let (res, _rows_red, _cols_red) = paged_reader.read_file_paged(1, 0, 2, 20).unwrap();
+        assert_eq!("id | name\n\r2  | b", res);
+        paged_reader.add_filter(exclude("id"));
+        let (res, _rows_red, _cols_red) = paged_reader.read_file_paged(1, 0, 2, 20).unwrap();
+        assert_eq!("2  | b\n\r3  | c", res);
+
+        assert_eq!(5, paged_reader.scroll_right(0, 1));
+        assert_eq!(7, paged_reader.scroll_right(5, 2));
+        assert_eq!(5, paged_reader.scroll_left(7, 1));
+        assert_eq!(0, paged_reader.scroll_left(7, 3));
+    }
+
+    #[test]
+    fn test_hex() {
+        let mut paged_reader = paged_reader_for(b"first line\nsecond line\nthird");
+        paged_reader.set_line_numbers(true);
+        paged_reader.set_hex(true);
+        let (res, rows_red, _cols_red) = paged_reader.read_file_paged(1, 0, 5, 80).unwrap();
+        assert_eq!(
+            "00000010: 6420 6c69 6e65 0a74 6869 7264            d line.third",
+            res
+        );
+        assert_eq!(1, rows_red);
+        assert_eq!(2, paged_reader.count_lines());
+        assert_eq!(1, paged_reader.line_at_offset(20));
+        assert_eq!(1, paged_reader.line_at_offset(100));
+
+        paged_reader.set_hex(false);
+        assert_eq!(1, paged_reader.line_at_offset(20));
+        assert_eq!(11, paged_reader.offset_of_line(1));
+    }
+
+    #[test]
+    fn test_line_numbers() {
+        let mut paged_reader = paged_reader_for(b"first\nsecond\nthird");
+        paged_reader.set_line_numbers(true);
+        let (res, rows_red, cols_red) = paged_reader.read_file_paged(1, 0, 5, 12).unwrap();
+        assert_eq!("      2 seco\n\r      3 thir", res);
+        assert_eq!(2, rows_red);
+        assert_eq!(4, cols_red);
+
+        // Filtered out lines keep their number.
+        paged_reader.add_filter(include("^t"));
+        let (res, _rows_red, _cols_red) = paged_reader.read_file_paged(0, 0, 5, 20).unwrap();
+        assert_eq!("      3 third", res);
+    }
+
+    #[test]
+    fn test_cached_pages() {
+        let mut paged_reader = paged_reader_for(b"first\nsecond\nthird");
+        let (res, _rows_red, _cols_red) = paged_reader.read_file_paged(1, 0, 5, 12).unwrap();
+        assert_eq!("second\n\rthird", res);
+        assert_eq!(paged_reader.pages.len(), 1);
+        let (res, _rows_red, _cols_red) = paged_reader.read_file_paged(1, 0, 5, 12).unwrap();
+        assert_eq!("second\n\rthird", res);
+        assert_eq!(paged_reader.pages.len(), 1);
+        // The cached page isn't shown with the line numbers.
+        paged_reader.set_line_numbers(true);
+        let (res, _rows_red, _cols_red) = paged_reader.read_file_paged(1, 0, 5, 12).unwrap();
+        assert_eq!("      2 seco\n\r      3 thir", res);
+        for row_offset in 0..20 {
+            paged_reader.read_file_paged(row_offset, 0, 5, 12).unwrap();
+        }
+        assert_eq!(paged_reader.pages.len(), 16);
+    }
+
+    #[test]
+    fn test_count_rows() {
+        let mut paged_reader = paged_reader_for(b"a\nb\nc\n");
+        assert_eq!(paged_reader.get_rows_indexes(1, 0).unwrap(), vec![(0, 1)]);
+        assert_eq!(3, paged_reader.count_rows());
+        let res = paged_reader.get_rows_indexes(10, 0).unwrap();
+        assert_eq!(res, vec![(0, 1), (2, 3), (4, 5)]);
+
+        let mut paged_reader = paged_reader_for(b"a\nb\nc");
+        assert_eq!(3, paged_reader.count_rows());
+        paged_reader.add_filter(exclude("b"));
+        assert_eq!(2, paged_reader.count_rows());
+    }
+
+    #[test]
+    fn test_line_at_percent() {
+        // 10 lines of 10 bytes each.
+        let text = b"123456789\n".repeat(10);
+        let mut paged_reader = paged_reader_for(&text);
+        assert_eq!(0, paged_reader.line_at_percent(0));
+        assert_eq!(5, paged_reader.line_at_percent(50));
+        assert_eq!(5, paged_reader.line_at_percent(59));
+        assert_eq!(9, paged_reader.line_at_percent(100));
+        assert_eq!(9, paged_reader.line_at_percent(1000));
+    }
 }
@@ -1,14 +1,44 @@
-use crate::lesser::reader::PagedReader;
-use std::cmp::min;
+use crate::lesser::ansi::ControlChars;
+use crate::lesser::line_index::LineIndex;
+use crate::lesser::reader::{FilterPattern, PagedReader, Position};
+use crate::lesser::search::BigSearch;
+use crate::lesser::text::Text;
+use regex::bytes::Regex;
+use std::cmp::{max, min};
+use std::collections::HashMap;
 use std::io::Result;
 
 type PageToPrint = Option<String>;
 
+/// How many positions the jump list keeps.
+const JUMP_LIST_SIZE: usize = 100;
+
 pub struct ScreenMoveHandler {
-    /// Row-wise position currently displayed
+    /// First row currently displayed
     row_offset: u64,
-    /// Column-wise position currently displayed
+    /// First column currently displayed
     col_offset: u64,
+    /// Line at the top of the screen when the last filter was set. Used to get back there
+    /// if the filter is removed while nothing is displayed.
+    filter_line: u64,
+    /// Last searched pattern, and if it was searched backwards.
+    last_search: Option<(Regex, bool)>,
+    /// Screen row where goto and search put their target, starting from 1. Negative counts
+    /// from the bottom, like less's -j.
+    jump_target: i64,
+    /// Rows of the page kept on the screen when paging to the next or the previous one.
+    page_overlap: u64,
+    /// Row and column offsets set with m, by letter.
+    marks: HashMap<char, (u64, u64)>,
+    /// Row and column offsets before the last jump (goto, search, mark), for `''`.
+    before_jump: Option<(u64, u64)>,
+    /// Row and column offsets where the jumps started, oldest first, for Ctrl-O and Ctrl-I.
+    jump_list: Vec<(u64, u64)>,
+    /// The entry of jump_list we are at, or its length if we didn't walk back.
+    jump_index: usize,
+    /// The rows of the last page of a big file, when it's shown before the lines before it are
+    /// indexed: row_offset isn't known until they are.
+    tail: Option<u16>,
     paged_reader: PagedReader,
 }
 
@@ -17,70 +47,619 @@ impl ScreenMoveHandler {
         ScreenMoveHandler {
             row_offset: 0,
             col_offset: 0,
+            filter_line: 0,
+            last_search: None,
+            jump_target: 1,
+            page_overlap: 0,
+            marks: HashMap::new(),
+            before_jump: None,
+            jump_list: Vec::new(),
+            jump_index: 0,
+            tail: None,
             paged_reader,
         }
     }
+
+    pub(crate) fn set_jump_target(&mut self, jump_target: i64) {
+        self.jump_target = jump_target;
+    }
+
+    pub(crate) fn set_page_overlap(&mut self, page_overlap: u64) {
+        self.page_overlap = page_overlap;
+    }
+
+    /// How many rows count pages are, less the ones kept on the screen. At least a row each.
+    fn pages_distance(&self, count: u64, rows: u16) -> u64 {
+        let page = max((rows as u64).saturating_sub(self.page_overlap), 1);
+        page.saturating_mul(count)
+    }
+
+    /// How many rows the jump target is from the top of the screen.
+    fn jump_position(&self, rows: u16) -> u64 {
+        let last = max(rows as i64 - 1, 0);
+        // Not under the pinned header.
+        let first = if self.paged_reader.pinned_header() {
+            1
+        } else {
+            0
+        };
+        let position = if self.jump_target > 0 {
+            self.jump_target - 1
+        } else {
+            rows as i64 + self.jump_target
+        };
+        min(max(position, first), last) as u64
+    }
+
+    /// Moves so that row is on the jump target.
+    fn jump_to(&mut self, row: u64, rows: u16, cols: u16) -> Result<PageToPrint> {
+        let row_offset = row.saturating_sub(self.jump_position(rows));
+        self.jump(row_offset, self.col_offset, rows, cols)
+    }
+
+    /// Moves to the given offsets, remembering where we were for `''` and the jump list.
+    fn jump(
+        &mut self,
+        row_offset: u64,
+        col_offset: u64,
+        rows: u16,
+        cols: u16,
+    ) -> Result<PageToPrint> {
+        let from = (self.row_offset, self.col_offset);
+        let ret = self.move_to(row_offset, col_offset, rows, cols)?;
+        if ret.is_some() {
+            self.remember_jump(from);
+        }
+        Ok(ret)
+    }
+
+    /// Remembers the offsets a jump started from, for `''` and the jump list.
+    fn remember_jump(&mut self, from: (u64, u64)) {
+        self.before_jump = Some(from);
+        // A new jump forgets the ones we walked back from, like in vim.
+        self.jump_list.truncate(self.jump_index);
+        self.jump_list.push(from);
+        if self.jump_list.len() > JUMP_LIST_SIZE {
+            self.jump_list.remove(0);
+        }
+        self.jump_index = self.jump_list.len();
+    }
+
+    /// Moves to the given offsets. If there's nothing to show there, stays where it is.
+    fn move_to(
+        &mut self,
+        row_offset: u64,
+        col_offset: u64,
+        rows: u16,
+        cols: u16,
+    ) -> Result<PageToPrint> {
+        let old_col_offset = self.col_offset;
+        self.col_offset = col_offset;
+        let ret = self.move_y(row_offset, rows, cols)?;
+        if ret.is_none() {
+            self.col_offset = old_col_offset;
+        }
+        Ok(ret)
+    }
+
+    /// Goes back count entries in the jump list.
+    pub(crate) fn jump_back(&mut self, count: u64, rows: u16, cols: u16) -> Result<PageToPrint> {
+        debug!("Received jump back request");
+        if self.jump_index == self.jump_list.len() {
+            // Remember where we are, to come back here with jump_forward.
+            self.jump_list.push((self.row_offset, self.col_offset));
+        }
+        match (self.jump_index as u64).checked_sub(count) {
+            Some(index) => self.walk_jump_list(index as usize, rows, cols),
+            None => Ok(None),
+        }
+    }
+
+    /// Goes forward count entries in the jump list, after jump_back.
+    pub(crate) fn jump_forward(&mut self, count: u64, rows: u16, cols: u16) -> Result<PageToPrint> {
+        debug!("Received jump forward request");
+        match (self.jump_index as u64).checked_add(count) {
+            Some(index) if index < self.jump_list.len() as u64 => {
+                self.walk_jump_list(index as usize, rows, cols)
+            }
+            _ => Ok(None),
+        }
+    }
+
+    fn walk_jump_list(&mut self, index: usize, rows: u16, cols: u16) -> Result<PageToPrint> {
+        let (row_offset, col_offset) = self.jump_list[index];
+        let ret = self.move_to(row_offset, col_offset, rows, cols)?;
+        if ret.is_some() {
+            self.jump_index = index;
+        }
+        Ok(ret)
+    }
+
+    pub(crate) fn set_mark(&mut self, mark: char) {
+        debug!("Received set mark request: {}", mark);
+        self.marks.insert(mark, (self.row_offset, self.col_offset));
+    }
+
+    /// The line number (starting from 1) and column at the top left of the screen.
+    pub(crate) fn top_position(&mut self) -> Option<(u64, u64)> {
+        if self.tail.is_some() {
+            return None;
+        }
+        let line = self.paged_reader.line_of_row(self.row_offset)?;
+        Some((line + 1, self.col_offset))
+    }
+
+    /// Puts line_number at the top of the screen, starting from col. Used to reopen a file
+    /// where it was left, before the first page is read.
+    pub(crate) fn restore_position(&mut self, line_number: u64, col: u64) {
+        self.row_offset = self.row_of_line_or_last(line_number.saturating_sub(1));
+        self.col_offset = col;
+    }
+
+    /// The marks as line numbers (starting from 1) and columns, which unlike rows don't
+    /// depend on the filters. Used to save them for the next time.
+    pub(crate) fn line_marks(&mut self) -> HashMap<char, (u64, u64)> {
+        let mut line_marks = HashMap::new();
+        for (mark, (row, col)) in &self.marks {
+            if let Some(line) = self.paged_reader.line_of_row(*row) {
+                line_marks.insert(*mark, (line + 1, *col));
+            }
+        }
+        line_marks
+    }
+
+    /// Sets the marks saved by line_marks.
+    pub(crate) fn restore_line_marks(&mut self, line_marks: &HashMap<char, (u64, u64)>) {
+        for (mark, (line, col)) in line_marks {
+            let row = self.row_of_line_or_last(line.saturating_sub(1));
+            self.marks.insert(*mark, (row, *col));
+        }
+    }
+
+    /// Goes back to a mark, or with `'` to where we were before the last jump.
+    pub(crate) fn goto_mark(&mut self, mark: char, rows: u16, cols: u16) -> Result<PageToPrint> {
+        debug!("Received goto mark request: {}", mark);
+        let target = match mark {
+            '\'' => self.before_jump,
+            mark => self.marks.get(&mark).copied(),
+        };
+        match target {
+            Some((row_offset, col_offset)) => self.jump(row_offset, col_offset, rows, cols),
+            None => Ok(None),
+        }
+    }
+
+    fn read_page(&mut self, rows: u16, cols: u16) -> Result<(String, usize, usize)> {
+        if self.tail.is_some() {
+            self.tail = Some(rows);
+            match self.paged_reader.read_tail(self.col_offset, rows, cols) {
+                Some(page) => return Ok(page),
+                None => self.settle_tail(),
+            }
+        }
+        // The long lines are wrapped again when the screen is resized.
+        if matches!(self.paged_reader.wrap(), Some(wrap_cols) if wrap_cols != cols) {
+            self.reflow(|paged_reader| paged_reader.set_wrap(Some(cols)));
+        }
+        self.paged_reader
+            .read_file_paged(self.row_offset, self.col_offset, rows, cols)
+    }
+
     /// The first page
     pub(crate) fn initial_screen(&mut self, rows: u16, cols: u16) -> Result<PageToPrint> {
-        let (page, rows_red, cols_red) = self.paged_reader.read_file_paged(0, 0, rows, cols)?;
-        self.row_offset += rows_red as u64;
-        self.col_offset += cols_red as u64;
+        let (page, rows_red, _cols_red) = self.read_page(rows, cols)?;
         let ret = if rows_red > 0 { Some(page) } else { None };
         Ok(ret)
     }
 
-    /// Doesn't trigger any movement, just rereads the current screen.
+    /// Doesn't trigger any movement, just rereads the current screen. If the file got
+    /// shorter than where the page was, shows its last page.
     pub(crate) fn reload(&mut self, rows: u16, cols: u16) -> Result<PageToPrint> {
-        // reset the index back to the start of the line:
+        // The top line and column stay, rewrapped by read_page if the width changed.
+        let (page, rows_red, _cols_red) = self.read_page(rows, cols)?;
+        if rows_red == 0 && self.row_offset > 0 {
+            self.row_offset = self.paged_reader.count_rows().saturating_sub(rows as u64);
+            return self.redraw(rows, cols);
+        }
+        Ok(Some(page))
+    }
+
+    /// Rereads the current screen, keeping the current position.
+    pub(crate) fn redraw(&mut self, rows: u16, cols: u16) -> Result<PageToPrint> {
+        let (page, _rows_red, _cols_red) = self.read_page(rows, cols)?;
+        Ok(Some(page))
+    }
+
+    /// Pages the file again after it changed, see PagedReader::set_text.
+    pub(crate) fn set_text(&mut self, text: Text, grown: bool) {
+        self.settle_tail();
+        self.paged_reader.set_text(text, grown);
+    }
+
+    /// If the page shows the last row.
+    pub(crate) fn at_end(&mut self, rows: u16) -> bool {
+        if self.tail.is_some() {
+            return true;
+        }
+        let past_page = self.row_offset.saturating_add(rows as u64);
+        self.paged_reader.line_of_row(past_page).is_none()
+    }
+
+    /// Where the current page is, if it's not empty.
+    pub(crate) fn position(&mut self, rows: u16) -> Option<Position> {
+        // Not known until the lines before the last page are indexed.
+        if self.tail.is_some() {
+            return None;
+        }
+        self.paged_reader.position(self.row_offset, rows)
+    }
+
+    /// If the last page of a big file is shown, and the lines before it are being indexed.
+    pub(crate) fn indexing_tail(&self) -> bool {
+        self.tail.is_some()
+    }
+
+    /// If the lines before the last page of a big file are indexed now, putting the page
+    /// among them: its position can be shown. Doesn't wait for them.
+    pub(crate) fn tail_indexed(&mut self) -> bool {
+        if self.tail.is_none() || !self.paged_reader.all_indexed() {
+            return false;
+        }
+        self.settle_tail();
+        true
+    }
+
+    /// Puts the last page of a big file among the rows, before moving from it. This waits for
+    /// the lines before it to be indexed.
+    pub(crate) fn settle_tail(&mut self) {
+        if let Some(rows) = self.tail.take() {
+            self.row_offset = self.paged_reader.count_rows().saturating_sub(rows as u64);
+        }
+    }
+
+    /// How many lines the file has. This will read the whole file.
+    pub(crate) fn count_lines(&mut self) -> u64 {
+        self.paged_reader.count_lines()
+    }
+
+    pub(crate) fn line_numbers(&self) -> bool {
+        self.paged_reader.line_numbers()
+    }
+
+    pub(crate) fn line_index(&self) -> Option<&LineIndex> {
+        self.paged_reader.line_index()
+    }
+
+    /// Switches between the rendered Markdown and its markup. Lines stay the same, so does
+    /// the position.
+    pub(crate) fn toggle_markdown(&mut self, rows: u16, cols: u16) -> Result<PageToPrint> {
+        debug!("Received toggle markdown request");
+        let mut toggled = false;
+        self.reflow(|paged_reader| toggled = paged_reader.toggle_markdown());
+        if !toggled {
+            return Ok(None);
+        }
+        self.redraw(rows, cols)
+    }
+
+    /// Switches between the text and its hex dump. The byte at the top of the screen stays
+    /// there.
+    pub(crate) fn toggle_hex(&mut self, rows: u16, cols: u16) -> Result<PageToPrint> {
+        debug!("Received toggle hex request");
+        let offset = match self.paged_reader.line_of_row(self.row_offset) {
+            Some(line) => self.paged_reader.offset_of_line(line),
+            None => 0,
+        };
+        let hex = !self.paged_reader.hex();
+        self.paged_reader.set_hex(hex);
+        let line = self.paged_reader.line_at_offset(offset);
+        self.row_offset = self.row_of_line_or_last(line);
         self.col_offset = 0;
-        // Re read this page:
-        let min_row_offset = (self.row_offset as i64) - (rows as i64);
-        self.row_offset = std::cmp::max(min_row_offset, 0) as u64;
+        self.redraw(rows, cols)
+    }
 
-        let (page, rows_red, cols_red) =
-            self.paged_reader
-                .read_file_paged(self.row_offset, self.col_offset, rows, cols)?;
-        self.row_offset += rows_red as u64;
-        self.col_offset += cols_red as u64;
+    /// Shows or hides the tabs, trailing spaces and line endings.
+    pub(crate) fn toggle_whitespace(&mut self, rows: u16, cols: u16) -> Result<PageToPrint> {
+        debug!("Received toggle whitespace request");
+        let whitespace = !self.paged_reader.whitespace();
+        self.reflow(|paged_reader| paged_reader.set_whitespace(whitespace));
+        self.redraw(rows, cols)
+    }
 
-        let ret = if rows_red > 0 { Some(page) } else { None };
-        Ok(ret)
+    /// Passes the colors to the terminal, like -R, or shows their escape sequences.
+    pub(crate) fn toggle_raw_control_chars(&mut self, rows: u16, cols: u16) -> Result<PageToPrint> {
+        debug!("Received toggle raw control chars request");
+        let control_chars = match self.paged_reader.control_chars() {
+            ControlChars::Raw => ControlChars::Caret,
+            ControlChars::Caret | ControlChars::Strip => ControlChars::Raw,
+        };
+        self.reflow(|paged_reader| paged_reader.set_control_chars(control_chars));
+        self.redraw(rows, cols)
     }
 
-    // X axis: read the page and moves the col offset position
-    // from self.col_offset to self.col_offset+ cols_red.
-    fn move_x(&mut self, rows: u16, cols: u16) -> Result<PageToPrint> {
-        // Re read the same rows
-        let fixed_row_offset = std::cmp::max((self.row_offset as i64) - (rows as i64), 0) as u64;
+    pub(crate) fn raw_control_chars(&self) -> bool {
+        self.paged_reader.control_chars() == ControlChars::Raw
+    }
+
+    /// Wraps the long lines on more rows, or cuts them at the edge of the screen again.
+    pub(crate) fn toggle_wrap(&mut self, rows: u16, cols: u16) -> Result<PageToPrint> {
+        debug!("Received toggle wrap request");
+        let wrap = match self.paged_reader.wrap() {
+            Some(_cols) => None,
+            None => Some(cols),
+        };
+        self.reflow(|paged_reader| paged_reader.set_wrap(wrap));
+        self.col_offset = 0;
+        self.redraw(rows, cols)
+    }
+
+    pub(crate) fn wrap(&self) -> bool {
+        self.paged_reader.wrap().is_some()
+    }
+
+    /// Shows or hides the line numbers gutter.
+    pub(crate) fn toggle_line_numbers(&mut self, rows: u16, cols: u16) -> Result<PageToPrint> {
+        debug!("Received toggle line numbers request");
+        let line_numbers = !self.paged_reader.line_numbers();
+        self.reflow(|paged_reader| paged_reader.set_line_numbers(line_numbers));
+        self.redraw(rows, cols)
+    }
 
-        let (page, _rows_red, cols_red) =
-            self.paged_reader
-                .read_file_paged(fixed_row_offset, self.col_offset, rows, cols)?;
-        self.col_offset += cols_red as u64;
+    /// Changes how the lines are shown, keeping the line and column at the top of the screen
+    /// there: the rows the long lines are wrapped on change with it.
+    fn reflow<F: FnOnce(&mut PagedReader)>(&mut self, change: F) {
+        let top = self.paged_reader.position_of_row(self.row_offset);
+        self.remap_rows(change);
+        if let Some((line, col)) = top {
+            self.row_offset = match self.paged_reader.row_of_position(line, col) {
+                Some(row) => row,
+                None => self.row_of_line_or_last(line),
+            };
+        }
+    }
+
+    /// Changes which rows the lines are on, like the filters and the wrapping do. The rows of
+    /// the marks and of the jumps change with it: they stay on their lines.
+    fn remap_rows<F: FnOnce(&mut PagedReader)>(&mut self, change: F) {
+        let line_marks = self.line_marks();
+        let before_jump = self.before_jump.map(|jump| self.line_of_jump(jump));
+        let jump_list: Vec<_> = self
+            .jump_list
+            .clone()
+            .into_iter()
+            .map(|jump| self.line_of_jump(jump))
+            .collect();
+        change(&mut self.paged_reader);
+        self.restore_line_marks(&line_marks);
+        self.before_jump = before_jump.map(|jump| self.row_of_jump(jump));
+        self.jump_list = jump_list
+            .into_iter()
+            .map(|jump| self.row_of_jump(jump))
+            .collect();
+    }
+
+    /// The line of the row a jump went from. Past the end, past the last line.
+    fn line_of_jump(&mut self, (row, col): (u64, u64)) -> (u64, u64) {
+        (self.paged_reader.line_of_row(row).unwrap_or(u64::MAX), col)
+    }
+
+    /// The row of a jump from line_of_jump.
+    fn row_of_jump(&mut self, (line, col): (u64, u64)) -> (u64, u64) {
+        (self.row_of_line_or_last(line), col)
+    }
+
+    /// Stacks a filter on the current ones. The line at the top of the screen stays there,
+    /// or the first one shown after it.
+    pub(crate) fn add_filter(&mut self, pattern: FilterPattern) {
+        debug!("Received add filter request: {:?}", pattern);
+        self.refilter(|paged_reader| paged_reader.add_filter(pattern));
+    }
+
+    /// Removes all the filters, keeping the line at the top of the screen.
+    pub(crate) fn clear_filters(&mut self) {
+        debug!("Received clear filters request");
+        self.refilter(PagedReader::clear_filters);
+    }
+
+    pub(crate) fn filters_count(&self) -> usize {
+        self.paged_reader.filters_count()
+    }
+
+    /// Changes the filters, keeping the line at the top of the screen there.
+    fn refilter<F: FnOnce(&mut PagedReader)>(&mut self, change_filters: F) {
+        let top_line = self
+            .paged_reader
+            .line_of_row(self.row_offset)
+            .unwrap_or(self.filter_line);
+        self.filter_line = top_line;
+        self.remap_rows(change_filters);
+        self.row_offset = self.row_of_line_or_last(top_line);
+    }
+
+    /// The line on the jump target, if any.
+    pub(crate) fn target_line(&mut self, rows: u16) -> Option<u64> {
+        let target_row = self.row_offset + self.jump_position(rows);
+        self.paged_reader.line_of_row(target_row)
+    }
+
+    /// Folds the innermost object or array with the line on the jump target, or unfolds it
+    /// if it's folded. Its first line goes on the jump target.
+    pub(crate) fn toggle_fold(&mut self, rows: u16, cols: u16) -> Result<PageToPrint> {
+        debug!("Received toggle fold request");
+        let target_row = self.row_offset + self.jump_position(rows);
+        let first_line = match self.paged_reader.line_of_row(target_row) {
+            Some(line) => self.paged_reader.toggle_fold(line as usize),
+            None => None,
+        };
+        let first_line = match first_line {
+            Some(first_line) => first_line as u64,
+            None => return Ok(None),
+        };
+        let row = self.row_of_line_or_last(first_line);
+        self.row_offset = row.saturating_sub(self.jump_position(rows));
+        self.redraw(rows, cols)
+    }
+
+    /// Folds the blocks deeper than level. Without a level, unfolds them all, or if none was
+    /// folded folds all but the outermost ones. The line at the top of the screen stays
+    /// there, or the block it's folded in.
+    pub(crate) fn fold_level(
+        &mut self,
+        level: Option<u64>,
+        rows: u16,
+        cols: u16,
+    ) -> Result<PageToPrint> {
+        debug!("Received fold level request: {:?}", level);
+        let top_line = self.paged_reader.line_of_row(self.row_offset).unwrap_or(0);
+        let changed = match level {
+            Some(level) => self.paged_reader.fold_from_depth(level as usize),
+            None => self.paged_reader.unfold_all() || self.paged_reader.fold_from_depth(1),
+        };
+        if !changed {
+            return Ok(None);
+        }
+        let line = self.paged_reader.shown_line(top_line);
+        self.row_offset = self.row_of_line_or_last(line);
+        self.redraw(rows, cols)
+    }
+
+    /// The row showing line, or the first after it. If there are none, the last row.
+    fn row_of_line_or_last(&mut self, line: u64) -> u64 {
+        match self.paged_reader.row_of_line(line) {
+            Some(row) => row,
+            None => self.paged_reader.count_rows().saturating_sub(1),
+        }
+    }
+
+    /// Moves to the next row matching pattern, starting after (or before, if backwards)
+    /// the row on the jump target.
+    pub(crate) fn search(
+        &mut self,
+        pattern: Regex,
+        backwards: bool,
+        rows: u16,
+        cols: u16,
+    ) -> Result<PageToPrint> {
+        debug!(
+            "Received search request: {:?}, backwards: {}",
+            pattern, backwards
+        );
+        self.set_search(pattern, backwards);
+        self.search_next(false, 1, rows, cols)
+    }
+
+    /// Searches for pattern from now on, with search_next.
+    pub(crate) fn set_search(&mut self, pattern: Regex, backwards: bool) {
+        self.last_search = Some((pattern, backwards));
+    }
+
+    /// search_next, to run in the background if the text is big. None if it's not, or there's
+    /// no search.
+    pub(crate) fn big_search(&mut self, reverse: bool, count: u64, rows: u16) -> Option<BigSearch> {
+        if !self.paged_reader.searched_in_chunks() {
+            return None;
+        }
+        let (pattern, backwards) = self.last_search.clone()?;
+        let backwards = backwards != reverse;
+        let row = self.row_offset + self.jump_position(rows);
+        // The lines before the one on the jump target, or after it. Without filters, the rows
+        // are the lines unless they're wrapped.
+        let line = self.paged_reader.line_of_row(row).unwrap_or(row);
+        let from = if backwards {
+            self.paged_reader.offset_of_line(line)
+        } else {
+            self.paged_reader.offset_of_line(line.saturating_add(1))
+        };
+        Some(BigSearch {
+            text: self.paged_reader.text(),
+            pattern,
+            newline: self.paged_reader.newline(),
+            from,
+            backwards,
+            count,
+        })
+    }
+
+    /// Moves to the line starting at offset, found by a big search.
+    pub(crate) fn jump_to_offset(
+        &mut self,
+        offset: usize,
+        rows: u16,
+        cols: u16,
+    ) -> Result<PageToPrint> {
+        let line = self.paged_reader.line_at_offset(offset);
+        let row = self.row_of_line_or_last(line);
+        self.jump_to(row, rows, cols)
+    }
+
+    /// Repeats the last search, going to the count-th match. If reverse, in the opposite
+    /// direction.
+    pub(crate) fn search_next(
+        &mut self,
+        reverse: bool,
+        count: u64,
+        rows: u16,
+        cols: u16,
+    ) -> Result<PageToPrint> {
+        let (pattern, backwards) = match &self.last_search {
+            Some(last_search) => last_search,
+            None => return Ok(None),
+        };
+        let row = self.row_offset + self.jump_position(rows);
+        match find_nth_row(
+            &mut self.paged_reader,
+            pattern,
+            *backwards != reverse,
+            count,
+            row,
+        ) {
+            Some(row) => self.jump_to(row, rows, cols),
+            None => Ok(None),
+        }
+    }
+
+    /// Shows the page starting at the count-th form feed after the top of the screen, or
+    /// before it if backwards, with its page break at the top.
+    pub(crate) fn goto_form_feed(
+        &mut self,
+        count: u64,
+        backwards: bool,
+        rows: u16,
+        cols: u16,
+    ) -> Result<PageToPrint> {
+        debug!("Received form feed request, backwards: {}", backwards);
+        let form_feed = Regex::new(r"^\f").unwrap();
+        let row = self.row_offset;
+        match find_nth_row(&mut self.paged_reader, &form_feed, backwards, count, row) {
+            Some(row) => self.jump(row, self.col_offset, rows, cols),
+            None => Ok(None),
+        }
+    }
+
+    // X axis: read the page at self.col_offset.
+    fn move_x(&mut self, rows: u16, cols: u16) -> Result<PageToPrint> {
+        let (page, _rows_red, cols_red) = self.read_page(rows, cols)?;
         let ret = if cols_red > 0 { Some(page) } else { None };
         Ok(ret)
     }
 
-    /// Move left one column
-    pub(crate) fn move_left(&mut self, rows: u16, cols: u16) -> Result<PageToPrint> {
-        debug!("Received move right request");
-        // I need to read not from the beginning of this page, but from the beginning of the last page. Thus * 2.
-        let min_col_offset = (self.col_offset as i64) - ((cols + 1) as i64);
-        // we're not moving by rows:
-        self.col_offset = std::cmp::max(min_col_offset, 0) as u64;
+    /// Move left count columns, or count columns of a table
+    pub(crate) fn move_left(&mut self, count: u64, rows: u16, cols: u16) -> Result<PageToPrint> {
+        debug!("Received move left request");
+        self.col_offset = self.paged_reader.scroll_left(self.col_offset, count);
         self.move_x(rows, cols)
     }
 
-    /// Move right one column
-    pub(crate) fn move_right(&mut self, rows: u16, cols: u16) -> Result<PageToPrint> {
+    /// Move right count columns, or count columns of a table
+    pub(crate) fn move_right(&mut self, count: u64, rows: u16, cols: u16) -> Result<PageToPrint> {
         debug!("Received move right request");
         // This is used to avoid going back one screen if the move_x has returned None
         // (e.g it hasn't read anything).
         let old_offset = self.col_offset;
-        self.col_offset = (self.col_offset as i64 - (cols - 1) as i64) as u64; // - cols as i64) as u64;
+        self.col_offset = self.paged_reader.scroll_right(self.col_offset, count);
         let ret = self.move_x(rows, cols);
         ret.iter().for_each(|opt| {
-            if opt.is_none() && old_offset != self.col_offset {
+            if opt.is_none() {
                 self.col_offset = old_offset;
             }
         });
@@ -89,61 +668,330 @@ impl ScreenMoveHandler {
 
     // Y axis:
 
-    fn move_y(&mut self, rows: u16, cols: u16) -> Result<PageToPrint> {
-        let fixed_col_offset = std::cmp::max((self.col_offset as i64) - (cols as i64), 0) as u64;
-        let (page, rows_red, _cols_red) =
-            self.paged_reader
-                .read_file_paged(self.row_offset, fixed_col_offset, rows, cols)?;
-        // fix offset
-        self.row_offset = min(self.row_offset, self.paged_reader.cached_rows() as u64);
-        self.row_offset += rows_red as u64;
-        let ret = if rows_red > 0 { Some(page) } else { None };
+    /// Shows the page starting at row_offset. If there's nothing to show there,
+    /// stays on the current page.
+    fn move_y(&mut self, row_offset: u64, rows: u16, cols: u16) -> Result<PageToPrint> {
+        let old_offset = self.row_offset;
+        self.row_offset = row_offset;
+        let (page, rows_red, _cols_red) = self.read_page(rows, cols)?;
+        let ret = if rows_red > 0 {
+            Some(page)
+        } else {
+            self.row_offset = old_offset;
+            None
+        };
         Ok(ret)
     }
 
-    pub(crate) fn move_down_page(&mut self, rows: u16, cols: u16) -> Result<PageToPrint> {
-        debug!("Received move down page request");
-        self.move_y(rows, cols)
+    /// Moves down to the page starting at row_offset, but not past the last page: the last
+    /// row stops at the bottom of the screen. The text could still be growing, so it's the
+    /// last page of the rows there are now.
+    fn move_down_to(&mut self, row_offset: u64, rows: u16, cols: u16) -> Result<PageToPrint> {
+        let last_row = row_offset.saturating_add((rows as u64).saturating_sub(1));
+        if self.paged_reader.line_of_row(last_row).is_some() {
+            return self.move_y(row_offset, rows, cols);
+        }
+        let last_page = self.paged_reader.count_rows().saturating_sub(rows as u64);
+        // Already there, or past it after a jump to the last lines.
+        if last_page <= self.row_offset {
+            return Ok(None);
+        }
+        self.move_y(last_page, rows, cols)
     }
-    pub(crate) fn move_up_page(&mut self, rows: u16, cols: u16) -> Result<PageToPrint> {
-        debug!("Received move up request");
 
-        // I need to read not from the beginning of this page, but from the beginning of the last page. Thus * 2.
-        let min_row_offset = (self.row_offset as i64) - (rows as i64) * 2;
-        self.row_offset = std::cmp::max(min_row_offset, 0) as u64;
-        self.move_y(rows, cols)
+    /// Move down count pages
+    pub(crate) fn move_down_page(
+        &mut self,
+        count: u64,
+        rows: u16,
+        cols: u16,
+    ) -> Result<PageToPrint> {
+        debug!("Received move down page request");
+        let distance = self.pages_distance(count, rows);
+        self.move_down_to(self.row_offset.saturating_add(distance), rows, cols)
+    }
+    /// Move up count pages
+    pub(crate) fn move_up_page(&mut self, count: u64, rows: u16, cols: u16) -> Result<PageToPrint> {
+        debug!("Received move up page request");
+        let distance = self.pages_distance(count, rows);
+        self.move_y(self.row_offset.saturating_sub(distance), rows, cols)
+    }
+    /// Move down size rows, or half a page if size is None.
+    pub(crate) fn move_down_half_page(
+        &mut self,
+        size: Option<u64>,
+        rows: u16,
+        cols: u16,
+    ) -> Result<PageToPrint> {
+        debug!("Received move down half page request");
+        let distance = size.unwrap_or_else(|| half_page(rows));
+        self.move_down_to(self.row_offset.saturating_add(distance), rows, cols)
+    }
+    /// Move up size rows, or half a page if size is None.
+    pub(crate) fn move_up_half_page(
+        &mut self,
+        size: Option<u64>,
+        rows: u16,
+        cols: u16,
+    ) -> Result<PageToPrint> {
+        debug!("Received move up half page request");
+        let distance = size.unwrap_or_else(|| half_page(rows));
+        self.move_y(self.row_offset.saturating_sub(distance), rows, cols)
     }
-    pub(crate) fn move_up(&mut self, rows: u16, cols: u16) -> Result<PageToPrint> {
+    /// Move up count rows
+    pub(crate) fn move_up(&mut self, count: u64, rows: u16, cols: u16) -> Result<PageToPrint> {
         debug!("Received move up request");
-        let min_row_offset = (self.row_offset as i64) - ((rows + 1) as i64);
-        self.row_offset = std::cmp::max(min_row_offset, 0) as u64;
-        self.move_y(rows, cols)
+        self.move_y(self.row_offset.saturating_sub(count), rows, cols)
     }
 
-    pub(crate) fn move_down(&mut self, rows: u16, cols: u16) -> Result<PageToPrint> {
-        debug!("Received move up request");
-        // This is used to avoid going back one screen if the move_x has returned None
-        // (e.g it hasn't read anything).
-        let old_offset = self.row_offset;
-        self.row_offset = (self.row_offset as i64 - (rows - 1) as i64) as u64; // - cols as i64) as u64;
-        let ret = self.move_y(rows, cols);
-        ret.iter().for_each(|opt| {
-            if opt.is_none() && old_offset != self.col_offset {
-                self.row_offset = old_offset;
-            }
-        });
-        ret
+    /// Move down count rows
+    pub(crate) fn move_down(&mut self, count: u64, rows: u16, cols: u16) -> Result<PageToPrint> {
+        debug!("Received move down request");
+        self.move_down_to(self.row_offset.saturating_add(count), rows, cols)
     }
 
     pub(crate) fn move_to_beginning(&mut self, rows: u16, cols: u16) -> Result<PageToPrint> {
         debug!("Received move to beginning request");
-        self.row_offset = 0;
-        self.move_y(rows, cols)
+        self.jump(0, self.col_offset, rows, cols)
     }
 
+    /// Shows line_number (starting from 1) on the jump target. If it's filtered out, the first
+    /// line shown after it.
+    pub(crate) fn goto_line(
+        &mut self,
+        line_number: u64,
+        rows: u16,
+        cols: u16,
+    ) -> Result<PageToPrint> {
+        debug!("Received goto line request: {}", line_number);
+        let row = self.row_of_line_or_last(line_number.saturating_sub(1));
+        self.jump_to(row, rows, cols)
+    }
+
+    /// Shows the line at percent of the file (by bytes) on the jump target.
+    pub(crate) fn goto_percent(
+        &mut self,
+        percent: u64,
+        rows: u16,
+        cols: u16,
+    ) -> Result<PageToPrint> {
+        debug!("Received goto percent request: {}", percent);
+        let line = self.paged_reader.line_at_percent(percent);
+        let row = self.row_of_line_or_last(line);
+        self.jump_to(row, rows, cols)
+    }
+
+    /// Shows the last page, with the last row at the bottom of the screen.
     pub(crate) fn move_to_end(&mut self, rows: u16, cols: u16) -> Result<PageToPrint> {
         debug!("Received move to end request");
-        self.row_offset = std::u64::MAX - rows as u64;
-        self.move_y(rows, cols)
+        // In a big file it's found from the end, and the lines before it are indexed later.
+        if let Some((page, _rows_red, _cols_red)) =
+            self.paged_reader.read_tail(self.col_offset, rows, cols)
+        {
+            self.remember_jump((self.row_offset, self.col_offset));
+            self.tail = Some(rows);
+            self.paged_reader.index_in_background();
+            return Ok(Some(page));
+        }
+        let last_page = self.paged_reader.count_rows().saturating_sub(rows as u64);
+        self.jump(last_page, self.col_offset, rows, cols)
+    }
+
+    /// Shows the last page of a file being followed. Unlike move_to_end it's not a jump: it's
+    /// done each time the file grows.
+    pub(crate) fn follow_end(&mut self, rows: u16, cols: u16) -> Result<PageToPrint> {
+        let last_page = self.paged_reader.count_rows().saturating_sub(rows as u64);
+        self.move_y(last_page, rows, cols)
+    }
+}
+
+/// The count-th row with a match of pattern after row, or before it if backwards.
+fn find_nth_row(
+    paged_reader: &mut PagedReader,
+    pattern: &Regex,
+    backwards: bool,
+    count: u64,
+    row: u64,
+) -> Option<u64> {
+    let mut found = row;
+    for _ in 0..count {
+        found = if backwards {
+            paged_reader.find_row(pattern, found.checked_sub(1)?, true)?
+        } else {
+            paged_reader.find_row(pattern, found.saturating_add(1), false)?
+        };
+    }
+    Some(found)
+}
+
+/// At least one row, so d and u always move on tiny screens.
+fn half_page(rows: u16) -> u64 {
+    max(rows as u64 / 2, 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lesser::reader::{FilterPattern, PagedReader};
+    use crate::lesser::screen_move_handler::ScreenMoveHandler;
+    use memmap::MmapMut;
+    use regex::bytes::Regex;
+    use std::io::Write;
+
+    fn handler_for(text: &[u8]) -> ScreenMoveHandler {
+        let mut mmap = MmapMut::map_anon(text.len()).expect("Anon mmap");
+        (&mut mmap[..]).write_all(text).unwrap();
+        ScreenMoveHandler::new(PagedReader::new(mmap.make_read_only().unwrap()))
+    }
+
+    #[test]
+    fn test_filter_keeps_marks() {
+        let text: String = (1..=30)
+            .map(|i| format!("{} {}\n", i, if i % 2 == 0 { "even" } else { "odd" }))
+            .collect();
+        let mut handler = handler_for(text.as_bytes());
+        handler.goto_line(10, 5, 20).unwrap();
+        handler.set_mark('a');
+        handler.add_filter(FilterPattern {
+            pattern: Regex::new("even").unwrap(),
+            exclude: false,
+        });
+        handler.move_to_beginning(5, 20).unwrap();
+        handler.goto_mark('a', 5, 20).unwrap();
+        assert_eq!(handler.top_position(), Some((10, 0)));
+        handler.move_to_beginning(5, 20).unwrap();
+        handler.clear_filters();
+        handler.goto_mark('a', 5, 20).unwrap();
+        assert_eq!(handler.top_position(), Some((10, 0)));
+    }
+
+    #[test]
+    fn test_filter_keeps_jumps() {
+        let text: String = (1..=30)
+            .map(|i| format!("{} {}\n", i, if i % 2 == 0 { "even" } else { "odd" }))
+            .collect();
+        let mut handler = handler_for(text.as_bytes());
+        handler.goto_line(10, 5, 20).unwrap();
+        handler.goto_line(20, 5, 20).unwrap();
+        handler.add_filter(FilterPattern {
+            pattern: Regex::new("even").unwrap(),
+            exclude: false,
+        });
+        // Back where the jump to line 20 started.
+        handler.goto_mark('\'', 5, 20).unwrap();
+        assert_eq!(handler.top_position(), Some((10, 0)));
+        handler.clear_filters();
+        handler.jump_back(1, 5, 20).unwrap();
+        assert_eq!(handler.top_position(), Some((20, 0)));
+        handler.jump_back(1, 5, 20).unwrap();
+        assert_eq!(handler.top_position(), Some((10, 0)));
+    }
+
+    #[test]
+    fn test_toggle_wrap() {
+        let text = "a long line, wrapped on three rows\n".repeat(10);
+        let mut handler = handler_for(text.as_bytes());
+        handler.goto_line(4, 5, 12).unwrap();
+        handler.set_mark('a');
+        handler.move_down(2, 5, 12).unwrap();
+        assert_eq!(handler.top_position(), Some((6, 0)));
+
+        handler.toggle_wrap(5, 12).unwrap();
+        assert!(handler.wrap());
+        assert_eq!(handler.top_position(), Some((6, 0)));
+        handler.goto_mark('a', 5, 12).unwrap();
+        assert_eq!(handler.top_position(), Some((4, 0)));
+        // Down a row, in the middle of the fourth line.
+        handler.move_down(1, 5, 12).unwrap();
+        assert_eq!(handler.top_position(), Some((4, 0)));
+
+        handler.toggle_wrap(5, 12).unwrap();
+        assert!(!handler.wrap());
+        assert_eq!(handler.top_position(), Some((4, 0)));
+        // Back where the jump to the mark started.
+        handler.goto_mark('\'', 5, 12).unwrap();
+        assert_eq!(handler.top_position(), Some((6, 0)));
+    }
+
+    #[test]
+    fn test_toggle_raw_control_chars() {
+        let mut handler = handler_for(b"\x1b[31mred\x1b[0m\n");
+        assert!(!handler.raw_control_chars());
+        let page = handler.toggle_raw_control_chars(5, 20).unwrap().unwrap();
+        assert!(handler.raw_control_chars());
+        assert!(page.contains("\x1b[31mred"));
+        let page = handler.toggle_raw_control_chars(5, 20).unwrap().unwrap();
+        assert!(!handler.raw_control_chars());
+        assert!(!page.contains("\x1b[31m"));
+        assert!(page.contains("^[") && page.contains("red"));
+    }
+
+    #[test]
+    fn test_resize() {
+        let text = "a long line, wrapped on three rows\n".repeat(10);
+        let mut handler = handler_for(text.as_bytes());
+        handler.toggle_wrap(5, 12).unwrap();
+        handler.goto_line(4, 5, 12).unwrap();
+        // The second row of the fourth line, from column 12.
+        handler.move_down(1, 5, 12).unwrap();
+        let page = handler.reload(5, 8).unwrap().unwrap();
+        assert!(page.starts_with("ine, wra"));
+        assert_eq!(handler.top_position(), Some((4, 0)));
+
+        // Not wrapped, the page stays scrolled right.
+        handler.toggle_wrap(5, 8).unwrap();
+        handler.move_right(5, 5, 8).unwrap();
+        assert_eq!(handler.top_position(), Some((4, 5)));
+        handler.reload(5, 20).unwrap();
+        assert_eq!(handler.top_position(), Some((4, 5)));
+    }
+
+    #[test]
+    fn test_at_end() {
+        let text = "line\n".repeat(10);
+        let mut handler = handler_for(text.as_bytes());
+        handler.initial_screen(4, 80).unwrap();
+        assert!(!handler.at_end(4));
+        handler.move_down_page(1, 4, 80).unwrap();
+        assert!(!handler.at_end(4));
+        handler.move_down(2, 4, 80).unwrap();
+        assert!(handler.at_end(4));
+        // A short file is all on the first page.
+        let mut handler = handler_for(b"one\ntwo\n");
+        handler.initial_screen(4, 80).unwrap();
+        assert!(handler.at_end(4));
+    }
+
+    #[test]
+    fn test_move_down_stops_at_last_page() {
+        let text = "line\n".repeat(10);
+        let mut handler = handler_for(text.as_bytes());
+        handler.initial_screen(4, 80).unwrap();
+        handler.move_down_page(2, 4, 80).unwrap();
+        assert_eq!(handler.top_position(), Some((7, 0)));
+        assert!(handler.move_down(1, 4, 80).unwrap().is_none());
+        assert!(handler.move_down_half_page(None, 4, 80).unwrap().is_none());
+        // The last line on the top of the screen, after a goto.
+        handler.goto_line(10, 4, 80).unwrap();
+        assert!(handler.move_down(1, 4, 80).unwrap().is_none());
+        assert_eq!(handler.top_position(), Some((10, 0)));
+    }
+
+    #[test]
+    fn test_page_overlap() {
+        let text = "line\n".repeat(20);
+        let mut handler = handler_for(text.as_bytes());
+        handler.set_page_overlap(1);
+        handler.initial_screen(4, 80).unwrap();
+        // The last row of the page is the first of the next one.
+        handler.move_down_page(1, 4, 80).unwrap();
+        assert_eq!(handler.top_position(), Some((4, 0)));
+        handler.move_down_page(2, 4, 80).unwrap();
+        assert_eq!(handler.top_position(), Some((10, 0)));
+        handler.move_up_page(1, 4, 80).unwrap();
+        assert_eq!(handler.top_position(), Some((7, 0)));
+        // Still a row at a time, on a screen no bigger than the overlap.
+        handler.set_page_overlap(5);
+        handler.move_down_page(1, 4, 80).unwrap();
+        assert_eq!(handler.top_position(), Some((8, 0)));
     }
 }
@@ -0,0 +1,574 @@
+use crate::lesser::formats::Message;
+use crate::lesser::less_env::IgnoreCase;
+use crate::lesser::text::Text;
+use crossbeam_channel::{RecvTimeoutError, Sender};
+use memchr::memmem::Finder;
+use memchr::{memchr, memrchr};
+use rayon::prelude::*;
+use regex::bytes::Regex;
+use regex_syntax::hir::{Hir, HirKind, Literal, RepetitionKind, RepetitionRange};
+use std::cmp::min;
+use std::ops::Range;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+/// Big texts are searched in chunks this big, all at once on the cores.
+const SEARCH_CHUNK: usize = 16 << 20;
+/// How far the end of a line is looked for at a time.
+const LINE_END_STEP: usize = 64 << 10;
+/// How often a big search tells how far it got.
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(200);
+
+/// A pattern typed in the prompt, used for searching or filtering.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Pattern {
+    pub(crate) text: String,
+    /// Match the text as it is, instead of as a regex.
+    pub(crate) literal: bool,
+    /// Only match whole words: `err` won't match `stderr`.
+    pub(crate) whole_word: bool,
+    /// When to match the letters in either case, like with -i and -I.
+    pub(crate) ignore_case: IgnoreCase,
+}
+
+impl Pattern {
+    pub(crate) fn to_regex(&self) -> Result<Regex, regex::Error> {
+        let regex = if self.literal {
+            regex::escape(&self.text)
+        } else {
+            self.text.clone()
+        };
+        let regex = if self.whole_word {
+            format!(r"\b(?:{})\b", regex)
+        } else {
+            regex
+        };
+        let ignore_case = match self.ignore_case {
+            IgnoreCase::Never => false,
+            // Like in less, a capital in the pattern means the case matters.
+            IgnoreCase::Smart => !self.text.chars().any(char::is_uppercase),
+            IgnoreCase::Always => true,
+        };
+        if ignore_case {
+            Regex::new(&format!("(?i){}", regex))
+        } else {
+            Regex::new(&regex)
+        }
+    }
+}
+
+/// The numbers of the big searches, to tell the one running from the ones cancelled.
+static BIG_SEARCHES: AtomicU64 = AtomicU64::new(0);
+
+/// A search for the count-th line with a match in a text too big to wait for it: it's done in
+/// a thread of its own, so that a key can cancel it.
+pub(crate) struct BigSearch {
+    pub(crate) text: Arc<Text>,
+    pub(crate) pattern: Regex,
+    /// The byte the lines end with.
+    pub(crate) newline: u8,
+    /// Where the lines looked at start, or end if backwards.
+    pub(crate) from: usize,
+    pub(crate) backwards: bool,
+    pub(crate) count: u64,
+}
+
+/// A big search running. Dropping it cancels it.
+pub(crate) struct RunningSearch {
+    id: u64,
+    progress: Arc<Progress>,
+    /// How much of the text it searched, the last it said.
+    percent: u8,
+}
+
+/// How far a search in chunks got, and if it's to stop.
+#[derive(Default)]
+pub(crate) struct Progress {
+    cancelled: AtomicBool,
+    /// How many bytes were searched.
+    scanned: AtomicUsize,
+}
+
+impl BigSearch {
+    /// Starts looking. Until it's done, it sends Message::SearchProgress with the id of the
+    /// running search every PROGRESS_INTERVAL. Then Message::SearchDone, with where the line
+    /// found starts.
+    pub(crate) fn spawn(self, sender: Sender<Message>) -> RunningSearch {
+        let id = BIG_SEARCHES.fetch_add(1, Ordering::Relaxed);
+        let progress = Arc::new(Progress::default());
+        let search_progress = Arc::clone(&progress);
+        thread::spawn(move || {
+            let to_scan = if self.backwards {
+                self.from
+            } else {
+                self.text.len().saturating_sub(self.from)
+            };
+            let (found_sender, found) = crossbeam_channel::bounded(1);
+            let run_progress = Arc::clone(&search_progress);
+            thread::spawn(move || found_sender.send(self.run(&run_progress)));
+            loop {
+                let message = match found.recv_timeout(PROGRESS_INTERVAL) {
+                    Ok(found) => Message::SearchDone(id, found),
+                    Err(RecvTimeoutError::Timeout) => {
+                        Message::SearchProgress(id, search_progress.percent(to_scan))
+                    }
+                    Err(RecvTimeoutError::Disconnected) => return,
+                };
+                if search_progress.cancelled.load(Ordering::Relaxed) {
+                    return;
+                }
+                let done = matches!(message, Message::SearchDone(..));
+                // lesser could be quitting.
+                if sender.send(message).is_err() || done {
+                    return;
+                }
+            }
+        });
+        RunningSearch {
+            id,
+            progress,
+            percent: 0,
+        }
+    }
+
+    fn run(&self, progress: &Progress) -> Option<usize> {
+        let mut from = self.from;
+        let mut found = None;
+        for _ in 0..self.count {
+            let lines = if self.backwards {
+                0..from
+            } else {
+                from..self.text.len()
+            };
+            let start = find_line(
+                &self.text,
+                &self.pattern,
+                self.newline,
+                lines,
+                self.backwards,
+                progress,
+            )?;
+            found = Some(start);
+            from = if self.backwards {
+                start
+            } else {
+                line_end(&self.text, self.newline, start) + 1
+            };
+        }
+        found
+    }
+}
+
+impl RunningSearch {
+    pub(crate) fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub(crate) fn percent(&self) -> u8 {
+        self.percent
+    }
+
+    pub(crate) fn set_percent(&mut self, percent: u8) {
+        self.percent = percent;
+    }
+}
+
+impl Drop for RunningSearch {
+    fn drop(&mut self) {
+        self.progress.cancelled.store(true, Ordering::Relaxed);
+    }
+}
+
+impl Progress {
+    /// How much of to_scan bytes was searched. Chunks past a match can be searched too, and
+    /// the count-th match is looked for again from each match, so it's no more than 99 until
+    /// it's done.
+    fn percent(&self, to_scan: usize) -> u8 {
+        let scanned = self.scanned.load(Ordering::Relaxed) as u64;
+        min(scanned * 100 / to_scan.max(1) as u64, 99) as u8
+    }
+}
+
+/// The start of the first line starting in lines with a match of pattern, or of the last one
+/// if backwards. A line starts at lines.start, and they end with newline. The lines are
+/// matched one by one, like when they're paged, but the chunks of text are searched in
+/// parallel. None as soon as it's cancelled, progress counts the bytes searched.
+pub(crate) fn find_line(
+    text: &Text,
+    pattern: &Regex,
+    newline: u8,
+    lines: Range<usize>,
+    backwards: bool,
+    progress: &Progress,
+) -> Option<usize> {
+    find_line_in_chunks(
+        text,
+        pattern,
+        newline,
+        lines,
+        backwards,
+        progress,
+        SEARCH_CHUNK,
+    )
+}
+
+fn find_line_in_chunks(
+    text: &Text,
+    pattern: &Regex,
+    newline: u8,
+    lines: Range<usize>,
+    backwards: bool,
+    progress: &Progress,
+    chunk_size: usize,
+) -> Option<usize> {
+    let chunks: Vec<usize> = lines.clone().step_by(chunk_size).collect();
+    let literal = required_literal(pattern);
+    let literal = literal.as_ref().map(Finder::new);
+    // Each chunk has the lines starting in it, even the ones ending in the next chunks.
+    let search = |chunk: &usize| {
+        // Nor are the pages a truncation took away read: the pager shows the file again.
+        if progress.cancelled.load(Ordering::Relaxed) || text.shrunk() {
+            return None;
+        }
+        let starts = *chunk..min(chunk + chunk_size, lines.end);
+        let scanned = starts.len();
+        let found = find_in_chunk(
+            text,
+            pattern,
+            literal.as_ref(),
+            newline,
+            starts,
+            lines.start,
+            backwards,
+        );
+        progress.scanned.fetch_add(scanned, Ordering::Relaxed);
+        found
+    };
+    if backwards {
+        chunks.par_iter().find_map_last(search)
+    } else {
+        chunks.par_iter().find_map_first(search)
+    }
+}
+
+/// The first (or last) line starting in starts with a match. A line starts at first. If all
+/// the matches have literal in them, only the lines with it are matched.
+fn find_in_chunk(
+    text: &Text,
+    pattern: &Regex,
+    literal: Option<&Finder>,
+    newline: u8,
+    starts: Range<usize>,
+    first: usize,
+    backwards: bool,
+) -> Option<usize> {
+    // The chunk starts after the newline before it, or with the first line.
+    let from = if starts.start == first {
+        starts.start
+    } else {
+        starts.start - 1
+    };
+    let to = line_end(text, newline, starts.end - 1);
+    let bytes = text.bytes(from..to);
+    let mut line_start = if starts.start == first {
+        0
+    } else {
+        memchr(newline, &bytes)? + 1
+    };
+    let mut found = None;
+    while from + line_start < starts.end {
+        if let Some(literal) = literal {
+            let at = match literal.find(&bytes[line_start..]) {
+                Some(at) => line_start + at,
+                None => break,
+            };
+            // The start of the line with it.
+            line_start = memrchr(newline, &bytes[line_start..at])
+                .map_or(line_start, |end| line_start + end + 1);
+            if from + line_start >= starts.end {
+                break;
+            }
+        }
+        let line_end =
+            memchr(newline, &bytes[line_start..]).map_or(bytes.len(), |end| line_start + end);
+        if pattern.is_match(&bytes[line_start..line_end]) {
+            found = Some(from + line_start);
+            if !backwards {
+                break;
+            }
+        }
+        line_start = line_end + 1;
+    }
+    found
+}
+
+/// The longest string all the matches of pattern have in them, if any: it's looked for with
+/// memmem, at the speed of memory, and the regex only runs on the lines with it.
+fn required_literal(pattern: &Regex) -> Option<Vec<u8>> {
+    let hir = regex_syntax::Parser::new().parse(pattern.as_str()).ok()?;
+    let literal = match required(&hir) {
+        Required::Exactly(literal) | Required::Within(literal) => literal,
+    };
+    Some(literal).filter(|literal| !literal.is_empty())
+}
+
+/// What all the matches of a regex have in them.
+enum Required {
+    /// It only matches this string.
+    Exactly(Vec<u8>),
+    /// They all have this string in them. It can be empty.
+    Within(Vec<u8>),
+}
+
+fn required(hir: &Hir) -> Required {
+    match hir.kind() {
+        // They match no bytes.
+        HirKind::Empty | HirKind::Anchor(_) | HirKind::WordBoundary(_) => Required::Exactly(vec![]),
+        HirKind::Literal(Literal::Unicode(c)) => Required::Exactly(c.to_string().into_bytes()),
+        HirKind::Literal(Literal::Byte(byte)) => Required::Exactly(vec![*byte]),
+        HirKind::Group(group) => required(&group.hir),
+        HirKind::Repetition(repetition) => {
+            let at_least_once = match &repetition.kind {
+                RepetitionKind::ZeroOrOne | RepetitionKind::ZeroOrMore => false,
+                RepetitionKind::OneOrMore => true,
+                RepetitionKind::Range(RepetitionRange::Exactly(min))
+                | RepetitionKind::Range(RepetitionRange::AtLeast(min))
+                | RepetitionKind::Range(RepetitionRange::Bounded(min, _)) => *min > 0,
+            };
+            match required(&repetition.hir) {
+                Required::Exactly(literal) | Required::Within(literal) if at_least_once => {
+                    Required::Within(literal)
+                }
+                _ => Required::Within(vec![]),
+            }
+        }
+        // The strings of the parts in a row that only match one, joined.
+        HirKind::Concat(hirs) => {
+            let mut longest = vec![];
+            let mut joined = vec![];
+            let mut exactly = true;
+            for hir in hirs {
+                match required(hir) {
+                    Required::Exactly(literal) => joined.extend(literal),
+                    Required::Within(literal) => {
+                        exactly = false;
+                        for literal in [std::mem::take(&mut joined), literal] {
+                            if literal.len() > longest.len() {
+                                longest = literal;
+                            }
+                        }
+                    }
+                }
+            }
+            if exactly {
+                Required::Exactly(joined)
+            } else if joined.len() > longest.len() {
+                Required::Within(joined)
+            } else {
+                Required::Within(longest)
+            }
+        }
+        HirKind::Class(_) | HirKind::Alternation(_) => Required::Within(vec![]),
+    }
+}
+
+/// Where the line with the byte at offset ends: at its newline, or at the end of the text.
+fn line_end(text: &Text, newline: u8, mut offset: usize) -> usize {
+    let len = text.len();
+    while offset < len {
+        let end = min(offset.saturating_add(LINE_END_STEP), len);
+        if let Some(line_end) = memchr(newline, &text.bytes(offset..end)) {
+            return offset + line_end;
+        }
+        offset = end;
+    }
+    len
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lesser::less_env::IgnoreCase;
+    use crate::lesser::search::{
+        find_line_in_chunks, required_literal, BigSearch, Pattern, Progress,
+    };
+    use crate::lesser::text::Text;
+    use regex::bytes::Regex;
+    use std::sync::atomic::Ordering;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_literal_pattern() {
+        let pattern = Pattern {
+            text: "foo[1]".to_owned(),
+            literal: true,
+            whole_word: false,
+            ignore_case: IgnoreCase::Never,
+        };
+        let regex = pattern.to_regex().unwrap();
+        assert!(regex.is_match(b"call foo[1] here"));
+        assert!(!regex.is_match(b"foo1"));
+
+        let pattern = Pattern {
+            literal: false,
+            ..pattern
+        };
+        assert!(pattern.to_regex().unwrap().is_match(b"foo1"));
+
+        let pattern = Pattern {
+            text: "a.b*(".to_owned(),
+            literal: true,
+            whole_word: false,
+            ignore_case: IgnoreCase::Never,
+        };
+        assert!(pattern.to_regex().unwrap().is_match(b"a.b*("));
+    }
+
+    #[test]
+    fn test_whole_word_pattern() {
+        let pattern = Pattern {
+            text: "err|warn".to_owned(),
+            literal: false,
+            whole_word: true,
+            ignore_case: IgnoreCase::Never,
+        };
+        let regex = pattern.to_regex().unwrap();
+        assert!(regex.is_match(b"an err here"));
+        assert!(regex.is_match(b"warn: x"));
+        assert!(!regex.is_match(b"written to stderr"));
+        assert!(!regex.is_match(b"warning"));
+    }
+
+    #[test]
+    fn test_ignore_case_pattern() {
+        let pattern = Pattern {
+            text: "Err".to_owned(),
+            literal: false,
+            whole_word: true,
+            ignore_case: IgnoreCase::Always,
+        };
+        let regex = pattern.to_regex().unwrap();
+        assert!(regex.is_match(b"an ERR here"));
+        assert!(regex.is_match(b"err: x"));
+        assert!(!regex.is_match(b"stderr"));
+
+        let pattern = Pattern {
+            text: "A.B".to_owned(),
+            literal: true,
+            ..pattern
+        };
+        assert!(pattern.to_regex().unwrap().is_match(b"a.b"));
+        assert!(!pattern.to_regex().unwrap().is_match(b"axb"));
+
+        let pattern = Pattern {
+            ignore_case: IgnoreCase::Never,
+            ..pattern
+        };
+        assert!(!pattern.to_regex().unwrap().is_match(b"a.b"));
+    }
+
+    #[test]
+    fn test_smart_ignore_case_pattern() {
+        let pattern = Pattern {
+            text: "err".to_owned(),
+            literal: false,
+            whole_word: false,
+            ignore_case: IgnoreCase::Smart,
+        };
+        assert!(pattern.to_regex().unwrap().is_match(b"an ERR here"));
+
+        let pattern = Pattern {
+            text: "Err".to_owned(),
+            ..pattern
+        };
+        assert!(pattern.to_regex().unwrap().is_match(b"Err: x"));
+        assert!(!pattern.to_regex().unwrap().is_match(b"an ERR here"));
+        assert!(!pattern.to_regex().unwrap().is_match(b"err: x"));
+    }
+
+    #[test]
+    fn test_find_line() {
+        let text = Text::from(b"ab\nfoo bar\nxx\n^foo\nlast foo".to_vec());
+        let len = text.len();
+        let foo = Regex::new("foo").unwrap();
+        // Anchors match at the start of each line.
+        let start = Regex::new("^x").unwrap();
+        let running = Progress::default();
+        // Lines start and end in other chunks.
+        for chunk_size in &[1, 4, 100] {
+            let find = |pattern, lines, backwards| {
+                find_line_in_chunks(
+                    &text,
+                    pattern,
+                    b'\n',
+                    lines,
+                    backwards,
+                    &running,
+                    *chunk_size,
+                )
+            };
+            assert_eq!(find(&foo, 0..len, false), Some(3));
+            assert_eq!(find(&foo, 11..len, false), Some(14));
+            assert_eq!(find(&foo, 0..len, true), Some(19));
+            assert_eq!(find(&foo, 0..15, true), Some(14));
+            assert_eq!(find(&foo, 0..3, true), None);
+            assert_eq!(find(&start, 0..len, false), Some(11));
+        }
+        // Lines ending with a lone \r.
+        let old_mac = Text::from(b"ab\rfoo\rx foo".to_vec());
+        let find =
+            |backwards| find_line_in_chunks(&old_mac, &foo, b'\r', 0..12, backwards, &running, 4);
+        assert_eq!(find(false), Some(3));
+        assert_eq!(find(true), Some(7));
+        // All the chunks searched, with no match.
+        let progress = Progress::default();
+        let none = Regex::new("none").unwrap();
+        assert_eq!(
+            find_line_in_chunks(&text, &none, b'\n', 0..len, true, &progress, 4),
+            None
+        );
+        assert_eq!(progress.percent(len * 2), 50);
+        assert_eq!(progress.percent(len), 99);
+        progress.cancelled.store(true, Ordering::Relaxed);
+        assert_eq!(
+            find_line_in_chunks(&text, &foo, b'\n', 0..len, false, &progress, 4),
+            None
+        );
+    }
+
+    #[test]
+    fn test_required_literal() {
+        let literal = |pattern| required_literal(&Regex::new(pattern).unwrap());
+        assert_eq!(literal("foo"), Some(b"foo".to_vec()));
+        assert_eq!(literal(r"\b(?:err)\b"), Some(b"err".to_vec()));
+        assert_eq!(literal("a+bcd"), Some(b"bcd".to_vec()));
+        assert_eq!(literal("fo[o]bar"), Some(b"bar".to_vec()));
+        assert_eq!(literal("(abc)+d"), Some(b"abc".to_vec()));
+        assert_eq!(literal("foo|bar"), None);
+        assert_eq!(literal("x*"), None);
+        assert_eq!(literal("(?i)foo"), None);
+    }
+
+    #[test]
+    fn test_big_search() {
+        let text = Arc::new(Text::from(b"ab\nfoo bar\nxx\n^foo\nlast foo".to_vec()));
+        let running = Progress::default();
+        let search = |from, backwards, count| {
+            let search = BigSearch {
+                text: Arc::clone(&text),
+                pattern: Regex::new("foo").unwrap(),
+                newline: b'\n',
+                from,
+                backwards,
+                count,
+            };
+            search.run(&running)
+        };
+        assert_eq!(search(0, false, 2), Some(14));
+        assert_eq!(search(3, false, 3), Some(19));
+        assert_eq!(search(3, false, 4), None);
+        assert_eq!(search(19, true, 2), Some(3));
+        assert_eq!(search(19, true, 3), None);
+    }
+}
@@ -0,0 +1,238 @@
+use std::collections::HashMap;
+use std::io::{ErrorKind, Result};
+use std::path::{Path, PathBuf};
+
+/// What is remembered about a file between sessions.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub(crate) struct FileState {
+    /// Line and column of each mark, by letter.
+    pub(crate) marks: HashMap<char, (u64, u64)>,
+    /// Line and column at the top left of the screen when the file was closed.
+    pub(crate) position: Option<(u64, u64)>,
+}
+
+impl FileState {
+    fn is_empty(&self) -> bool {
+        self.marks.is_empty() && self.position.is_none()
+    }
+}
+
+/// How many files the state file remembers. The ones not opened for the longest are dropped.
+const MAX_FILES: usize = 1000;
+
+/// The state file, `$XDG_STATE_HOME/lesser/state` or `~/.local/state/lesser/state`, like
+/// less's history file. Each file has a `[/path/of/the/file]` section, followed by its marks
+/// as `mark <letter> <line> <col>` and its last position as `position <line> <col>`.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct State {
+    /// The last opened file last.
+    files: Vec<(PathBuf, FileState)>,
+}
+
+impl State {
+    /// A missing state file is an empty state.
+    pub(crate) fn load(path: &Path) -> Result<State> {
+        match std::fs::read_to_string(path) {
+            Ok(text) => Ok(State::parse(&text)),
+            Err(error) if error.kind() == ErrorKind::NotFound => Ok(State::default()),
+            Err(error) => Err(error),
+        }
+    }
+
+    pub(crate) fn save(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(path, self.to_text())
+    }
+
+    /// Lines that don't make sense are skipped: losing a mark is better than not starting.
+    fn parse(text: &str) -> State {
+        let mut state = State::default();
+        for line in text.lines() {
+            if let Some(path) = line
+                .strip_prefix('[')
+                .and_then(|line| line.strip_suffix(']'))
+            {
+                state
+                    .files
+                    .push((PathBuf::from(path), FileState::default()));
+                continue;
+            }
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let file = state.files.last_mut().map(|(_path, file)| file);
+            match (words.as_slice(), file) {
+                (["mark", mark, line, col], Some(file)) => {
+                    let mut chars = mark.chars();
+                    if let (Some(mark), None, Ok(line), Ok(col)) =
+                        (chars.next(), chars.next(), line.parse(), col.parse())
+                    {
+                        file.marks.insert(mark, (line, col));
+                    }
+                }
+                (["position", line, col], Some(file)) => {
+                    if let (Ok(line), Ok(col)) = (line.parse(), col.parse()) {
+                        file.position = Some((line, col));
+                    }
+                }
+                _ => debug!("Skipping state line: {}", line),
+            }
+        }
+        state
+    }
+
+    fn to_text(&self) -> String {
+        let mut text = String::new();
+        for (path, file) in &self.files {
+            text.push_str(&format!("[{}]\n", path.display()));
+            if let Some((line, col)) = file.position {
+                text.push_str(&format!("position {} {}\n", line, col));
+            }
+            let mut marks: Vec<_> = file.marks.iter().collect();
+            marks.sort();
+            for (mark, (line, col)) in marks {
+                text.push_str(&format!("mark {} {} {}\n", mark, line, col));
+            }
+        }
+        text
+    }
+
+    pub(crate) fn file(&self, path: &Path) -> Option<&FileState> {
+        self.files
+            .iter()
+            .find(|(file_path, _file)| file_path == path)
+            .map(|(_path, file)| file)
+    }
+
+    /// Replaces what is remembered about path, making it the last opened file. Files with
+    /// nothing to remember are dropped.
+    pub(crate) fn set_file(&mut self, path: PathBuf, file: FileState) {
+        self.files.retain(|(file_path, _file)| *file_path != path);
+        if !file.is_empty() {
+            self.files.push((path, file));
+        }
+        let extra = self.files.len().saturating_sub(MAX_FILES);
+        self.files.drain(..extra);
+    }
+}
+
+/// How many patterns the search history keeps.
+const MAX_PATTERNS: usize = 100;
+
+/// The patterns searched or filtered, oldest first, saved in `history` next to the state
+/// file, one per line.
+#[derive(Debug, Default)]
+pub(crate) struct SearchHistory {
+    patterns: Vec<String>,
+    path: Option<PathBuf>,
+}
+
+impl SearchHistory {
+    /// A history file that can't be read is an empty history.
+    pub(crate) fn load() -> SearchHistory {
+        let path = state_dir().map(|dir| dir.join("history"));
+        let patterns = match path.as_ref().map(std::fs::read_to_string) {
+            Some(Ok(text)) => text.lines().map(str::to_owned).collect(),
+            Some(Err(error)) if error.kind() != ErrorKind::NotFound => {
+                debug!("Can't read the search history: {}", error);
+                Vec::new()
+            }
+            _ => Vec::new(),
+        };
+        SearchHistory { patterns, path }
+    }
+
+    pub(crate) fn patterns(&self) -> &[String] {
+        &self.patterns
+    }
+
+    /// Adds pattern as the last one, and saves the history.
+    pub(crate) fn add(&mut self, pattern: &str) {
+        if pattern.is_empty() {
+            return;
+        }
+        self.patterns.retain(|old| old != pattern);
+        self.patterns.push(pattern.to_owned());
+        let extra = self.patterns.len().saturating_sub(MAX_PATTERNS);
+        self.patterns.drain(..extra);
+        if let Err(error) = self.save() {
+            debug!("Can't write the search history: {}", error);
+        }
+    }
+
+    fn save(&self) -> Result<()> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let mut text = self.patterns.join("\n");
+        text.push('\n');
+        std::fs::write(path, text)
+    }
+}
+
+/// `$XDG_STATE_HOME/lesser/state`, or `~/.local/state/lesser/state`.
+pub(crate) fn state_path() -> Option<PathBuf> {
+    Some(state_dir()?.join("state"))
+}
+
+/// `$XDG_STATE_HOME/lesser`, or `~/.local/state/lesser`.
+fn state_dir() -> Option<PathBuf> {
+    let state_dir = match std::env::var_os("XDG_STATE_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var_os("HOME")?)
+            .join(".local")
+            .join("state"),
+    };
+    Some(state_dir.join("lesser"))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lesser::state::{FileState, SearchHistory, State};
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    fn test_state_round_trip() {
+        let mut state = State::default();
+        let mut file = FileState::default();
+        file.marks.insert('b', (120, 0));
+        file.marks.insert('a', (7, 4));
+        state.set_file(PathBuf::from("/var/log/big.log"), file);
+        let file = FileState {
+            position: Some((42, 0)),
+            ..FileState::default()
+        };
+        state.set_file(PathBuf::from("/tmp/small"), file);
+        state.set_file(PathBuf::from("/tmp/empty"), FileState::default());
+        let text = state.to_text();
+        assert_eq!(
+            text,
+            "[/var/log/big.log]\nmark a 7 4\nmark b 120 0\n[/tmp/small]\nposition 42 0\n"
+        );
+        assert_eq!(State::parse(&text), state);
+
+        let state = State::parse("mark x 1 1\n[/a]\nmark ab 1 1\nmark c 3 nope\nmark d 5 0\n");
+        let file = state.file(Path::new("/a")).unwrap();
+        assert_eq!(file.marks.len(), 1);
+        assert_eq!(file.marks.get(&'d'), Some(&(5, 0)));
+    }
+
+    #[test]
+    fn test_search_history() {
+        let mut history = SearchHistory::default();
+        history.add("error");
+        history.add("warn");
+        history.add("");
+        history.add("error");
+        assert_eq!(history.patterns(), ["warn", "error"]);
+        for i in 0..200 {
+            history.add(&i.to_string());
+        }
+        assert_eq!(history.patterns().len(), 100);
+        assert_eq!(history.patterns()[0], "100");
+    }
+}
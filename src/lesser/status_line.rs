@@ -0,0 +1,278 @@
+use crate::lesser::reader::Position;
+
+/// Which of the prompts is shown in the status line: `-m` picks the medium one, `-M` the long one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PromptStyle {
+    Short,
+    Medium,
+    Long,
+}
+
+/// The status line formats, in the less prompt language (see `man less`, PROMPTS):
+/// `%f` is the file name, `%i`/`%m` which file it is and how many there are, `%lt`/`%lb`
+/// the top/bottom line, `%L` the number of lines, `%pt`/`%pb` the percent at the top/bottom
+/// line, `?x..:..` shows a part only if `x` is known (`e` is, at the end of the file), `\`
+/// escapes the next char.
+pub struct Prompts {
+    /// The lines shown and how far in the file they are.
+    short: String,
+    /// Plus how many lines there are.
+    medium: String,
+    /// Plus how far in the file the first line is.
+    long: String,
+}
+
+impl Default for Prompts {
+    fn default() -> Self {
+        Prompts {
+            short: "?f%f:Standard input.?m (file %i of %m).?lt lines %lt-%lb %pb\\%.?e (END)."
+                .to_owned(),
+            medium: "?f%f:Standard input.?m (file %i of %m).?lt lines %lt-%lb?L/%L. %pb\\%.?e \
+                     (END)."
+                .to_owned(),
+            long: "?f%f:Standard input.?m (file %i of %m).?lt lines %lt-%lb?L/%L. %pt-%pb\\%.?e \
+                   (END)."
+                .to_owned(),
+        }
+    }
+}
+
+impl Prompts {
+    /// Sets a prompt like less's -P: `s`, `m` or `M` first picks the short, medium or long
+    /// one. Anything else sets the short one.
+    pub fn set(&mut self, prompt: &str) {
+        let mut chars = prompt.chars();
+        let style = match chars.next() {
+            Some('s') => PromptStyle::Short,
+            Some('m') => PromptStyle::Medium,
+            Some('M') => PromptStyle::Long,
+            _ => return self.set_style(PromptStyle::Short, prompt),
+        };
+        self.set_style(style, chars.as_str());
+    }
+
+    pub fn set_style(&mut self, style: PromptStyle, prompt: &str) {
+        *self.style_mut(style) = prompt.to_owned();
+    }
+
+    pub fn get(mut self, style: PromptStyle) -> String {
+        std::mem::take(self.style_mut(style))
+    }
+
+    fn style_mut(&mut self, style: PromptStyle) -> &mut String {
+        match style {
+            PromptStyle::Short => &mut self.short,
+            PromptStyle::Medium => &mut self.medium,
+            PromptStyle::Long => &mut self.long,
+        }
+    }
+}
+
+/// What the status line can show.
+pub(crate) struct StatusInfo<'a> {
+    /// None if reading from a pipe.
+    pub(crate) name: Option<&'a str>,
+    /// Which file is shown, from 1, and how many there are. None if there's only one.
+    pub(crate) files: Option<(usize, usize)>,
+    /// None if nothing is shown.
+    pub(crate) position: Option<Position>,
+    /// Only known if the prompt asks for it, since it reads the whole file.
+    pub(crate) total_lines: Option<u64>,
+}
+
+impl<'a> StatusInfo<'a> {
+    fn value(&self, escape: &str) -> Option<String> {
+        let position = self.position.as_ref();
+        match escape {
+            "f" => self.name.map(str::to_owned),
+            "i" => self.files.map(|(index, _count)| index.to_string()),
+            "m" => self.files.map(|(_index, count)| count.to_string()),
+            "l" | "lt" => position.map(|position| position.first_line.to_string()),
+            "lb" => position.map(|position| position.last_line.to_string()),
+            "L" => self.total_lines.map(|lines| lines.to_string()),
+            "p" | "pt" => position.map(|position| position.first_percent.to_string()),
+            "pb" => position.map(|position| position.percent.to_string()),
+            // At the end of the file: nothing to show, but known for `?e`.
+            "e" if matches!(position, Some(position) if position.percent >= 100) => {
+                Some(String::new())
+            }
+            _ => None,
+        }
+    }
+}
+
+/// If the prompt needs the number of lines, which is expensive to know.
+pub(crate) fn needs_total_lines(prompt: &str) -> bool {
+    prompt.contains("%L") || prompt.contains("?L")
+}
+
+/// Replaces the escapes of prompt with their value.
+pub(crate) fn format_prompt(prompt: &str, info: &StatusInfo) -> String {
+    let mut formatter = Formatter {
+        chars: prompt.chars().collect(),
+        position: 0,
+        info,
+        text: String::new(),
+    };
+    formatter.sequence(true, false);
+    formatter.text
+}
+
+struct Formatter<'a, 'b> {
+    chars: Vec<char>,
+    position: usize,
+    info: &'a StatusInfo<'b>,
+    text: String,
+}
+
+impl<'a, 'b> Formatter<'a, 'b> {
+    /// Formats until the end of the current condition branch (`:` or `.`), if in_condition.
+    /// Nothing is written if not emit, the text is only skipped.
+    fn sequence(&mut self, emit: bool, in_condition: bool) {
+        while let Some(c) = self.next() {
+            match c {
+                ':' | '.' if in_condition => {
+                    self.position -= 1;
+                    return;
+                }
+                '\\' => {
+                    if let Some(c) = self.next() {
+                        self.push(emit, c);
+                    }
+                }
+                '%' => {
+                    let escape = self.escape();
+                    if let Some(value) = self.info.value(&escape) {
+                        if emit {
+                            self.text.push_str(&value);
+                        }
+                    }
+                }
+                '?' => {
+                    let escape = self.escape();
+                    let known = self.info.value(&escape).is_some();
+                    self.sequence(emit && known, true);
+                    if self.peek() == Some(':') {
+                        self.position += 1;
+                        self.sequence(emit && !known, true);
+                    }
+                    if self.peek() == Some('.') {
+                        self.position += 1;
+                    }
+                }
+                c => self.push(emit, c),
+            }
+        }
+    }
+
+    /// The name after a % or ?, like `f` or `lt`.
+    fn escape(&mut self) -> String {
+        let mut escape = String::new();
+        if let Some(c) = self.next() {
+            escape.push(c);
+            if matches!(c, 'l' | 'p') && matches!(self.peek(), Some('t') | Some('b')) {
+                escape.push(self.next().unwrap());
+            }
+        }
+        escape
+    }
+
+    fn push(&mut self, emit: bool, c: char) {
+        if emit {
+            self.text.push(c);
+        }
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.chars.get(self.position).copied();
+        self.position += 1;
+        c
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.position).copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lesser::reader::Position;
+    use crate::lesser::status_line::{format_prompt, PromptStyle, Prompts, StatusInfo};
+
+    #[test]
+    fn test_format_prompt() {
+        let info = StatusInfo {
+            name: Some("file.txt"),
+            files: None,
+            position: Some(Position {
+                first_line: 11,
+                last_line: 20,
+                first_percent: 40,
+                percent: 100,
+            }),
+            total_lines: None,
+        };
+        let prompts = Prompts::default();
+        assert_eq!(
+            format_prompt(&prompts.get(PromptStyle::Long), &info),
+            "file.txt lines 11-20 40-100% (END)"
+        );
+        assert_eq!(
+            format_prompt("?f%f:Standard input. %pt\\% ?L(%L lines):no total.", &info),
+            "file.txt 40% no total"
+        );
+        let info = StatusInfo {
+            name: None,
+            files: None,
+            position: None,
+            total_lines: Some(3),
+        };
+        assert_eq!(
+            format_prompt("?f%f:Standard input.?lt %lt.?L, %L lines.", &info),
+            "Standard input, 3 lines"
+        );
+        let info = StatusInfo {
+            name: Some("b.txt"),
+            files: Some((2, 5)),
+            position: None,
+            total_lines: None,
+        };
+        assert_eq!(
+            format_prompt(&Prompts::default().get(PromptStyle::Short), &info),
+            "b.txt (file 2 of 5)"
+        );
+        let info = StatusInfo {
+            name: Some("b.txt"),
+            files: None,
+            position: Some(Position {
+                first_line: 1,
+                last_line: 3,
+                first_percent: 0,
+                percent: 100,
+            }),
+            total_lines: None,
+        };
+        assert_eq!(
+            format_prompt(&Prompts::default().get(PromptStyle::Short), &info),
+            "b.txt lines 1-3 100% (END)"
+        );
+        let info = StatusInfo {
+            total_lines: Some(3),
+            ..info
+        };
+        assert_eq!(
+            format_prompt(&Prompts::default().get(PromptStyle::Medium), &info),
+            "b.txt lines 1-3/3 100% (END)"
+        );
+    }
+
+    #[test]
+    fn test_default_prompts_differ() {
+        let short = Prompts::default().get(PromptStyle::Short);
+        let medium = Prompts::default().get(PromptStyle::Medium);
+        let long = Prompts::default().get(PromptStyle::Long);
+        assert_ne!(short, medium);
+        assert_ne!(medium, long);
+        assert_ne!(short, long);
+    }
+}
@@ -0,0 +1,302 @@
+use crate::lesser::text::Text;
+use memchr::memchr_iter;
+use memmap::Mmap;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::process::{Child, Command, Stdio};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use tempdir::TempDir;
+
+/// Text that is still coming, from a pipe or a command, kept as it comes so that it can be
+/// paged (and followed) while it grows.
+pub(crate) struct Stream {
+    /// What came so far, updated by the thread reading the input.
+    received: Arc<(Mutex<Received>, Condvar)>,
+    /// The command writing the text: killed if it's still running when the stream is dropped.
+    child: Option<Child>,
+}
+
+/// What happens to piped text coming past the buffer max.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Overflow {
+    /// All of it is kept, moved to a temporary file.
+    Spill,
+    /// The oldest lines are dropped to make room, for endless streams.
+    DropOldest,
+}
+
+/// Where the text that came is kept: in memory while it's small, in a temporary file once
+/// it's more than the buffer max.
+enum Buffer {
+    Memory(Vec<u8>),
+    Spilled {
+        /// Removed, with the file, when the stream is dropped.
+        _dir: TempDir,
+        file: File,
+    },
+}
+
+struct Received {
+    buffer: Buffer,
+    /// How many bytes came.
+    len: usize,
+    /// How many of them were dropped, from the start, with Overflow::DropOldest.
+    dropped: usize,
+    /// The byte the lines end with.
+    newline: u8,
+    /// How many newlines came.
+    lines: usize,
+    /// The input ended, or can't be read anymore.
+    done: bool,
+}
+
+impl Received {
+    /// Keeps bytes. If they get more than buffer_max, either everything is moved to a
+    /// temporary file, or the oldest lines are dropped.
+    fn keep(&mut self, bytes: &[u8], buffer_max: u64, overflow: Overflow) -> io::Result<()> {
+        self.len += bytes.len();
+        let newline = self.newline;
+        self.lines += memchr_iter(newline, bytes).count();
+        if let (Buffer::Memory(memory), Overflow::DropOldest) = (&mut self.buffer, overflow) {
+            memory.extend_from_slice(bytes);
+            let excess = memory.len().saturating_sub(buffer_max as usize);
+            if excess > 0 {
+                // Whole lines, unless one is longer than the buffer.
+                let cut = memory[excess - 1..]
+                    .iter()
+                    .position(|c| *c == newline)
+                    .map_or(excess, |newline| excess + newline);
+                memory.drain(..cut);
+                self.dropped += cut;
+            }
+            return Ok(());
+        }
+        if let Buffer::Memory(memory) = &self.buffer {
+            if (memory.len() + bytes.len()) as u64 > buffer_max {
+                let dir = TempDir::new("lesser")?;
+                let mut file = OpenOptions::new()
+                    .read(true)
+                    .write(true)
+                    .create(true)
+                    .truncate(true)
+                    .open(dir.path().join("stream"))?;
+                file.write_all(memory)?;
+                self.buffer = Buffer::Spilled { _dir: dir, file };
+            }
+        }
+        match &mut self.buffer {
+            Buffer::Memory(memory) => memory.extend_from_slice(bytes),
+            Buffer::Spilled { file, .. } => file.write_all(bytes)?,
+        }
+        Ok(())
+    }
+}
+
+impl Stream {
+    /// Starts reading input in the background, lines ending with newline. Past buffer_max
+    /// bytes it goes to a temporary file instead of memory, or the oldest lines are dropped.
+    pub(crate) fn spawn(
+        mut input: impl Read + Send + 'static,
+        buffer_max: u64,
+        overflow: Overflow,
+        newline: u8,
+    ) -> Stream {
+        let received = Received {
+            buffer: Buffer::Memory(Vec::new()),
+            len: 0,
+            dropped: 0,
+            newline,
+            lines: 0,
+            done: false,
+        };
+        let received = Arc::new((Mutex::new(received), Condvar::new()));
+        let reader_received = Arc::clone(&received);
+        thread::spawn(move || {
+            let (received, changed) = &*reader_received;
+            let mut buffer = vec![0; 64 * 1024];
+            loop {
+                let read = match input.read(&mut buffer) {
+                    Ok(0) => break,
+                    Ok(read) => read,
+                    Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(error) => {
+                        debug!("Stopped reading the input: {}", error);
+                        break;
+                    }
+                };
+                let kept = received
+                    .lock()
+                    .unwrap()
+                    .keep(&buffer[..read], buffer_max, overflow);
+                changed.notify_all();
+                if let Err(error) = kept {
+                    debug!("Can't keep the input: {}", error);
+                    break;
+                }
+            }
+            received.lock().unwrap().done = true;
+            changed.notify_all();
+        });
+        Stream {
+            received,
+            child: None,
+        }
+    }
+
+    /// Runs command with the shell, for its output and its errors.
+    pub(crate) fn command(
+        command: &str,
+        buffer_max: u64,
+        overflow: Overflow,
+        newline: u8,
+    ) -> io::Result<Stream> {
+        let mut child = Command::new("sh")
+            .arg("-c")
+            .arg(format!("exec 2>&1\n{}", command))
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let output = child.stdout.take().expect("Piped stdout");
+        let mut stream = Stream::spawn(output, buffer_max, overflow, newline);
+        stream.child = Some(child);
+        Ok(stream)
+    }
+
+    /// Waits until more than lines lines came, or all of them if they're fewer. With a
+    /// timeout, it stops waiting after it anyway.
+    pub(crate) fn wait_for_lines(&self, lines: usize, timeout: Option<Duration>) {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        let (received, changed) = &*self.received;
+        let mut received = received.lock().unwrap();
+        while received.lines <= lines && !received.done {
+            received = match deadline {
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return;
+                    }
+                    changed.wait_timeout(received, deadline - now).unwrap().0
+                }
+                None => changed.wait(received).unwrap(),
+            };
+        }
+    }
+
+    /// Waits until all the input came.
+    pub(crate) fn wait_for_end(&self) {
+        self.wait_for_lines(usize::MAX, None);
+    }
+
+    /// How many bytes came so far, while there are no more than lines lines and more can come.
+    /// None once there's enough to fill them, or it's all there is.
+    pub(crate) fn waiting_for(&self, lines: usize) -> Option<usize> {
+        let received = self.received.0.lock().unwrap();
+        (received.lines <= lines && !received.done).then_some(received.len)
+    }
+
+    /// If all the input came.
+    pub(crate) fn done(&self) -> bool {
+        self.received.0.lock().unwrap().done
+    }
+
+    /// How many bytes came so far.
+    pub(crate) fn len(&self) -> usize {
+        self.received.0.lock().unwrap().len
+    }
+
+    /// The text that came so far, None if nothing did.
+    pub(crate) fn text(&self) -> io::Result<Option<Text>> {
+        Ok(self.kept()?.0)
+    }
+
+    /// The text kept so far, None if there's none, and how many bytes came before it and were
+    /// dropped.
+    pub(crate) fn kept(&self) -> io::Result<(Option<Text>, usize)> {
+        let received = self.received.0.lock().unwrap();
+        let text = match &received.buffer {
+            Buffer::Memory(memory) if memory.is_empty() => None,
+            Buffer::Memory(memory) => Some(Text::Memory(memory.clone())),
+            // Only the stream writes to its file, and never makes it shorter.
+            Buffer::Spilled { file, .. } => Some(Text::Mapped(unsafe { Mmap::map(file)? }, None)),
+        };
+        Ok((text, received.dropped))
+    }
+}
+
+impl Drop for Stream {
+    fn drop(&mut self) {
+        if let Some(child) = &mut self.child {
+            // It could be done already.
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lesser::stream::{Overflow, Stream};
+    use crate::lesser::text::Text;
+    use std::time::Duration;
+
+    #[test]
+    fn test_wait_for_lines() {
+        let stream =
+            Stream::command("echo 1; echo 2; sleep 10", 1024, Overflow::Spill, b'\n').unwrap();
+        // More than one line came, the command is still running.
+        stream.wait_for_lines(1, None);
+        assert_eq!(&stream.text().unwrap().unwrap().all()[..], b"1\n2\n");
+        assert_eq!(stream.waiting_for(1), None);
+        assert_eq!(stream.waiting_for(2), Some(4));
+        // The third line doesn't come.
+        stream.wait_for_lines(2, Some(Duration::from_millis(10)));
+        assert_eq!(stream.len(), 4);
+    }
+
+    #[test]
+    fn test_command() {
+        let stream =
+            Stream::command("echo out; echo err >&2", 1024, Overflow::Spill, b'\n').unwrap();
+        stream.wait_for_end();
+        assert_eq!(&stream.text().unwrap().unwrap().all()[..], b"out\nerr\n");
+    }
+
+    #[test]
+    fn test_spill() {
+        let stream = Stream::spawn(&b"a\nb"[..], 1024, Overflow::Spill, b'\n');
+        stream.wait_for_end();
+        assert!(matches!(stream.text().unwrap(), Some(Text::Memory(_))));
+        let stream = Stream::spawn(&b"a\nb"[..], 2, Overflow::Spill, b'\n');
+        stream.wait_for_end();
+        let text = stream.text().unwrap().unwrap();
+        assert!(matches!(text, Text::Mapped(..)));
+        assert_eq!(&text.all()[..], b"a\nb");
+        let empty = Stream::spawn(&b""[..], 2, Overflow::Spill, b'\n');
+        empty.wait_for_end();
+        assert!(empty.text().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_drop_oldest() {
+        let stream = Stream::spawn(&b"one\ntwo\nthree\n"[..], 8, Overflow::DropOldest, b'\n');
+        stream.wait_for_end();
+        let (text, dropped) = stream.kept().unwrap();
+        assert_eq!(&text.unwrap().all()[..], b"three\n");
+        assert_eq!(dropped, 8);
+        assert_eq!(stream.len(), 14);
+        // A line longer than the buffer is cut.
+        let stream = Stream::spawn(&b"0123456789"[..], 4, Overflow::DropOldest, b'\n');
+        stream.wait_for_end();
+        let (text, dropped) = stream.kept().unwrap();
+        assert_eq!(&text.unwrap().all()[..], b"6789");
+        assert_eq!(dropped, 6);
+        // Records ending with NUL.
+        let stream = Stream::spawn(&b"one\0two\0three\0"[..], 8, Overflow::DropOldest, b'\0');
+        stream.wait_for_end();
+        let (text, dropped) = stream.kept().unwrap();
+        assert_eq!(&text.unwrap().all()[..], b"three\0");
+        assert_eq!(dropped, 8);
+    }
+}
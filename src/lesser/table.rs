@@ -0,0 +1,151 @@
+use crate::lesser::ansi::text_width;
+use std::cmp::max;
+use std::path::Path;
+
+/// Between the columns of the aligned table.
+const COLUMN_SEPARATOR: &str = " │ ";
+
+/// CSV or TSV with the columns aligned, and where each column starts.
+#[derive(Debug)]
+pub(crate) struct Table {
+    pub(crate) text: String,
+    /// The first screen column of each table column, starting from 0.
+    pub(crate) column_starts: Vec<u64>,
+}
+
+/// The separator of the fields for the file extension: `,` for .csv files, tab for .tsv.
+pub(crate) fn separator_of(path: &Path) -> Option<u8> {
+    match path.extension()?.to_str()? {
+        "csv" => Some(b','),
+        "tsv" => Some(b'\t'),
+        _ => None,
+    }
+}
+
+/// The separator, when the file name doesn't say it: tab if the first line of text has one.
+pub(crate) fn guess_separator(text: &[u8]) -> u8 {
+    let first_line = text.split(|c| *c == b'\n').next().unwrap_or_default();
+    if first_line.contains(&b'\t') {
+        b'\t'
+    } else {
+        b','
+    }
+}
+
+/// Pads the fields of each record so the columns line up.
+pub(crate) fn align(text: &[u8], separator: u8) -> Table {
+    let records = parse_records(&String::from_utf8_lossy(text), separator as char);
+    let mut widths: Vec<usize> = Vec::new();
+    for record in &records {
+        for (column, field) in record.iter().enumerate() {
+            let width = text_width(field);
+            match widths.get_mut(column) {
+                Some(max_width) => *max_width = max(*max_width, width),
+                None => widths.push(width),
+            }
+        }
+    }
+    let separator_width = text_width(COLUMN_SEPARATOR);
+    let column_starts = widths
+        .iter()
+        .scan(0, |start, width| {
+            let column_start = *start;
+            *start += width + separator_width;
+            Some(column_start as u64)
+        })
+        .collect();
+    let lines: Vec<String> = records
+        .iter()
+        .map(|record| {
+            let mut line = String::new();
+            for (column, field) in record.iter().enumerate() {
+                if column > 0 {
+                    line.push_str(COLUMN_SEPARATOR);
+                }
+                // The last field is not padded, no need for spaces at the end of the line.
+                line.push_str(field);
+                if column + 1 < record.len() {
+                    line.extend(std::iter::repeat_n(' ', widths[column] - text_width(field)));
+                }
+            }
+            line
+        })
+        .collect();
+    Table {
+        text: lines.join("\n"),
+        column_starts,
+    }
+}
+
+/// The fields of each record. With `,`, fields can be quoted like in RFC 4180: `"a, ""b"""`
+/// is `a, "b"`. A newline in a quoted field is shown as a space, to keep a record per line.
+fn parse_records(text: &str, separator: char) -> Vec<Vec<String>> {
+    let quoting = separator == ',';
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoting && in_quotes => {
+                if chars.peek() == Some(&'"') {
+                    chars.next();
+                    field.push('"');
+                } else {
+                    in_quotes = false;
+                }
+            }
+            '"' if quoting && field.is_empty() => in_quotes = true,
+            '\n' | '\r' if in_quotes => {
+                if c == '\n' {
+                    field.push(' ');
+                }
+            }
+            '\r' if chars.peek() == Some(&'\n') => (),
+            '\n' => {
+                record.push(std::mem::take(&mut field));
+                records.push(std::mem::take(&mut record));
+            }
+            c if c == separator && !in_quotes => record.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+    // The last line, if it doesn't end with a newline.
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+    records
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lesser::table::{align, guess_separator, parse_records};
+
+    #[test]
+    fn test_parse_records() {
+        let records = parse_records("a,\"b, \"\"c\"\"\"\r\n\"multi\nline\",,x\n", ',');
+        assert_eq!(
+            records,
+            [vec!["a", "b, \"c\""], vec!["multi line", "", "x"]]
+        );
+        // No quoting in TSV.
+        assert_eq!(parse_records("\"a\tb", '\t'), [vec!["\"a", "b"]]);
+        assert_eq!(guess_separator(b"a\tb,c"), b'\t');
+        assert_eq!(guess_separator(b"a,b\nc\td"), b',');
+    }
+
+    #[test]
+    fn test_align() {
+        let table = align(b"name,age,city\nAlexandra,7,Rome\nBo,42\n", b',');
+        assert_eq!(
+            table.text,
+            "name      │ age │ city\nAlexandra │ 7   │ Rome\nBo        │ 42"
+        );
+        assert_eq!(table.column_starts, [0, 12, 18]);
+        // Wide chars take two columns.
+        let table = align("名,x\nabc,y".as_bytes(), b',');
+        assert_eq!(table.text, "名  │ x\nabc │ y");
+    }
+}
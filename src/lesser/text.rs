@@ -0,0 +1,324 @@
+use memchr::memchr;
+use memmap::{Mmap, MmapOptions};
+use std::borrow::Cow;
+use std::cmp::min;
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io;
+use std::ops::Range;
+use std::os::unix::io::AsRawFd;
+use std::sync::Mutex;
+
+/// Files bigger than this are mapped a window at a time, so they don't take all the address
+/// space (on 32 bit targets).
+const WHOLE_MAP_MAX: u64 = 1 << 30;
+/// The size of a window: a multiple of the page size, so windows start on a page.
+const WINDOW_SIZE: usize = 64 << 20;
+/// How many windows stay mapped, to go back and forth between the last ones.
+const MAPPED_WINDOWS: usize = 4;
+/// How far past the page the text is read ahead, the way it's scrolled.
+const READ_AHEAD: usize = 8 << 20;
+/// How far behind the page the mapped text is kept. Past it, the kernel can let it go.
+const KEEP_BEHIND: usize = 64 << 20;
+/// How much of the start of a text is looked at to find how its lines end.
+const NEWLINE_GUESS: usize = 64 << 10;
+
+/// The bytes being paged: a file mapped in memory, or text kept in memory, like a small pipe.
+pub enum Text {
+    /// With the file mapped, if another process can make it shorter than the map.
+    Mapped(Mmap, Option<File>),
+    Memory(Vec<u8>),
+    Windowed(Windows),
+}
+
+/// A file too big to be mapped at once, mapped a window at a time as its bytes are read.
+pub struct Windows {
+    file: File,
+    len: usize,
+    window_size: usize,
+    /// The windows read last, by their start in the file, the newest last.
+    mapped: Mutex<Vec<(usize, Mmap)>>,
+}
+
+impl Text {
+    /// Maps file, a window at a time if it's big.
+    pub fn map(file: &File) -> io::Result<Text> {
+        let len = file.metadata()?.len();
+        if len > WHOLE_MAP_MAX {
+            Text::windowed(file, WINDOW_SIZE)
+        } else {
+            let mmap = unsafe { Mmap::map(file)? };
+            Ok(Text::Mapped(mmap, Some(file.try_clone()?)))
+        }
+    }
+
+    fn windowed(file: &File, window_size: usize) -> io::Result<Text> {
+        let len = file.metadata()?.len();
+        Ok(Text::Windowed(Windows {
+            file: file.try_clone()?,
+            len: usize::try_from(len).map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "The file is too big to be paged",
+                )
+            })?,
+            window_size,
+            mapped: Mutex::new(Vec::new()),
+        }))
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            Text::Mapped(mmap, _file) => mmap.len(),
+            Text::Memory(bytes) => bytes.len(),
+            Text::Windowed(windows) => windows.len,
+        }
+    }
+
+    /// The bytes in range: borrowed, unless they're in windows and have to be copied.
+    pub fn bytes(&self, range: Range<usize>) -> Cow<'_, [u8]> {
+        match self {
+            Text::Mapped(mmap, _file) => Cow::Borrowed(&mmap[range]),
+            Text::Memory(bytes) => Cow::Borrowed(&bytes[range]),
+            Text::Windowed(windows) => Cow::Owned(windows.read(range)),
+        }
+    }
+
+    /// The bytes in range as text, with the invalid UTF-8 replaced.
+    pub fn lossy(&self, range: Range<usize>) -> Cow<'_, str> {
+        match self.bytes(range) {
+            Cow::Borrowed(bytes) => String::from_utf8_lossy(bytes),
+            Cow::Owned(bytes) => Cow::Owned(String::from_utf8_lossy(&bytes).into_owned()),
+        }
+    }
+
+    /// The first len bytes, or all of them if there are fewer.
+    pub fn head(&self, len: usize) -> Cow<'_, [u8]> {
+        self.bytes(0..min(len, self.len()))
+    }
+
+    /// The byte the lines end with: \n, also with Windows line endings, where it's after a \r.
+    /// Old Mac files end them with a lone \r: when there's one in the first NEWLINE_GUESS
+    /// bytes, but no \n.
+    pub fn newline(&self) -> u8 {
+        let head = self.head(NEWLINE_GUESS);
+        if memchr(b'\n', &head).is_none() && memchr(b'\r', &head).is_some() {
+            b'\r'
+        } else {
+            b'\n'
+        }
+    }
+
+    /// If the file mapped got shorter than the text: touching the pages it lost kills lesser
+    /// with SIGBUS. The threads reading the text in the background look before each chunk.
+    pub fn shrunk(&self) -> bool {
+        let file = match self {
+            Text::Mapped(_, Some(file)) => file,
+            Text::Windowed(windows) => &windows.file,
+            Text::Mapped(_, None) | Text::Memory(_) => return false,
+        };
+        matches!(file.metadata(), Ok(metadata) if metadata.len() < self.len() as u64)
+    }
+
+    /// All the bytes: a copy of the whole file if it's windowed.
+    pub fn all(&self) -> Cow<'_, [u8]> {
+        self.bytes(0..self.len())
+    }
+
+    /// Tells the kernel how a big text is read, for files on slow disks: page is on the
+    /// screen, scrolled forward or back. The text after it (or before) is read ahead, and in a
+    /// map the text far behind can go.
+    pub fn advise(&self, page: Range<usize>, forward: bool) {
+        let len = self.len();
+        if len <= KEEP_BEHIND {
+            return;
+        }
+        let (ahead, behind) = if forward {
+            (
+                page.end..min(page.end.saturating_add(READ_AHEAD), len),
+                0..page.start.saturating_sub(KEEP_BEHIND),
+            )
+        } else {
+            (
+                page.start.saturating_sub(READ_AHEAD)..page.start,
+                min(page.end.saturating_add(KEEP_BEHIND), len)..len,
+            )
+        };
+        // There's no advice for reading backwards.
+        let (map_order, file_order) = if forward {
+            (libc::MADV_SEQUENTIAL, libc::POSIX_FADV_SEQUENTIAL)
+        } else {
+            (libc::MADV_NORMAL, libc::POSIX_FADV_NORMAL)
+        };
+        match self {
+            Text::Mapped(mmap, _file) => {
+                madvise(mmap, 0..len, map_order);
+                madvise(mmap, ahead, libc::MADV_WILLNEED);
+                madvise(mmap, behind, libc::MADV_DONTNEED);
+            }
+            // Only the windows read last stay mapped anyway.
+            Text::Windowed(windows) => {
+                fadvise(&windows.file, 0..len, file_order);
+                fadvise(&windows.file, ahead, libc::POSIX_FADV_WILLNEED);
+            }
+            Text::Memory(_) => {}
+        }
+    }
+}
+
+/// Gives the kernel advice about the bytes of mmap in range.
+fn madvise(mmap: &Mmap, range: Range<usize>, advice: libc::c_int) {
+    if range.start >= range.end {
+        return;
+    }
+    // The advice is about whole pages.
+    let start = range.start - range.start % page_size();
+    let result = unsafe {
+        libc::madvise(
+            mmap.as_ptr().add(start) as *mut libc::c_void,
+            range.end - start,
+            advice,
+        )
+    };
+    if result != 0 {
+        debug!("Can't advise about the map: {}", io::Error::last_os_error());
+    }
+}
+
+/// Gives the kernel advice about the bytes of file in range.
+fn fadvise(file: &File, range: Range<usize>, advice: libc::c_int) {
+    if range.start >= range.end {
+        return;
+    }
+    let result = unsafe {
+        libc::posix_fadvise(
+            file.as_raw_fd(),
+            range.start as libc::off_t,
+            range.len() as libc::off_t,
+            advice,
+        )
+    };
+    // The error is returned, not in errno.
+    if result != 0 {
+        debug!(
+            "Can't advise about the file: {}",
+            io::Error::from_raw_os_error(result)
+        );
+    }
+}
+
+fn page_size() -> usize {
+    unsafe { libc::sysconf(libc::_SC_PAGESIZE) as usize }
+}
+
+impl Windows {
+    fn read(&self, range: Range<usize>) -> Vec<u8> {
+        assert!(
+            range.start <= range.end && range.end <= self.len,
+            "Range {:?} out of {} bytes",
+            range,
+            self.len
+        );
+        let mut bytes = Vec::with_capacity(range.len());
+        let mut at = range.start;
+        while at < range.end {
+            let start = at - at % self.window_size;
+            let end = min(start + self.window_size, range.end);
+            self.with_window(start, |window| {
+                bytes.extend_from_slice(&window[at - start..end - start]);
+            });
+            at = end;
+        }
+        bytes
+    }
+
+    /// Calls read with the window starting at start, mapping it if it isn't. If it can't be
+    /// mapped, like when the file got shorter, its bytes are zeros.
+    fn with_window(&self, start: usize, read: impl FnOnce(&[u8])) {
+        let mut mapped = self.mapped.lock().unwrap();
+        let index = match mapped.iter().position(|(offset, _)| *offset == start) {
+            Some(index) => index,
+            None => {
+                let len = min(self.window_size, self.len - start);
+                let window = unsafe {
+                    MmapOptions::new()
+                        .offset(start as u64)
+                        .len(len)
+                        .map(&self.file)
+                };
+                match window {
+                    Ok(window) => {
+                        if mapped.len() == MAPPED_WINDOWS {
+                            mapped.remove(0);
+                        }
+                        mapped.push((start, window));
+                        mapped.len() - 1
+                    }
+                    Err(error) => {
+                        debug!("Can't map the window at {}: {}", start, error);
+                        return read(&vec![0; len]);
+                    }
+                }
+            }
+        };
+        read(&mapped[index].1);
+    }
+}
+
+impl From<Mmap> for Text {
+    fn from(mmap: Mmap) -> Self {
+        Text::Mapped(mmap, None)
+    }
+}
+
+impl From<Vec<u8>> for Text {
+    fn from(bytes: Vec<u8>) -> Self {
+        Text::Memory(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lesser::text::Text;
+    use std::io::Write;
+
+    #[test]
+    fn test_windowed() {
+        let bytes: Vec<u8> = (0..200_000u32).map(|i| (i % 251) as u8).collect();
+        let mut file = tempfile();
+        file.write_all(&bytes).unwrap();
+        // Windows of 64K, a multiple of the page size.
+        let text = Text::windowed(&file, 1 << 16).unwrap();
+        assert_eq!(text.len(), bytes.len());
+        assert_eq!(&text.bytes(10..20)[..], &bytes[10..20]);
+        // Across two windows.
+        assert_eq!(&text.bytes(65_000..140_000)[..], &bytes[65_000..140_000]);
+        assert_eq!(&text.all()[..], &bytes[..]);
+        assert_eq!(&text.head(5)[..], &bytes[..5]);
+    }
+
+    #[test]
+    fn test_shrunk() {
+        let mut file = tempfile();
+        file.write_all(b"a\nb\nc\n").unwrap();
+        let text = Text::map(&file).unwrap();
+        assert!(!text.shrunk());
+        file.set_len(8).unwrap();
+        assert!(!text.shrunk());
+        file.set_len(2).unwrap();
+        assert!(text.shrunk());
+        // A text in memory can't lose its bytes.
+        assert!(!Text::from(b"a\n".to_vec()).shrunk());
+    }
+
+    fn tempfile() -> std::fs::File {
+        let dir = tempdir::TempDir::new("lesser").unwrap();
+        std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(dir.path().join("windowed"))
+            .unwrap()
+    }
+}
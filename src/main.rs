@@ -4,7 +4,11 @@ extern crate clap;
 #[macro_use]
 extern crate log;
 
-use crate::lesser::run;
+use crate::lesser::{
+    parse_control_notation, parse_encoding, parse_separator, parse_size, parse_tab_width, run,
+    Config, ControlChars, ControlNotation, IgnoreCase, LessOptions, Overflow, PromptStyle, Prompts,
+    QuitAtEof, Settings,
+};
 use std::path::PathBuf;
 
 mod lesser;
@@ -13,8 +17,148 @@ mod lesser;
 #[clap(version = "0.0.1", author = "Federico Ponzi")]
 struct Opts {
     #[clap(takes_value = true)]
-    /// name of the file to read
-    filename: Option<PathBuf>,
+    /// names of the files to read (:n and :p switch between them), - for the standard input.
+    /// +G starts at the end, +N at line N, +/pattern at the first match
+    filenames: Vec<PathBuf>,
+    #[clap(long = "literal")]
+    /// treat search patterns as literal strings, not regexes (Ctrl-R toggles it in the prompt)
+    literal: bool,
+    #[clap(short = 'i', long = "ignore-case")]
+    /// ignore the case of the letters when searching and filtering, unless the pattern has
+    /// capitals, like in less (-i toggles it)
+    ignore_case: bool,
+    #[clap(short = 'I', long = "IGNORE-CASE")]
+    /// ignore the case of the letters even if the pattern has capitals (-I toggles it)
+    ignore_case_always: bool,
+    #[clap(short = 'N', long = "line-numbers")]
+    /// show line numbers (# toggles them)
+    line_numbers: bool,
+    #[clap(short = 'n', long = "no-line-numbers")]
+    /// don't show line numbers, even if LESS or the config file asks for them
+    no_line_numbers: bool,
+    #[clap(short = 'F', long = "quit-if-one-screen")]
+    /// print the file and exit if it fits in the screen, like cat
+    quit_if_one_screen: bool,
+    #[clap(short = 'e', long = "quit-at-eof")]
+    /// quit going forward from the end of the text, or show the next file
+    quit_at_eof: bool,
+    #[clap(short = 'E', long = "QUIT-AT-EOF")]
+    /// quit as soon as the end of the last file is shown
+    quit_at_first_eof: bool,
+    #[clap(short = 'X', long = "no-alt-screen")]
+    /// leave the last page on the terminal when exiting
+    no_alt_screen: bool,
+    #[clap(short = 'R', long = "RAW-CONTROL-CHARS")]
+    /// show the colors of the text (its ANSI color sequences), like `ls --color` or `git diff`
+    raw_control_chars: bool,
+    #[clap(long = "strip-ansi")]
+    /// remove the escape sequences, like colors, from the text (wins over -R)
+    strip_ansi: bool,
+    #[clap(short = 'x', long = "tabs", parse(try_from_str = parse_tab_width))]
+    /// put the tab stops this many columns apart (8 by default)
+    tabs: Option<usize>,
+    #[clap(long = "control-chars", parse(try_from_str = parse_control_notation))]
+    /// show the control chars as caret (^G, the default) or hex (\x07), highlighted
+    control_chars: Option<ControlNotation>,
+    #[clap(long = "show-whitespace")]
+    /// show tabs as →, trailing spaces as · and line endings as ¶, dimmed (W toggles them)
+    show_whitespace: bool,
+    #[clap(long = "wrap")]
+    /// wrap the long lines on more rows instead of scrolling them horizontally (w toggles it)
+    wrap: bool,
+    #[clap(long = "wrap-words")]
+    /// with --wrap, wrap the long lines between words instead of at the edge of the screen
+    wrap_words: bool,
+    #[clap(long = "wrap-marker")]
+    /// with --wrap, start the rows a long line is wrapped on with this, like '↪ '
+    wrap_marker: Option<String>,
+    #[clap(short = 'z', long = "null")]
+    /// the lines end with NUL, like the output of `find -print0` (same as --separator '\0')
+    null: bool,
+    #[clap(long = "separator", parse(try_from_str = parse_separator))]
+    /// the lines end with this byte instead of a newline, like ',', '\0' or '\x1e'
+    separator: Option<u8>,
+    #[clap(long = "encoding", parse(try_from_str = parse_encoding))]
+    /// the encoding of the text, like latin1, utf-16le or shift_jis (found from the text
+    /// otherwise)
+    encoding: Option<&'static encoding_rs::Encoding>,
+    #[clap(long = "no-syntax-highlighting")]
+    /// don't color the text by its syntax (found from the file name or the first line)
+    no_syntax_highlighting: bool,
+    #[clap(long = "diff")]
+    /// color the text as a unified diff (done anyway if it starts like one, e.g. git diff)
+    diff: bool,
+    #[clap(long = "json")]
+    /// pretty print the text as JSON, with foldable objects and arrays (done anyway for .json
+    /// files, and for piped JSON)
+    json: bool,
+    #[clap(long = "table")]
+    /// align the columns of CSV or TSV text (done anyway for .csv and .tsv files)
+    table: bool,
+    #[clap(long = "header")]
+    /// keep the first row of tables on the top of the screen
+    header: bool,
+    #[clap(long = "render-markdown")]
+    /// show Markdown files with styles instead of their markup (M switches back)
+    render_markdown: bool,
+    #[clap(long = "hex")]
+    /// show the bytes in hex, like xxd, for binary files (x switches back to the text)
+    hex: bool,
+    #[clap(short = 'f', long = "force")]
+    /// open binary files without asking first
+    force: bool,
+    #[clap(short = 'L', long = "no-lessopen")]
+    /// don't run the input preprocessor of LESSOPEN (like lesspipe) on the files
+    no_lessopen: bool,
+    #[clap(long = "exec", conflicts_with = "filenames")]
+    /// page the output of a shell command as it comes, instead of files (R runs it again)
+    exec: Option<String>,
+    #[clap(long = "buffer-max", parse(try_from_str = parse_size))]
+    /// how much piped text is kept in memory, like 16M (4M by default). Past it, the text is
+    /// moved to a temporary file
+    buffer_max: Option<u64>,
+    #[clap(long = "drop-oldest")]
+    /// past --buffer-max, drop the oldest lines instead of keeping them in a file, for endless
+    /// streams
+    drop_oldest: bool,
+    #[clap(short = 'j', long = "jump-target", allow_hyphen_values = true)]
+    /// screen row where goto and search put their target line (negative counts from the bottom)
+    jump_target: Option<i64>,
+    #[clap(long = "page-overlap")]
+    /// keep this many rows of the page on the screen when paging, to not lose the place
+    page_overlap: Option<u64>,
+    #[clap(short = 'P', long = "prompt")]
+    /// status line format, like less's. Starting with m or M sets the medium or long one
+    prompt: Vec<String>,
+    #[clap(short = 'm', long = "long-prompt")]
+    /// show the medium status line
+    medium_prompt: bool,
+    #[clap(short = 'M', long = "LONG-PROMPT")]
+    /// show the long status line
+    long_prompt: bool,
+    #[clap(long = "resume")]
+    /// reopen the file where it was left the last time
+    resume: bool,
+    #[clap(long = "no-resume")]
+    /// start from the beginning, even if the config file asks to resume
+    no_resume: bool,
+    #[clap(long = "index-cache")]
+    /// save where the lines of big files are, so they open faster the next time
+    index_cache: bool,
+    #[clap(long = "no-index-cache")]
+    /// don't save nor reuse the line index, even if the config file asks to
+    no_index_cache: bool,
+}
+
+/// A flag and its opposite, like -N and -n: None if neither was given.
+fn flag(on: bool, off: bool) -> Option<bool> {
+    if off {
+        Some(false)
+    } else if on {
+        Some(true)
+    } else {
+        None
+    }
 }
 
 fn main() {
@@ -24,7 +168,126 @@ fn main() {
         .filter("LESSER_LOG")
         .write_style("LESSER_LOG_STYLE");
     env_logger::init_from_env(env);
-    if let Err(error) = run(opts.filename) {
+    let config = match Config::load() {
+        Ok(config) => config,
+        Err(error) => {
+            eprintln!("Error: {}", error);
+            return;
+        }
+    };
+    let less = LessOptions::from_env();
+    // Like in less, +command runs a command at startup: it's not a file.
+    let (commands, filenames): (Vec<PathBuf>, Vec<PathBuf>) = opts
+        .filenames
+        .into_iter()
+        .partition(|filename| filename.to_string_lossy().starts_with('+'));
+    let startup_command = commands
+        .last()
+        .map(|command| command.to_string_lossy()[1..].to_owned());
+    let mut prompts = Prompts::default();
+    if let Some(prompt) = &config.prompt {
+        prompts.set_style(PromptStyle::Short, prompt);
+    }
+    for prompt in less.prompts.iter().chain(&opts.prompt) {
+        prompts.set(prompt);
+    }
+    let prompt_style = if opts.long_prompt {
+        Some(PromptStyle::Long)
+    } else if opts.medium_prompt {
+        Some(PromptStyle::Medium)
+    } else {
+        None
+    };
+    // Like in less, LESSOPEN turns files into text (e.g. with lesspipe), unless -L.
+    let no_lessopen = flag(opts.no_lessopen, false)
+        .or(less.no_lessopen)
+        .unwrap_or(false);
+    let preprocessor = |variable| std::env::var(variable).ok().filter(|_| !no_lessopen);
+    // The flags win over LESS, that wins over the config file.
+    let settings = Settings {
+        literal_search: opts.literal || config.literal_search,
+        ignore_case: if opts.ignore_case_always {
+            Some(IgnoreCase::Always)
+        } else if opts.ignore_case {
+            Some(IgnoreCase::Smart)
+        } else {
+            None
+        }
+        .or(less.ignore_case)
+        .unwrap_or(IgnoreCase::Never),
+        line_numbers: flag(opts.line_numbers, opts.no_line_numbers)
+            .or(less.line_numbers)
+            .unwrap_or(config.line_numbers),
+        quit_if_one_screen: flag(opts.quit_if_one_screen, false)
+            .or(less.quit_if_one_screen)
+            .unwrap_or(config.quit_if_one_screen),
+        quit_at_eof: if opts.quit_at_first_eof {
+            Some(QuitAtEof::First)
+        } else if opts.quit_at_eof {
+            Some(QuitAtEof::Second)
+        } else {
+            None
+        }
+        .or(less.quit_at_eof)
+        .unwrap_or(QuitAtEof::Never),
+        no_alt_screen: flag(opts.no_alt_screen, false)
+            .or(less.no_alt_screen)
+            .unwrap_or(config.no_alt_screen),
+        jump_target: opts
+            .jump_target
+            .or(less.jump_target)
+            .unwrap_or(config.jump_target),
+        page_overlap: opts
+            .page_overlap
+            .or(less.page_overlap)
+            .unwrap_or(config.page_overlap),
+        keys: config.keys,
+        prompt: prompts.get(
+            prompt_style
+                .or(less.prompt_style)
+                .unwrap_or(PromptStyle::Short),
+        ),
+        startup_command,
+        resume: flag(opts.resume, opts.no_resume).unwrap_or(config.resume),
+        control_chars: if opts.strip_ansi || config.strip_ansi {
+            ControlChars::Strip
+        } else if flag(opts.raw_control_chars, false)
+            .or(less.raw_control_chars)
+            .unwrap_or(config.raw_control_chars)
+        {
+            ControlChars::Raw
+        } else {
+            ControlChars::Caret
+        },
+        tab_width: opts.tabs.or(less.tab_width).unwrap_or(config.tab_width),
+        control_notation: opts.control_chars.unwrap_or(config.control_notation),
+        show_whitespace: opts.show_whitespace || config.show_whitespace,
+        wrap: opts.wrap || config.wrap,
+        wrap_words: opts.wrap_words || config.wrap_words,
+        wrap_marker: opts.wrap_marker.unwrap_or(config.wrap_marker),
+        separator: opts.separator.or(opts.null.then_some(b'\0')),
+        encoding: opts.encoding,
+        syntax_highlighting: !opts.no_syntax_highlighting && config.syntax_highlighting,
+        log_levels: config.log_levels,
+        diff: opts.diff,
+        json: opts.json,
+        table: opts.table,
+        table_header: opts.header || config.table_header,
+        render_markdown: opts.render_markdown || config.render_markdown,
+        hex: opts.hex,
+        force: flag(opts.force, false).or(less.force).unwrap_or(false),
+        lessopen: preprocessor("LESSOPEN"),
+        lessclose: preprocessor("LESSCLOSE"),
+        exec: opts.exec,
+        buffer_max: opts.buffer_max.unwrap_or(config.buffer_max),
+        overflow: if opts.drop_oldest || config.drop_oldest {
+            Overflow::DropOldest
+        } else {
+            Overflow::Spill
+        },
+        index_cache: flag(opts.index_cache, opts.no_index_cache).unwrap_or(config.index_cache),
+    };
+    if let Err(error) = run(filenames, settings) {
         eprintln!("Error: {}", error);
     };
 }